@@ -0,0 +1,21 @@
+use chess::{bitboards::BitboardGame, board::Board};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// Perft from the start position on both backends, which count the same moves there
+fn perft(c: &mut Criterion) {
+    let board = Board::new(None).unwrap();
+    let mut group = c.benchmark_group("perft");
+    for depth in 1..=3 {
+        group.bench_with_input(BenchmarkId::new("mailbox", depth), &depth, |b, &depth| {
+            b.iter(|| board.perft(depth))
+        });
+        group.bench_with_input(BenchmarkId::new("bitboard", depth), &depth, |b, &depth| {
+            let mut game = BitboardGame::from(&board);
+            b.iter(|| game.perft(depth))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, perft);
+criterion_main!(benches);
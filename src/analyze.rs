@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    board::Board,
+    pieces::Move,
+    players::{EnginePlayer, MoveRequest, Player, PlayerAction},
+};
+
+const HELP: &str = "Commands:
+  position startpos|fen <fen> [moves <move>...]  set up a position
+  move <move>...     play moves, in SAN such as Nf3 or as e2e4
+  undo               take back the last move
+  go depth <plies>   search to the given depth
+  go ms <millis>     search for the given time
+  eval               a quick evaluation and the best move
+  hint               the move to play here, without playing it
+  fen                print the position as FEN
+  board              print the board
+  help               print this text
+  quit               leave";
+
+// The position being analysed, with the moves that led to it so they can be undone
+pub struct Analysis {
+    boards: Vec<Board>, // the start position first
+}
+
+impl Analysis {
+    pub fn new(board: Board) -> Self {
+        Analysis {
+            boards: vec![board],
+        }
+    }
+
+    fn board(&self) -> &Board {
+        self.boards
+            .last()
+            .expect("there is always a start position")
+    }
+
+    // Reads a move in SAN or coordinates, and checks it is legal
+    fn parse_move(&self, text: &str) -> anyhow::Result<Move> {
+        let board = self.board();
+        let mv = board.parse_san(text).or_else(|_| board.parse_move(text))?;
+        if !board.legal_moves(board.current_turn()).contains(&mv) {
+            bail!("{text} is not legal here");
+        }
+        Ok(mv)
+    }
+
+    fn play(&mut self, moves: &[&str]) -> anyhow::Result<()> {
+        for text in moves {
+            let mv = self.parse_move(text)?;
+            let board = self.board().apply(&mv);
+            self.boards.push(board);
+        }
+        Ok(())
+    }
+
+    // How often each position on the way here occurred, so the search sees repetitions
+    fn repetitions(&self) -> HashMap<u64, u8> {
+        let mut repetitions = HashMap::new();
+        for board in self.boards.iter() {
+            *repetitions.entry(board.repetition_key()).or_insert(0) += 1;
+        }
+        repetitions
+    }
+
+    fn search(&self, mut engine: EnginePlayer) -> anyhow::Result<String> {
+        let board = self.board();
+        let repetitions = self.repetitions();
+        let request = MoveRequest::new(board).with_repetitions(&repetitions);
+        let PlayerAction::Move(mv) = engine.make_move(&request) else {
+            bail!("there are no legal moves");
+        };
+        let line = engine.last_line().unwrap_or_else(|| vec![mv]);
+        let eval = engine
+            .last_eval()
+            .context("the engine gave no evaluation")?;
+        Ok(format!(
+            "Eval: {eval}, best {} ({})",
+            mv.san(board),
+            board.line_san(&line)
+        ))
+    }
+
+    // Carries out one command, returning what to print
+    pub fn run_command(&mut self, line: &str) -> anyhow::Result<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["position", rest @ ..] => {
+                let (board, moves) = match rest {
+                    ["startpos", moves @ ..] => (Board::new(None)?, moves),
+                    ["fen", rest @ ..] => {
+                        let split = rest.iter().position(|word| *word == "moves");
+                        let (fen, moves) = rest.split_at(split.unwrap_or(rest.len()));
+                        (Board::new(Some(fen.join(" ")))?, moves)
+                    }
+                    _ => bail!("expected position startpos or position fen <fen>"),
+                };
+                let moves = match moves {
+                    ["moves", moves @ ..] => moves,
+                    [] => &[],
+                    _ => bail!("expected moves after the position"),
+                };
+                let mut analysis = Analysis::new(board);
+                analysis.play(moves)?;
+                *self = analysis;
+                Ok(self.board().get_fen())
+            }
+            ["move", moves @ ..] if !moves.is_empty() => {
+                self.play(moves)?;
+                Ok(self.board().get_fen())
+            }
+            ["undo"] => {
+                if self.boards.len() == 1 {
+                    bail!("there is no move to take back");
+                }
+                self.boards.pop();
+                Ok(self.board().get_fen())
+            }
+            ["go", "depth", depth] => {
+                let depth: usize = depth.parse().context("could not parse depth")?;
+                if depth == 0 {
+                    bail!("depth must be at least 1");
+                }
+                self.search(EnginePlayer::with_depth(depth))
+            }
+            ["go", "ms", ms] => {
+                let ms = ms.parse().context("could not parse ms")?;
+                let (eval, line) = EnginePlayer::analyse(self.board(), Duration::from_millis(ms))?;
+                let board = self.board();
+                Ok(format!(
+                    "Eval: {eval}, best {} ({})",
+                    line[0].san(board),
+                    board.line_san(&line)
+                ))
+            }
+            ["eval"] => {
+                let (eval, mv) = EnginePlayer::evaluate(self.board())?;
+                Ok(format!("Eval: {eval}, best {}", mv.san(self.board())))
+            }
+            ["hint"] => {
+                let (eval, mv) = EnginePlayer::evaluate(self.board())?;
+                Ok(format!("Hint: {} (eval {eval})", mv.san(self.board())))
+            }
+            ["fen"] => Ok(self.board().get_fen()),
+            ["board"] => Ok(self.board().to_string()),
+            ["help"] => Ok(HELP.into()),
+            _ => bail!("unknown command \"{}\", try help", line.trim()),
+        }
+    }
+}
+
+// Reads commands line by line until quit or the end of the input
+pub fn repl(board: Board, input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+    let mut analysis = Analysis::new(board);
+    for line in input.lines() {
+        let line = line?;
+        if line.trim() == "quit" {
+            break;
+        }
+        match analysis.run_command(&line) {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => writeln!(output, "{text}")?,
+            Err(err) => writeln!(output, "Error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Color;
+
+    #[test]
+    fn test_scripted_session() {
+        let commands = "\
+move e4 e5
+move Nf3
+fen
+undo
+move g1f3 b8c6
+go depth 1
+move Qh5
+undo
+bogus
+position fen 7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1
+go depth 3
+quit
+fen
+";
+        let mut output = Vec::new();
+        repl(Board::new(None).unwrap(), commands.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        let after_nf3 = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b - - 1 2";
+        assert_eq!(lines[1], after_nf3);
+        assert_eq!(lines[2], after_nf3); // fen
+        assert_eq!(
+            lines[3],
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w - - 0 2"
+        );
+        assert!(lines[5].starts_with("Eval: "), "{}", lines[5]);
+        // the knight on f3 is in the way
+        assert!(lines[6].starts_with("Error: "), "{}", lines[6]);
+        assert_eq!(lines[7], after_nf3); // undoing Nc6
+        assert!(
+            lines[8].starts_with("Error: unknown command"),
+            "{}",
+            lines[8]
+        );
+        assert_eq!(lines[9], "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1");
+        assert!(lines[10].starts_with("Eval: W3"), "{}", lines[10]);
+        // nothing is read after quit
+        assert_eq!(lines.len(), 11);
+    }
+
+    #[test]
+    fn test_position_command() {
+        let mut analysis = Analysis::new(Board::new(None).unwrap());
+        let fen = analysis
+            .run_command("position startpos moves e2e4 e7e5")
+            .unwrap();
+        assert_eq!(
+            fen,
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w - - 0 2"
+        );
+        // the moves of the position can be undone, but not the position itself
+        analysis.run_command("undo").unwrap();
+        analysis.run_command("undo").unwrap();
+        assert!(analysis.run_command("undo").is_err());
+
+        let fen = "8/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let after = analysis
+            .run_command(&format!("position fen {fen} moves e8=Q"))
+            .unwrap();
+        assert_eq!(after, "4Q3/8/8/8/8/8/8/k3K3 b - - 0 1");
+        assert!(analysis.run_command("position fen nonsense").is_err());
+        assert!(analysis.run_command("position startpos e2e4").is_err());
+        assert!(analysis.run_command("go depth 0").is_err());
+    }
+
+    #[test]
+    fn test_hint() {
+        let fen = "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1";
+        let mut analysis = Analysis::new(Board::new(Some(fen.into())).unwrap());
+        let hint = analysis.run_command("hint").unwrap();
+        let san = hint
+            .strip_prefix("Hint: ")
+            .and_then(|hint| hint.strip_suffix(" (eval W3)"))
+            .unwrap_or_else(|| panic!("{hint}"));
+        // the hint is not played
+        assert_eq!(analysis.run_command("fen").unwrap(), fen);
+        analysis.run_command(&format!("move {san}")).unwrap();
+        let board = analysis.board();
+        assert!(board.is_in_check(Color::Black));
+        assert!(board.legal_moves(Color::Black).is_empty(), "{hint}");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    board::Position,
+    pieces::{Color, Move, PieceType, SpecialMove, ALL_DIRECTIONS, KNIGHT_MOVES},
+};
+
+use super::{
+    magic::{bishop_attacks, rook_attacks},
+    pawns::pawn_attacks,
+    Bitboard, BitboardGame,
+};
+
+// For every square, the squares reached with one of the given (file, rank) steps
+// that stay on the board
+const fn attack_table(steps: [(i32, i32); 8]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+        let mut i = 0;
+        while i < steps.len() {
+            let (to_file, to_rank) = (file + steps[i].0, rank + steps[i].1);
+            if to_file >= 0 && to_file < 8 && to_rank >= 0 && to_rank < 8 {
+                table[square] |= 1 << (to_rank * 8 + to_file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const KNIGHT_ATTACKS: [u64; 64] = attack_table(KNIGHT_MOVES);
+const KING_ATTACKS: [u64; 64] = attack_table(ALL_DIRECTIONS);
+
+pub(super) fn knight_attacks(square: u32) -> Bitboard {
+    Bitboard(KNIGHT_ATTACKS[square as usize])
+}
+
+pub(super) fn king_attacks(square: u32) -> Bitboard {
+    Bitboard(KING_ATTACKS[square as usize])
+}
+
+impl BitboardGame {
+    // Whether any piece of the given color attacks the square
+    pub fn is_attacked(&self, square: u32, by: Color) -> bool {
+        self.attackers(square, by, self.occupancy_all()) != 0
+    }
+
+    // The pieces of the given color that attack the square, with the bishops, rooks
+    // and queens blocked by the given occupancy rather than the actual one
+    pub fn attackers(&self, square: u32, by: Color, occupied: u64) -> u64 {
+        let pieces = |typ| self.pieces((by, typ));
+        let diagonal = bishop_attacks(square, occupied).0;
+        let straight = rook_attacks(square, occupied).0;
+        // a pawn attacks the square from where a pawn on it would attack the other way
+        let pawn_sources = pawn_attacks(1 << square, !by);
+        knight_attacks(square).0 & pieces(PieceType::Knight)
+            | king_attacks(square).0 & pieces(PieceType::King)
+            | pawn_sources & pieces(PieceType::Pawn)
+            | diagonal & (pieces(PieceType::Bishop) | pieces(PieceType::Queen))
+            | straight & (pieces(PieceType::Rook) | pieces(PieceType::Queen))
+    }
+
+    // The knight and king moves of the given color, castling left out, without
+    // checking whether they leave the king in check
+    pub fn leaper_moves(&self, color: Color) -> Vec<Move> {
+        let own = self.occupancy(color);
+        let mut moves = Vec::new();
+        let leapers = [
+            (PieceType::Knight, KNIGHT_ATTACKS),
+            (PieceType::King, KING_ATTACKS),
+        ];
+        for (typ, attacks) in leapers {
+            for from in Bitboard(self.pieces((color, typ))) {
+                for to in Bitboard(attacks[from as usize] & !own) {
+                    let to_square = Position::from(to as usize);
+                    moves.push(Move {
+                        from: Position::from(from as usize),
+                        to: to_square,
+                        special: self
+                            .get(to)
+                            .map(|(_, typ)| SpecialMove::Capture(typ, to_square)),
+                    });
+                }
+            }
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::board::Board;
+
+    // The squares reached with the steps, one offset at a time
+    fn brute_force(square: u32, steps: &[(i32, i32)]) -> u64 {
+        let from = Position::from(square as usize);
+        steps
+            .iter()
+            .filter_map(|&(file, rank)| from.offset(file, rank))
+            .map(|to| 1 << (to.rank() * 8 + to.file()))
+            .fold(0, |attacks, mask| attacks | mask)
+    }
+
+    #[test]
+    fn test_tables() {
+        for square in 0..64 {
+            assert_eq!(knight_attacks(square).0, brute_force(square, &KNIGHT_MOVES));
+            assert_eq!(king_attacks(square).0, brute_force(square, &ALL_DIRECTIONS));
+        }
+        // in the corners, with nothing wrapping around to the other side
+        let a1: Vec<u32> = knight_attacks(0).into_iter().collect();
+        assert_eq!(a1, [10, 17]); // c2, b3
+        let h8: Vec<u32> = king_attacks(63).into_iter().collect();
+        assert_eq!(h8, [54, 55, 62]); // g7, h7, g8
+        let h4: Vec<u32> = knight_attacks(31).into_iter().collect();
+        assert_eq!(h4, [14, 21, 37, 46]); // g2, f3, f5, g6
+    }
+
+    // Compares the bitboards with the mailbox board in the position
+    fn check_position(board: &Board) {
+        let game = BitboardGame::from(board);
+        for square in 0..64 {
+            let position = Position::from(square as usize);
+            for color in [Color::White, Color::Black] {
+                assert_eq!(
+                    game.is_attacked(square, color),
+                    board.is_square_attacked(&position, color),
+                    "{position} by {color:?} in {}",
+                    board.get_fen()
+                );
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            let mut expected: Vec<Move> = board
+                .get_pieces(color)
+                .into_iter()
+                .filter(|(_, piece)| matches!(piece.typ, PieceType::Knight | PieceType::King))
+                .flat_map(|(pos, piece)| piece.get_moves(board, &pos))
+                .filter(|mv| !matches!(mv.special, Some(SpecialMove::Castling(..))))
+                .collect();
+            let mut moves = game.leaper_moves(color);
+            expected.sort_by_key(|mv| format!("{mv:?}"));
+            moves.sort_by_key(|mv| format!("{mv:?}"));
+            assert_eq!(moves, expected, "{color:?} in {}", board.get_fen());
+        }
+    }
+
+    #[test]
+    fn test_against_mailbox() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "N6N/8/8/8/8/8/8/N3K2N w - - 0 1",
+        ];
+        for fen in fens {
+            check_position(&Board::new(Some(fen.into())).unwrap());
+        }
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..60 {
+                check_position(&board);
+                let moves = board.legal_moves(board.current_turn());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+    }
+}
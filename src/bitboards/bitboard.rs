@@ -1,13 +1,17 @@
 use std::{
     array::IntoIter,
-    ops::{Add, AddAssign, Mul, MulAssign},
+    fmt::Display,
+    ops::{
+        BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, ShlAssign, Shr,
+        ShrAssign,
+    },
 };
 
-use crate::pieces::{Color, PieceType};
+use crate::pieces::{self, Color, PieceType};
 
-type Piece = (Color, PieceType);
+pub(crate) type Piece = (Color, PieceType);
 
-const PIECES: [Piece; 12] = [
+pub(super) const PIECES: [Piece; 12] = [
     (Color::White, PieceType::King),
     (Color::Black, PieceType::King),
     (Color::White, PieceType::Queen),
@@ -40,15 +44,37 @@ fn piece_idx((color, typ): Piece) -> usize {
     color + piece
 }
 
+// The castling rights, as bits of BitboardGame::castling
+pub const WHITE_KINGSIDE: u8 = 1;
+pub const WHITE_QUEENSIDE: u8 = 2;
+pub const BLACK_KINGSIDE: u8 = 4;
+pub const BLACK_QUEENSIDE: u8 = 8;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct BitboardGame {
     bitboards: [Bitboard; 12],
+    occupancy: [Bitboard; 2], // all pieces of each color, kept up to date by set and remove
+    en_passant: Option<u32>,  // the square a pawn skipped with a double step just now
+    turn: Color,
+    castling: u8,   // the rights that are left
+    halfmoves: u32, // plies since the last capture or pawn move
+}
+
+impl Default for BitboardGame {
+    fn default() -> Self {
+        BitboardGame::new()
+    }
 }
 
 impl BitboardGame {
     pub fn new(/*fen: Into<String> */) -> Self {
         Self {
             bitboards: [Bitboard(0); PIECES.len()],
+            occupancy: [Bitboard(0); 2],
+            en_passant: None,
+            turn: Color::White,
+            castling: 0,
+            halfmoves: 0,
         }
     }
 
@@ -61,61 +87,222 @@ impl BitboardGame {
     }
 
     pub fn get(&self, pos: u32) -> Option<Piece> {
+        if !Bitboard(self.occupancy_all()).contains(pos) {
+            return None;
+        }
         for (board, piece) in self.bitboards.iter().zip(PIECES.iter()) {
-            if board.is_set(pos) {
+            if board.contains(pos) {
                 return Some(*piece);
             }
         }
         None
     }
 
+    // Puts the piece on the square, returning the one it replaces
     pub fn set(&mut self, pos: u32, piece: Piece) -> Option<Piece> {
-        let prev = self.get(pos);
+        let prev = self.remove(pos);
 
-        let mask: u64 = 1 << pos;
-        *self.board_for_mut(piece) += Bitboard(mask);
+        let mask = Bitboard(1 << pos);
+        *self.board_for_mut(piece) |= mask;
+        self.occupancy[piece.0 as usize] |= mask;
         prev
     }
+
+    pub fn remove(&mut self, pos: u32) -> Option<Piece> {
+        let piece = self.get(pos)?;
+        self.board_for_mut(piece).clear(pos);
+        self.occupancy[piece.0 as usize].clear(pos);
+        Some(piece)
+    }
+
+    // The squares of all pieces of the kind
+    pub fn pieces(&self, piece: Piece) -> u64 {
+        self.board_for(piece).0
+    }
+
+    pub fn en_passant(&self) -> Option<u32> {
+        self.en_passant
+    }
+
+    pub fn set_en_passant(&mut self, square: Option<u32>) {
+        self.en_passant = square;
+    }
+
+    pub fn turn(&self) -> Color {
+        self.turn
+    }
+
+    pub fn set_turn(&mut self, color: Color) {
+        self.turn = color;
+    }
+
+    pub fn castling(&self) -> u8 {
+        self.castling
+    }
+
+    pub fn set_castling(&mut self, rights: u8) {
+        self.castling = rights;
+    }
+
+    pub fn halfmoves(&self) -> u32 {
+        self.halfmoves
+    }
+
+    pub fn set_halfmoves(&mut self, halfmoves: u32) {
+        self.halfmoves = halfmoves;
+    }
+
+    pub fn occupancy(&self, color: Color) -> u64 {
+        self.occupancy[color as usize].0
+    }
+
+    pub fn occupancy_all(&self) -> u64 {
+        (self.occupancy[0] | self.occupancy[1]).0
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct Bitboard(u64);
+pub(super) struct Bitboard(pub(super) u64);
 
 impl Bitboard {
-    fn is_set(&self, idx: u32) -> bool {
+    pub(super) fn contains(&self, idx: u32) -> bool {
         self.0 & (1 << idx) != 0
     }
+
+    pub(super) fn union(self, other: Bitboard) -> Bitboard {
+        self | other
+    }
+
+    pub(super) fn intersection(self, other: Bitboard) -> Bitboard {
+        self & other
+    }
+
+    // The number of squares set
+    pub(super) fn count(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn clear(&mut self, idx: u32) {
+        self.0 &= !(1 << idx);
+    }
+
+    // The squares given by name, e.g. ["e2", "e4"]
+    #[cfg(test)]
+    pub(super) fn from_squares(squares: &[&str]) -> Self {
+        let position = |name: &&str| {
+            let name: &[u8; 2] = name.as_bytes().try_into().expect("a square such as e4");
+            crate::board::Position::from(name)
+        };
+        Bitboard(
+            squares
+                .iter()
+                .map(position)
+                .fold(0, |bits, pos| bits | 1 << (pos.rank() * 8 + pos.file())),
+        )
+    }
 }
 
-impl Add for Bitboard {
-    type Output = u64;
+// Writes a grid with rank 8 on top, the way Board shows the pieces
+fn write_grid(f: &mut std::fmt::Formatter<'_>, square: impl Fn(u32) -> String) -> std::fmt::Result {
+    writeln!(f, "  a b c d e f g h")?;
+    for rank in (0..8).rev() {
+        write!(f, "{}", rank + 1)?;
+        for file in 0..8 {
+            write!(f, " {}", square(rank * 8 + file))?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
 
-    fn add(self, rhs: Self) -> Self::Output {
-        self.0 | rhs.0
+impl Display for Bitboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_grid(f, |idx| {
+            if self.contains(idx) { "1" } else { "." }.to_string()
+        })
     }
 }
 
-impl AddAssign for Bitboard {
-    fn add_assign(&mut self, rhs: Self) {
-        (*self).0 |= rhs.0
+// The same as the Display of Board, so the two can be compared line by line
+impl Display for BitboardGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_grid(f, |idx| match self.get(idx) {
+            Some((color, typ)) => pieces::Piece {
+                color,
+                typ,
+                most_recent_move: None,
+            }
+            .to_string(),
+            None => " ".to_string(),
+        })
     }
 }
 
-impl Mul for Bitboard {
-    type Output = u64;
+// The set operations on the squares, as the same operation on the bits
+macro_rules! bitwise_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl $trait for Bitboard {
+            type Output = Bitboard;
+
+            fn $method(self, rhs: Self) -> Self::Output {
+                Bitboard(self.0 $op rhs.0)
+            }
+        }
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        self.0 | rhs.0
+        impl $assign_trait for Bitboard {
+            fn $assign_method(&mut self, rhs: Self) {
+                *self = *self $op rhs;
+            }
+        }
+    };
+}
+
+bitwise_op!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+bitwise_op!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+bitwise_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Self::Output {
+        Bitboard(!self.0)
+    }
+}
+
+// Shifting moves every square up the board, by ranks of 8, or down with Shr
+impl Shl<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        Bitboard(self.0 << rhs)
+    }
+}
+
+impl ShlAssign<u32> for Bitboard {
+    fn shl_assign(&mut self, rhs: u32) {
+        self.0 <<= rhs;
+    }
+}
+
+impl Shr<u32> for Bitboard {
+    type Output = Bitboard;
+
+    fn shr(self, rhs: u32) -> Self::Output {
+        Bitboard(self.0 >> rhs)
     }
 }
 
-impl MulAssign for Bitboard {
-    fn mul_assign(&mut self, rhs: Self) {
-        (*self).0 |= rhs.0
+impl ShrAssign<u32> for Bitboard {
+    fn shr_assign(&mut self, rhs: u32) {
+        self.0 >>= rhs;
     }
 }
 
-struct BitboardIter {
+pub(super) struct BitboardIter {
     bitboard: u64,
 }
 
@@ -126,10 +313,10 @@ impl Iterator for BitboardIter {
         if self.bitboard == 0 {
             None
         } else {
-            let one_index = self.bitboard.trailing_zeros() + 1;
-            let mask = 1 << one_index;
-            self.bitboard &= mask;
-            Some(one_index)
+            // take the lowest set bit off the board
+            let idx = self.bitboard.trailing_zeros();
+            self.bitboard &= self.bitboard - 1;
+            Some(idx)
         }
     }
 }
@@ -143,3 +330,175 @@ impl IntoIterator for Bitboard {
         BitboardIter { bitboard: self.0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(bitboard: u64) -> Vec<u32> {
+        Bitboard(bitboard).into_iter().collect()
+    }
+
+    #[test]
+    fn test_operators() {
+        let a = Bitboard::from_squares(&["a1", "b2", "c3"]);
+        let b = Bitboard::from_squares(&["b2", "c3", "d4"]);
+        assert_eq!(a | b, Bitboard::from_squares(&["a1", "b2", "c3", "d4"]));
+        assert_eq!(a & b, Bitboard::from_squares(&["b2", "c3"]));
+        assert_eq!(a ^ b, Bitboard::from_squares(&["a1", "d4"]));
+        assert_eq!(a.union(b), a | b);
+        assert_eq!(a.intersection(b), a & b);
+        assert_eq!((!a).count(), 61);
+        assert!((!a & a).is_empty());
+
+        // up a rank, then back down
+        assert_eq!(a << 8, Bitboard::from_squares(&["a2", "b3", "c4"]));
+        assert_eq!((a << 8) >> 8, a);
+
+        let mut c = a;
+        c &= b;
+        assert_eq!(c, a & b);
+        c |= a;
+        assert_eq!(c, a);
+        c ^= a;
+        assert!(c.is_empty());
+        let mut d = a;
+        d <<= 1;
+        assert_eq!(d, Bitboard::from_squares(&["b1", "c2", "d3"]));
+        d >>= 1;
+        assert_eq!(d, a);
+
+        assert!(a.contains(0) && !a.contains(1));
+        assert_eq!(a.count(), 3);
+        assert!(!a.is_empty() && Bitboard(0).is_empty());
+    }
+
+    #[test]
+    fn test_display() {
+        let a_file = Bitboard::from_squares(&["a1", "a2", "a3", "a4", "a5", "a6", "a7", "a8"]);
+        let expected = [
+            "  a b c d e f g h",
+            "8 1 . . . . . . .",
+            "7 1 . . . . . . .",
+            "6 1 . . . . . . .",
+            "5 1 . . . . . . .",
+            "4 1 . . . . . . .",
+            "3 1 . . . . . . .",
+            "2 1 . . . . . . .",
+            "1 1 . . . . . . .",
+        ];
+        assert_eq!(a_file.to_string(), expected.join("\n") + "\n");
+        assert_eq!(a_file.0, 0x0101_0101_0101_0101);
+
+        let diagonal = Bitboard::from_squares(&["a1", "b2", "c3", "d4", "e5", "f6", "g7", "h8"]);
+        let expected = [
+            "  a b c d e f g h",
+            "8 . . . . . . . 1",
+            "7 . . . . . . 1 .",
+            "6 . . . . . 1 . .",
+            "5 . . . . 1 . . .",
+            "4 . . . 1 . . . .",
+            "3 . . 1 . . . . .",
+            "2 . 1 . . . . . .",
+            "1 1 . . . . . . .",
+        ];
+        assert_eq!(diagonal.to_string(), expected.join("\n") + "\n");
+
+        let game = BitboardGame::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        let pawns = Bitboard(game.pieces((Color::White, PieceType::Pawn)));
+        let expected = [
+            "  a b c d e f g h",
+            "8 . . . . . . . .",
+            "7 . . . . . . . .",
+            "6 . . . . . . . .",
+            "5 . . . . . . . .",
+            "4 . . . . . . . .",
+            "3 . . . . . . . .",
+            "2 1 1 1 1 1 1 1 1",
+            "1 . . . . . . . .",
+        ];
+        assert_eq!(pawns.to_string(), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn test_display_game() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let game = BitboardGame::from_fen(fen).unwrap();
+            let board = crate::board::Board::new(Some(fen.into())).unwrap();
+            assert_eq!(game.to_string(), board.to_string(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        assert!(indices(0).is_empty());
+        assert_eq!(indices(1), [0]);
+        assert_eq!(indices(1 << 63), [63]);
+        assert_eq!(indices(1 | 1 << 63), [0, 63]);
+
+        let even: Vec<u32> = (0..64).step_by(2).collect();
+        assert_eq!(indices(0x5555_5555_5555_5555), even);
+        let odd: Vec<u32> = (1..64).step_by(2).collect();
+        assert_eq!(indices(0xAAAA_AAAA_AAAA_AAAA), odd);
+        assert_eq!(indices(u64::MAX), (0..64).collect::<Vec<_>>());
+
+        for idx in 0..64 {
+            assert_eq!(indices(1 << idx), [idx]);
+        }
+    }
+
+    // The occupancy masks as the pieces on the squares have them
+    fn check_occupancy(game: &BitboardGame) {
+        let mut occupancy = [0; 2];
+        for pos in 0..64 {
+            if let Some((color, _)) = game.get(pos) {
+                occupancy[color as usize] |= 1 << pos;
+            }
+        }
+        assert_eq!(game.occupancy(Color::White), occupancy[0]);
+        assert_eq!(game.occupancy(Color::Black), occupancy[1]);
+        assert_eq!(game.occupancy_all(), occupancy[0] | occupancy[1]);
+        // and every piece is on one board only
+        let total: u32 = game
+            .bitboards
+            .iter()
+            .map(|board| board.0.count_ones())
+            .sum();
+        assert_eq!(total, game.occupancy_all().count_ones());
+    }
+
+    #[test]
+    fn test_set_and_remove() {
+        let white_rook = (Color::White, PieceType::Rook);
+        let black_queen = (Color::Black, PieceType::Queen);
+        let mut game = BitboardGame::new();
+        check_occupancy(&game);
+
+        assert_eq!(game.set(0, white_rook), None);
+        assert_eq!(game.set(63, black_queen), None);
+        check_occupancy(&game);
+        assert_eq!(game.occupancy(Color::White), 1);
+        assert_eq!(game.occupancy(Color::Black), 1 << 63);
+
+        // a capture replaces the piece
+        assert_eq!(game.set(63, white_rook), Some(black_queen));
+        assert_eq!(game.get(63), Some(white_rook));
+        check_occupancy(&game);
+        assert_eq!(game.occupancy(Color::Black), 0);
+        assert_eq!(game.occupancy_all(), 1 | 1 << 63);
+
+        assert_eq!(game.remove(0), Some(white_rook));
+        assert_eq!(game.remove(0), None);
+        assert_eq!(game.get(0), None);
+        check_occupancy(&game);
+        assert_eq!(game.occupancy_all(), 1 << 63);
+
+        assert_eq!(game.remove(63), Some(white_rook));
+        assert_eq!(game, BitboardGame::new());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    board::{
+        zobrist_castling, zobrist_en_passant, zobrist_key, Board, Position, ZOBRIST_BLACK_TO_MOVE,
+    },
+    pieces::{Color, Piece, PieceType, SpecialMove},
+};
+
+use super::{
+    Bitboard, BitboardGame, BLACK_KINGSIDE, BLACK_QUEENSIDE, PIECES, WHITE_KINGSIDE,
+    WHITE_QUEENSIDE,
+};
+
+const CASTLING_RIGHTS: [(u8, Color, bool); 4] = [
+    (WHITE_KINGSIDE, Color::White, false),
+    (WHITE_QUEENSIDE, Color::White, true),
+    (BLACK_KINGSIDE, Color::Black, false),
+    (BLACK_QUEENSIDE, Color::Black, true),
+];
+
+// The squares of the king and the rook for each castling right
+const CASTLING_SQUARES: [(u8, usize, usize); 4] = [
+    (WHITE_KINGSIDE, 4, 7),
+    (WHITE_QUEENSIDE, 4, 0),
+    (BLACK_KINGSIDE, 60, 63),
+    (BLACK_QUEENSIDE, 60, 56),
+];
+
+impl From<&Board> for BitboardGame {
+    fn from(board: &Board) -> Self {
+        let mut game = BitboardGame::new();
+        for idx in 0..64 {
+            if let Some(piece) = board[Position::from(idx)] {
+                game.set(idx as u32, (piece.color, piece.typ));
+            }
+        }
+        // after a double step, whether or not the pawn can be taken
+        let double_step = board.last_move.filter(|last| {
+            last.from.rank().abs_diff(last.to.rank()) == 2
+                && board[last.to].is_some_and(|piece| piece.typ == PieceType::Pawn)
+        });
+        if let Some(last) = double_step {
+            let skipped = (last.from.rank() + last.to.rank()) / 2 * 8 + last.to.file();
+            game.set_en_passant(Some(skipped as u32));
+        }
+        game.set_turn(board.current_turn());
+        game.set_halfmoves((board.ply - board.last_pawn_move) as u32);
+        // the board allows castling as long as the king and the rook have not moved
+        let unmoved = |idx: usize, typ: PieceType| {
+            board[Position::from(idx)]
+                .is_some_and(|piece| piece.typ == typ && piece.most_recent_move.is_none())
+        };
+        let rights = CASTLING_SQUARES
+            .iter()
+            .filter(|&&(_, king, rook)| {
+                unmoved(king, PieceType::King) && unmoved(rook, PieceType::Rook)
+            })
+            .fold(0, |rights, (right, _, _)| rights | right);
+        game.set_castling(rights);
+        game
+    }
+}
+
+// The board does not read the castling rights from the FEN, every piece counts as
+// not having moved yet, which allows castling where it is possible
+impl From<&BitboardGame> for Board {
+    fn from(game: &BitboardGame) -> Self {
+        Board::new(Some(game.fen())).expect("the bitboards make a valid FEN")
+    }
+}
+
+impl BitboardGame {
+    // The squares where the board has a different piece, or none, for comparing the
+    // two representations
+    #[cfg(test)]
+    pub fn mismatches(&self, board: &Board) -> Vec<Position> {
+        (0..64)
+            .filter(|&idx| {
+                let piece = board[Position::from(idx)].map(|piece: Piece| (piece.color, piece.typ));
+                self.get(idx as u32) != piece
+            })
+            .map(Position::from)
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn same_pieces(&self, board: &Board) -> bool {
+        self.mismatches(board).is_empty()
+    }
+
+    // The same key as Board::repetition_key gives the same position, so that a game
+    // and a search on bitboards agree on repetitions
+    pub fn repetition_key(&self) -> u64 {
+        let mut key = 0;
+        for (color, typ) in PIECES {
+            for square in Bitboard(self.pieces((color, typ))) {
+                let piece = Piece {
+                    color,
+                    typ,
+                    most_recent_move: None,
+                };
+                key ^= zobrist_key(Position::from(square as usize), &piece);
+            }
+        }
+        if self.turn() == Color::Black {
+            key ^= ZOBRIST_BLACK_TO_MOVE;
+        }
+        // like the board, only when a pawn can take en passant
+        if let Some(target) = self.en_passant() {
+            let possible = self
+                .legal_moves()
+                .iter()
+                .any(|mv| matches!(mv.special, Some(SpecialMove::EnPassant(_))));
+            if possible {
+                key ^= zobrist_en_passant(target as usize % 8);
+            }
+        }
+        for (right, color, queenside) in CASTLING_RIGHTS {
+            if self.castling() & right != 0 {
+                key ^= zobrist_castling(color, queenside);
+            }
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+
+    const FENS: [&str; 12] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "4k3/8/8/8/8/8/8/4K3 b - - 10 40",
+        "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1",
+        "8/8/8/8/8/8/6k1/4K2R w K - 0 1",
+        "1k6/1P6/2K5/8/8/8/8/8 b - - 0 60",
+        "8/PPPPPPPP/8/8/8/8/pppppppp/8 w - - 0 1",
+    ];
+
+    #[test]
+    fn test_from_board() {
+        for fen in FENS {
+            let board = Board::new(Some(fen.into())).unwrap();
+            let game = BitboardGame::from(&board);
+            assert_eq!(game.mismatches(&board), [], "{fen}");
+            assert_eq!(game, BitboardGame::from_fen(fen).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_to_board() {
+        for fen in FENS {
+            let game = BitboardGame::from_fen(fen).unwrap();
+            let board = Board::from(&game);
+            assert!(game.same_pieces(&board), "{fen}");
+            assert_eq!(board.get_fen_pieces(), game.fen_pieces());
+            // the move number is not kept, and the board has no more halfmoves
+            // than plies since the start
+            let mut back = BitboardGame::from(&board);
+            assert!(back.halfmoves() <= game.halfmoves());
+            back.set_halfmoves(game.halfmoves());
+            assert_eq!(back, game);
+        }
+    }
+
+    #[test]
+    fn test_repetition_key() {
+        // the board takes castling rights from unmoved pieces, so compare along games
+        // from the start, with the moves made on the bitboards
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..10 {
+            let mut board = Board::new(None).unwrap();
+            let mut game = BitboardGame::from(&board);
+            for _ in 0..60 {
+                let moves = board.legal_moves(board.current_turn());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+                game.make_move(mv);
+                assert_eq!(
+                    game.repetition_key(),
+                    board.repetition_key(),
+                    "{}",
+                    board.get_fen()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let start = Board::new(None).unwrap();
+        let game = BitboardGame::from(&start);
+        let board = start.apply_san("e4").unwrap();
+        let squares: Vec<String> = game
+            .mismatches(&board)
+            .iter()
+            .map(|position| position.to_string())
+            .collect();
+        assert_eq!(squares, ["e2", "e4"]);
+        assert!(!game.same_pieces(&board));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{
+    board::{piece_value, Position},
+    pieces::{Color, Piece},
+};
+
+use super::{Bitboard, BitboardGame, PIECES};
+
+impl BitboardGame {
+    // The value of the pieces of the given color, the same as Board::material
+    pub fn material(&self, color: Color) -> i32 {
+        let mut material = 0;
+        for (_, typ) in PIECES.into_iter().filter(|(c, _)| *c == color) {
+            let piece = Piece {
+                color,
+                typ,
+                most_recent_move: None,
+            };
+            for square in Bitboard(self.pieces((color, typ))) {
+                material += piece_value(Position::from(square as usize), &piece);
+            }
+        }
+        material
+    }
+
+    pub fn material_balance(&self, perspective: Color) -> i32 {
+        self.material(perspective) - self.material(!perspective)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn test_material() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..10 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..100 {
+                let game = BitboardGame::from(&board);
+                for color in [Color::White, Color::Black] {
+                    assert_eq!(game.material(color), board.material(color));
+                }
+                let moves = board.legal_moves(board.current_turn());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+    }
+}
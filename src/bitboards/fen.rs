@@ -1,86 +1,246 @@
+use anyhow::anyhow;
 use nom::{
     branch::alt,
-    bytes::tag,
-    character::complete::one_of,
-    combinator::{map, value},
+    bytes::complete::tag,
+    character::complete::{anychar, one_of, u32 as parse_u32},
+    combinator::{eof, map, map_opt, opt, peek, value, verify},
+    multi::{fold_many1, separated_list1},
+    sequence::{preceded, terminated},
     IResult, Parser,
 };
 
-use crate::pieces::{Color, PieceType};
+use crate::{
+    board::Position,
+    pieces::{self, Color},
+};
 
-use super::BitboardGame;
+use super::{
+    BitboardGame, Piece, BLACK_KINGSIDE, BLACK_QUEENSIDE, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
 
 impl BitboardGame {
-    /* fn from_fen(fen: &str) -> anyhow::Result<BitboardGame> {
-      let mut board = BitboardGame::new();
-      Ok(board)
-    } */
-}
+    // Reads the pieces, side to move, castling rights, en passant square and
+    // halfmove clock. Only the pieces are needed, the move number is not kept.
+    pub fn from_fen(fen: &str) -> anyhow::Result<BitboardGame> {
+        let (_, (ranks, fields)) =
+            parser(fen).map_err(|err| anyhow!("invalid FEN {fen}: {err}"))?;
 
-fn parser(fen: &str) -> IResult<&str, &str> {
-    //let (pieces, rest) = parse_pieces(fen)?;
+        let mut board = BitboardGame::new();
+        if let Some((turn, castling, en_passant, halfmoves)) = fields {
+            board.set_turn(turn);
+            board.set_castling(castling);
+            board.set_en_passant(en_passant);
+            board.set_halfmoves(halfmoves.unwrap_or(0));
+        }
+        // the FEN starts with rank 8, which has the highest indices
+        for (row, squares) in ranks.iter().enumerate() {
+            for (file, square) in squares.iter().enumerate() {
+                if let Some(piece) = square {
+                    board.set(((7 - row) * 8 + file) as u32, *piece);
+                }
+            }
+        }
+        Ok(board)
+    }
 
-    Ok((fen, fen))
+    // The FEN of the position, the move number is not kept and always 1
+    pub fn fen(&self) -> String {
+        let turn = match self.turn() {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        let rights = [
+            (WHITE_KINGSIDE, 'K'),
+            (WHITE_QUEENSIDE, 'Q'),
+            (BLACK_KINGSIDE, 'k'),
+            (BLACK_QUEENSIDE, 'q'),
+        ];
+        let mut castling: String = rights
+            .iter()
+            .filter(|(right, _)| self.castling() & right != 0)
+            .map(|(_, letter)| letter)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = match self.en_passant() {
+            Some(square) => Position::from(square as usize).to_string(),
+            None => "-".into(),
+        };
+        format!(
+            "{} {turn} {castling} {en_passant} {} 1",
+            self.fen_pieces(),
+            self.halfmoves()
+        )
+    }
+
+    // The piece placement field of the FEN, rank 8 first
+    pub fn fen_pieces(&self) -> String {
+        let ranks: Vec<String> = (0..8)
+            .rev()
+            .map(|rank| {
+                let mut text = String::new();
+                let mut empty = 0;
+                for file in 0..8 {
+                    let Some(piece) = self.get(rank * 8 + file) else {
+                        empty += 1;
+                        continue;
+                    };
+                    if empty > 0 {
+                        text.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    text.push(piece_char(piece));
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                }
+                text
+            })
+            .collect();
+        ranks.join("/")
+    }
 }
 
-fn parse_pieces<'a>(fen: &str) -> IResult<&str, &str> {
-    let mut index = 0;
+fn piece_char((color, typ): Piece) -> char {
+    pieces::Piece {
+        color,
+        typ,
+        most_recent_move: None,
+    }
+    .fen_char()
+}
 
-    let mut board = BitboardGame::new();
+type Ranks = Vec<Vec<Option<Piece>>>;
+type Fields = (Color, u8, Option<u32>, Option<u32>);
 
-    for row in fen.split("/") {
-        let mut text = row;
-        loop {
-            if let Ok((rest, ws)) = parse_empty(text) {
-                index += ws;
-                text = rest;
-                continue;
-            }
-            if let Ok((rest, piece)) = parse_piece(text) {
-                board.set(index, piece);
-                text = rest;
-                index += 1;
-                continue;
-            }
-        }
-    }
+// The ranks of the piece placement, and the side to move, castling rights and en
+// passant square if they follow
+fn parser(fen: &str) -> IResult<&str, (Ranks, Option<Fields>)> {
+    let fields = alt((value(None, eof), map(parse_fields, Some)));
+    terminated((parse_pieces, fields), peek(alt((eof, tag(" "))))).parse(fen)
+}
 
-    Ok((fen, fen))
+fn parse_fields(input: &str) -> IResult<&str, Fields> {
+    let turn = alt((value(Color::White, tag("w")), value(Color::Black, tag("b"))));
+    let right = alt((
+        value(WHITE_KINGSIDE, tag("K")),
+        value(WHITE_QUEENSIDE, tag("Q")),
+        value(BLACK_KINGSIDE, tag("k")),
+        value(BLACK_QUEENSIDE, tag("q")),
+    ));
+    let castling = alt((
+        value(0, tag("-")),
+        fold_many1(right, || 0, |rights, right| rights | right),
+    ));
+    let square = map((one_of("abcdefgh"), one_of("12345678")), |(file, rank)| {
+        (rank as u32 - '1' as u32) * 8 + (file as u32 - 'a' as u32)
+    });
+    let en_passant = alt((value(None, tag("-")), map(square, Some)));
+    (
+        preceded(tag(" "), turn),
+        preceded(tag(" "), castling),
+        preceded(tag(" "), en_passant),
+        opt(preceded(tag(" "), parse_u32)),
+    )
+        .parse(input)
+}
+
+fn parse_pieces(input: &str) -> IResult<&str, Vec<Vec<Option<Piece>>>> {
+    verify(separated_list1(tag("/"), parse_rank), |ranks: &Vec<_>| {
+        ranks.len() == 8
+    })
+    .parse(input)
+}
+
+// The squares of one rank, which has to add up to 8
+fn parse_rank(input: &str) -> IResult<&str, Vec<Option<Piece>>> {
+    let squares = alt((
+        map(parse_empty, |empty| vec![None; empty as usize]),
+        map(parse_piece, |piece| vec![Some(piece)]),
+    ));
+    let rank = fold_many1(squares, Vec::new, |mut rank, squares| {
+        rank.extend(squares);
+        rank
+    });
+    verify(rank, |rank: &Vec<_>| rank.len() == 8).parse(input)
 }
 
 fn parse_empty(input: &str) -> IResult<&str, u32> {
     map(one_of("12345678"), |x| x.to_digit(10).unwrap()).parse(input)
-    // map(u8(), |x| x as u32).parse(input)
 }
 
-fn parse_piece(input: &str) -> IResult<&str, (Color, PieceType)> {
-    let black_king = value((Color::Black, PieceType::King), tag("k"));
-    let black_queen = value((Color::Black, PieceType::Queen), tag("q"));
-    let black_rook = value((Color::Black, PieceType::Rook), tag("r"));
-    let black_bishop = value((Color::Black, PieceType::Bishop), tag("b"));
-    let black_knight = value((Color::Black, PieceType::Knight), tag("n"));
-    let black_pawn = value((Color::Black, PieceType::Pawn), tag("p"));
-
-    let white_king = value((Color::White, PieceType::King), tag("K"));
-    let white_queen = value((Color::White, PieceType::Queen), tag("Q"));
-    let white_rook = value((Color::White, PieceType::Rook), tag("R"));
-    let white_bishop = value((Color::White, PieceType::Bishop), tag("B"));
-    let white_knight = value((Color::White, PieceType::Knight), tag("N"));
-    let white_pawn = value((Color::White, PieceType::Pawn), tag("P"));
-
-    alt((
-        black_king,
-        black_queen,
-        black_rook,
-        black_bishop,
-        black_knight,
-        black_pawn,
-        white_king,
-        white_queen,
-        white_rook,
-        white_bishop,
-        white_knight,
-        white_pawn,
-    ))
+fn parse_piece(input: &str) -> IResult<&str, Piece> {
+    map_opt(anychar, |ch| {
+        pieces::Piece::from_char(ch).map(|piece| (piece.color, piece.typ))
+    })
     .parse(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Position};
+
+    #[test]
+    fn test_from_fen() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 b - - 10 40",
+        ];
+        for fen in fens {
+            let board = BitboardGame::from_fen(fen).unwrap();
+            let mailbox = Board::new(Some(fen.into())).unwrap();
+            for idx in 0..64 {
+                let piece = mailbox[Position::from(idx)].map(|piece| (piece.color, piece.typ));
+                assert_eq!(
+                    board.get(idx as u32),
+                    piece,
+                    "{fen} at {}",
+                    Position::from(idx)
+                );
+            }
+        }
+        // and back again
+        for fen in fens {
+            let board = BitboardGame::from_fen(fen).unwrap();
+            assert!(fen.starts_with(&format!("{} ", board.fen_pieces())));
+            // all but the move number
+            assert_eq!(
+                board.fen().rsplit_once(' '),
+                fen.rsplit_once(' ').map(|(rest, _)| (rest, "1"))
+            );
+        }
+        // just the pieces will do
+        let board = BitboardGame::from_fen("8/8/8/8/8/8/8/4K3").unwrap();
+        assert_eq!(board.fen(), "8/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        let board = BitboardGame::from_fen("4k3/8/8/3pP3/8/8/8/R3K3 w Qk d6 0 3").unwrap();
+        assert_eq!(board.turn(), Color::White);
+        assert_eq!(board.castling(), WHITE_QUEENSIDE | BLACK_KINGSIDE);
+        assert_eq!(board.en_passant(), Some(43));
+    }
+
+    #[test]
+    fn test_invalid_fen() {
+        let invalid = [
+            "",
+            "8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/7 w - - 0 1",
+            "8/8/8/8/8/8/8/ppppppppp w - - 0 1",
+            "8/8/8/8/8/8/8/4x3 w - - 0 1",
+            "8/8/8/8/8/8/8/8w - - 0 1",
+            "8/8/8/8/8/8/8/80 w - - 0 1",
+            "8/8/8/8/8/8/8/8 x - - 0 1",
+            "8/8/8/8/8/8/8/8 w KX - 0 1",
+            "8/8/8/8/8/8/8/8 w - e9 0 1",
+        ];
+        for fen in invalid {
+            assert!(BitboardGame::from_fen(fen).is_err(), "{fen}");
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::sync::OnceLock;
+
+use crate::{
+    board::Position,
+    pieces::{DIAGONALS, STRAIGHTS},
+};
+
+use super::Bitboard;
+
+// Slides in each direction up to and including the first occupied square
+fn ray_attacks(square: u32, occupied: u64, steps: &[(i32, i32)]) -> u64 {
+    let from = Position::from(square as usize);
+    let mut attacks = 0;
+    for &(file, rank) in steps {
+        for to in from.iterate_offset(file, rank) {
+            let mask = 1 << (to.rank() * 8 + to.file());
+            attacks |= mask;
+            if occupied & mask != 0 {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+// The squares whose occupancy matters for a slider on the square: its rays without
+// the last square of each, as a piece there blocks nothing further
+fn relevant_mask(square: u32, steps: &[(i32, i32)]) -> u64 {
+    let from = Position::from(square as usize);
+    let mut mask = 0;
+    for &(file, rank) in steps {
+        let ray = from.iterate_offset(file, rank);
+        for to in ray.iter().take(ray.len().saturating_sub(1)) {
+            mask |= 1 << (to.rank() * 8 + to.file());
+        }
+    }
+    mask
+}
+
+// Every subset of the mask, starting with the empty one
+fn subsets(mask: u64) -> impl Iterator<Item = u64> {
+    let mut next = Some(0u64);
+    std::iter::from_fn(move || {
+        let subset = next?;
+        let following = subset.wrapping_sub(mask) & mask;
+        next = (following != 0).then_some(following);
+        Some(subset)
+    })
+}
+
+// Multipliers that map every occupancy of a square's relevant mask to its own
+// table entry, or to one with the same attacks. Found with the generate test.
+const ROOK_MAGICS: [u64; 64] = [
+    0x4a80021040002280,
+    0x0640001000436004,
+    0xc0800a1000822000,
+    0x0280100080080004,
+    0x0100020408001100,
+    0x0a00010810840200,
+    0x04000102083000c4,
+    0x4080002880104100,
+    0x2405002443028000,
+    0x0000802000804000,
+    0x0004802002801000,
+    0x8602002040081200,
+    0x1006000846001020,
+    0xc801000401000208,
+    0x08030001000c0a00,
+    0x4030800041000080,
+    0x0000218000804000,
+    0x0030004000402004,
+    0x20d0008080102000,
+    0x1200808010000800,
+    0x60a1010008001004,
+    0x0018808004000200,
+    0x0010040010424801,
+    0x0088020001204094,
+    0x001140048004b081,
+    0x0800200480400080,
+    0x2c90001080802000,
+    0x0c00090100201000,
+    0x0002002200040810,
+    0x4104020080800400,
+    0x8000080400021081,
+    0x0c40004200040081,
+    0x8080804010800020,
+    0x0000201002400042,
+    0x0240100080802002,
+    0x0080801002800800,
+    0x1000080080800402,
+    0x0014020080800400,
+    0x0012000802000104,
+    0x0000802040800100,
+    0x020020804000800b,
+    0x0008201000454000,
+    0x0820200100410014,
+    0x0000081042020020,
+    0x8218000804008080,
+    0x2001000400090022,
+    0x00a2040200010100,
+    0x0024088410620001,
+    0x2c05042480044100,
+    0x00e0004000822280,
+    0x80810c2000104100,
+    0x4000801000080080,
+    0x2d00210040801002,
+    0x0002010408100200,
+    0x4000088102104400,
+    0x08c0284400812a00,
+    0x0030430080062011,
+    0x0040110048220082,
+    0x0a04200100100841,
+    0x0201002204081001,
+    0x0102001020080402,
+    0x00a1000208040001,
+    0x0a00500801009204,
+    0x0010004080241102,
+];
+const BISHOP_MAGICS: [u64; 64] = [
+    0x0008084848002020,
+    0x0410500a04803100,
+    0x800820810602a421,
+    0x02244040820800c4,
+    0x08040421c0808203,
+    0x2000903008001004,
+    0x300a451028e00800,
+    0x0206044104100288,
+    0x2007100242041c08,
+    0x8801200109521080,
+    0x0400841802204100,
+    0x8000912402800027,
+    0x00468e0210400050,
+    0x0000110421241021,
+    0x0d002a00d00c5041,
+    0x0204868480901086,
+    0x042020400881054c,
+    0x0c100108010808b0,
+    0x0001000812040111,
+    0x1004114804208800,
+    0x4001000820080408,
+    0x000a00050304822a,
+    0x0100400104026020,
+    0x6201048022861030,
+    0x2308840020200282,
+    0x8402100002840800,
+    0x0004020011060400,
+    0x0004040088020908,
+    0x040204004a008202,
+    0x0001010002004108,
+    0x040401030080b088,
+    0x0104430100411820,
+    0x1001082001c00554,
+    0x0138480880140919,
+    0x1040842080100082,
+    0x0203400808008200,
+    0x400c200200422080,
+    0x24100100440a0048,
+    0x003004990000ac02,
+    0x00084211c0c22500,
+    0x0008041005200840,
+    0xb0820082a0008800,
+    0x80880a0802000404,
+    0x41c0006124018802,
+    0x0000080104020041,
+    0x0122120042040100,
+    0x0122081860804906,
+    0x0410010100280100,
+    0x0902881910102020,
+    0x4000440441080224,
+    0x0080604a00904802,
+    0x4100000a05040024,
+    0x4800100890240a00,
+    0x0100200202020828,
+    0x061410c602042100,
+    0x2420820202002040,
+    0x0032008448280c33,
+    0xd000020041080901,
+    0x0208400040541041,
+    0x8400100080840400,
+    0x40008000d0021204,
+    0x0000002890118600,
+    0x0000410801243088,
+    0x08409002020929a4,
+];
+
+// Where the attacks of one square are in the table
+#[derive(Debug, Copy, Clone, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+// The attacks of a slider for every square and relevant occupancy
+struct MagicTable {
+    magics: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+impl MagicTable {
+    fn new(steps: &[(i32, i32)], magics: &[u64; 64]) -> Self {
+        let mut table = MagicTable {
+            magics: [Magic::default(); 64],
+            attacks: Vec::new(),
+        };
+        for square in 0..64 {
+            let mask = relevant_mask(square, steps);
+            let bits = mask.count_ones();
+            let magic = Magic {
+                mask,
+                magic: magics[square as usize],
+                shift: 64 - bits,
+                offset: table.attacks.len(),
+            };
+            table.attacks.resize(magic.offset + (1 << bits), 0);
+            for occupied in subsets(mask) {
+                table.attacks[magic.index(occupied)] = ray_attacks(square, occupied, steps);
+            }
+            table.magics[square as usize] = magic;
+        }
+        table
+    }
+
+    fn attacks(&self, square: u32, occupied: u64) -> u64 {
+        self.attacks[self.magics[square as usize].index(occupied)]
+    }
+}
+
+// Built on first use, which takes a moment for the rooks
+fn rook_table() -> &'static MagicTable {
+    static TABLE: OnceLock<MagicTable> = OnceLock::new();
+    TABLE.get_or_init(|| MagicTable::new(&STRAIGHTS, &ROOK_MAGICS))
+}
+
+fn bishop_table() -> &'static MagicTable {
+    static TABLE: OnceLock<MagicTable> = OnceLock::new();
+    TABLE.get_or_init(|| MagicTable::new(&DIAGONALS, &BISHOP_MAGICS))
+}
+
+// The squares a rook on the square attacks with the given squares occupied, up to
+// and including the first piece in each direction
+pub(super) fn rook_attacks(square: u32, occupied: u64) -> Bitboard {
+    Bitboard(rook_table().attacks(square, occupied))
+}
+
+pub(super) fn bishop_attacks(square: u32, occupied: u64) -> Bitboard {
+    Bitboard(bishop_table().attacks(square, occupied))
+}
+
+pub(super) fn queen_attacks(square: u32, occupied: u64) -> Bitboard {
+    Bitboard(rook_attacks(square, occupied).0 | bishop_attacks(square, occupied).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn find_magic(square: u32, steps: &[(i32, i32)], rng: &mut StdRng) -> u64 {
+        let mask = relevant_mask(square, steps);
+        let bits = mask.count_ones();
+        let entries: Vec<(u64, u64)> = subsets(mask)
+            .map(|occupied| (occupied, ray_attacks(square, occupied, steps)))
+            .collect();
+        'search: loop {
+            let magic = rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>();
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+            let mut table = vec![None; 1 << bits];
+            for &(occupied, attacks) in &entries {
+                let idx = (occupied.wrapping_mul(magic) >> (64 - bits)) as usize;
+                match table[idx] {
+                    None => table[idx] = Some(attacks),
+                    Some(known) if known == attacks => {}
+                    Some(_) => continue 'search,
+                }
+            }
+            return magic;
+        }
+    }
+
+    // Looks for new magics, run with cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn generate() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (name, steps) in [("ROOK", &STRAIGHTS), ("BISHOP", &DIAGONALS)] {
+            let magics: Vec<String> = (0..64)
+                .map(|square| format!("0x{:016x}", find_magic(square, steps, &mut rng)))
+                .collect();
+            println!("{name}: {}", magics.join(", "));
+        }
+    }
+
+    #[test]
+    fn test_subsets() {
+        let subsets: Vec<u64> = subsets(0b1010).collect();
+        assert_eq!(subsets, [0b0000, 0b0010, 0b1000, 0b1010]);
+        assert_eq!(super::subsets(0).count(), 1);
+    }
+
+    #[test]
+    fn test_relevant_mask() {
+        // a rook in the corner: the edges up to the last square
+        assert_eq!(relevant_mask(0, &STRAIGHTS), 0x0001_0101_0101_017e);
+        // a bishop in the center leaves out the edge of the board
+        assert_eq!(relevant_mask(27, &DIAGONALS).count_ones(), 9);
+    }
+
+    #[test]
+    fn test_sliding_attacks() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..3000 {
+            // from nearly empty to nearly full boards
+            let occupied = match rng.gen_range(0..3) {
+                0 => rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>(),
+                1 => rng.gen::<u64>(),
+                _ => rng.gen::<u64>() | rng.gen::<u64>(),
+            };
+            for square in 0..64 {
+                let rook = ray_attacks(square, occupied, &STRAIGHTS);
+                let bishop = ray_attacks(square, occupied, &DIAGONALS);
+                assert_eq!(rook_attacks(square, occupied).0, rook);
+                assert_eq!(bishop_attacks(square, occupied).0, bishop);
+                assert_eq!(queen_attacks(square, occupied).0, rook | bishop);
+            }
+        }
+        // the square itself does not block
+        assert_eq!(rook_attacks(0, u64::MAX).0, 0b10 | 1 << 8);
+        assert_eq!(bishop_attacks(0, 0).0, 0x8040_2010_0804_0200);
+    }
+}
@@ -2,4 +2,10 @@ mod bitboard;
 
 pub use bitboard::*;
 
+mod attacks;
+mod convert;
+mod eval;
 mod fen;
+mod magic;
+mod moves;
+mod pawns;
@@ -0,0 +1,486 @@
+use crate::{
+    board::Position,
+    pieces::{Color, Move, PieceType, SpecialMove},
+};
+
+use super::{
+    magic::{bishop_attacks, queen_attacks, rook_attacks},
+    Bitboard, BitboardGame, Piece, BLACK_KINGSIDE, BLACK_QUEENSIDE, WHITE_KINGSIDE,
+    WHITE_QUEENSIDE,
+};
+
+// For each castling right: the king's start and end square, the rook's start and end
+// square, and the squares that have to be empty
+const CASTLING: [(u8, u32, u32, u32, u32, u64); 4] = [
+    (WHITE_KINGSIDE, 4, 6, 7, 5, 0x60),
+    (WHITE_QUEENSIDE, 4, 2, 0, 3, 0x0e),
+    (BLACK_KINGSIDE, 60, 62, 63, 61, 0x60 << 56),
+    (BLACK_QUEENSIDE, 60, 58, 56, 59, 0x0e << 56),
+];
+
+// The rights that are gone once a piece moves from or to the square
+const fn rights_lost() -> [u8; 64] {
+    let mut table = [0; 64];
+    table[4] = WHITE_KINGSIDE | WHITE_QUEENSIDE;
+    table[7] = WHITE_KINGSIDE;
+    table[0] = WHITE_QUEENSIDE;
+    table[60] = BLACK_KINGSIDE | BLACK_QUEENSIDE;
+    table[63] = BLACK_KINGSIDE;
+    table[56] = BLACK_QUEENSIDE;
+    table
+}
+
+const RIGHTS_LOST: [u8; 64] = rights_lost();
+
+// What make_move changes besides the moving pieces, for unmake_move to put back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling: u8,
+    en_passant: Option<u32>,
+    halfmoves: u32,
+}
+
+fn index(position: Position) -> u32 {
+    (position.rank() * 8 + position.file()) as u32
+}
+
+fn square(idx: u32) -> Position {
+    Position::from(idx as usize)
+}
+
+// The squares strictly between two squares on a rank, file or diagonal, none when
+// they are not on one
+fn between(a: u32, b: u32) -> u64 {
+    if rook_attacks(a, 0).contains(b) {
+        (rook_attacks(a, 1 << b) & rook_attacks(b, 1 << a)).0
+    } else if bishop_attacks(a, 0).contains(b) {
+        (bishop_attacks(a, 1 << b) & bishop_attacks(b, 1 << a)).0
+    } else {
+        0
+    }
+}
+
+impl BitboardGame {
+    // The bishop, rook and queen moves of the given color, without checking whether
+    // they leave the king in check
+    pub fn slider_moves(&self, color: Color) -> Vec<Move> {
+        let own = self.occupancy(color);
+        let occupied = self.occupancy_all();
+        let sliders = [
+            (
+                PieceType::Bishop,
+                bishop_attacks as fn(u32, u64) -> Bitboard,
+            ),
+            (PieceType::Rook, rook_attacks),
+            (PieceType::Queen, queen_attacks),
+        ];
+        let mut moves = Vec::new();
+        for (typ, attacks) in sliders {
+            for from in Bitboard(self.pieces((color, typ))) {
+                for to in Bitboard(attacks(from, occupied).0 & !own) {
+                    moves.push(Move {
+                        from: square(from),
+                        to: square(to),
+                        special: self
+                            .get(to)
+                            .map(|(_, typ)| SpecialMove::Capture(typ, square(to))),
+                    });
+                }
+            }
+        }
+        moves
+    }
+
+    // The castling moves of the given color, where the right is left, the squares
+    // between are empty and the king does not pass through check
+    pub fn castling_moves(&self, color: Color) -> Vec<Move> {
+        let occupied = self.occupancy_all();
+        CASTLING
+            .iter()
+            .filter(|&&(right, king_from, king_to, _, _, between)| {
+                let (low, high) = (king_from.min(king_to), king_from.max(king_to));
+                self.castling() & right != 0
+                    && self.get(king_from) == Some((color, PieceType::King))
+                    && occupied & between == 0
+                    && (low..=high).all(|idx| !self.is_attacked(idx, !color))
+            })
+            .map(|&(_, king_from, king_to, rook_from, rook_to, _)| Move {
+                from: square(king_from),
+                to: square(king_to),
+                special: Some(SpecialMove::Castling(square(rook_from), square(rook_to))),
+            })
+            .collect()
+    }
+
+    // All moves of the side to move, including those that leave its king in check
+    pub fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let color = self.turn();
+        let mut moves = self.pawn_moves(color);
+        moves.extend(self.leaper_moves(color));
+        moves.extend(self.slider_moves(color));
+        moves.extend(self.castling_moves(color));
+        moves
+    }
+
+    // The moves of the side to move that do not leave its king in check. Other than
+    // the king, a piece has to stay on the line of a pin and, in check, capture the
+    // checker or step in its way.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let color = self.turn();
+        let Some(king) = Bitboard(self.pieces((color, PieceType::King)))
+            .into_iter()
+            .next()
+        else {
+            return self.pseudo_legal_moves();
+        };
+        let occupied = self.occupancy_all();
+        let checkers = self.attackers(king, !color, occupied);
+        let check_mask = match checkers.count_ones() {
+            0 => u64::MAX,
+            1 => checkers | between(king, checkers.trailing_zeros()),
+            _ => 0, // only the king can get out of a double check
+        };
+        let pins = self.pin_masks(king, color);
+        // the king does not block the slider that checks it from the square behind
+        let without_king = occupied & !(1 << king);
+
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|mv| {
+                let (from, to) = (index(mv.from), index(mv.to));
+                match mv.special {
+                    // castling_moves already keeps the king out of check
+                    Some(SpecialMove::Castling(_, _)) => true,
+                    _ if from == king => self.attackers(to, !color, without_king) == 0,
+                    Some(SpecialMove::EnPassant(captured)) => {
+                        self.en_passant_is_safe(from, to, index(captured), king)
+                    }
+                    _ => check_mask & pins[from as usize] & (1 << to) != 0,
+                }
+            })
+            .collect()
+    }
+
+    // For every square, where a piece on it may go without exposing the king to a
+    // bishop, rook or queen: anywhere, unless the piece is pinned
+    fn pin_masks(&self, king: u32, color: Color) -> [u64; 64] {
+        let enemies = |typ| self.pieces((!color, typ));
+        let queens = enemies(PieceType::Queen);
+        // the enemy sliders on a line with the king, seen through the own pieces
+        let snipers = rook_attacks(king, self.occupancy(!color)).0
+            & (enemies(PieceType::Rook) | queens)
+            | bishop_attacks(king, self.occupancy(!color)).0
+                & (enemies(PieceType::Bishop) | queens);
+
+        let mut masks = [u64::MAX; 64];
+        for sniper in Bitboard(snipers) {
+            let ray = Bitboard(between(king, sniper));
+            let blockers = ray.intersection(Bitboard(self.occupancy_all()));
+            let own = blockers.intersection(Bitboard(self.occupancy(color)));
+            if blockers.count() == 1 && !own.is_empty() {
+                masks[blockers.0.trailing_zeros() as usize] = ray.union(Bitboard(1 << sniper)).0;
+            }
+        }
+        masks
+    }
+
+    // Whether the king is safe after taking en passant. Two pawns leave their
+    // squares at once, which can open a rank the pin masks do not cover.
+    fn en_passant_is_safe(&self, from: u32, to: u32, captured: u32, king: u32) -> bool {
+        let occupied = self.occupancy_all() & !(1 << from | 1 << captured) | 1 << to;
+        self.attackers(king, !self.turn(), occupied) & !(1 << captured) == 0
+    }
+
+    pub fn make_move(&mut self, mv: &Move) -> Undo {
+        let (from, to) = (index(mv.from), index(mv.to));
+        let undo = Undo {
+            captured: None,
+            castling: self.castling(),
+            en_passant: self.en_passant(),
+            halfmoves: self.halfmoves(),
+        };
+        let Some(piece) = self.remove(from) else {
+            return undo;
+        };
+        let captured_at = mv.captured().map_or(to, |(_, pos)| index(pos));
+        let captured = self.remove(captured_at);
+
+        match mv.special {
+            Some(SpecialMove::Promotion(typ, _)) => {
+                self.set(to, (piece.0, typ));
+            }
+            Some(SpecialMove::Castling(rook_from, rook_to)) => {
+                self.set(to, piece);
+                if let Some(rook) = self.remove(index(rook_from)) {
+                    self.set(index(rook_to), rook);
+                }
+            }
+            _ => {
+                self.set(to, piece);
+            }
+        }
+
+        self.set_castling(undo.castling & !(RIGHTS_LOST[from as usize] | RIGHTS_LOST[to as usize]));
+        self.set_en_passant(
+            (piece.1 == PieceType::Pawn && from.abs_diff(to) == 16).then_some((from + to) / 2),
+        );
+        if piece.1 == PieceType::Pawn || captured.is_some() {
+            self.set_halfmoves(0);
+        } else {
+            self.set_halfmoves(undo.halfmoves + 1);
+        }
+        self.set_turn(!self.turn());
+        Undo { captured, ..undo }
+    }
+
+    // Takes back a move made with make_move, given what it returned
+    pub fn unmake_move(&mut self, mv: &Move, undo: Undo) {
+        let (from, to) = (index(mv.from), index(mv.to));
+        self.set_turn(!self.turn());
+        self.set_castling(undo.castling);
+        self.set_en_passant(undo.en_passant);
+        self.set_halfmoves(undo.halfmoves);
+
+        let Some(piece) = self.remove(to) else {
+            return;
+        };
+        match mv.special {
+            Some(SpecialMove::Promotion(_, _)) => {
+                self.set(from, (piece.0, PieceType::Pawn));
+            }
+            Some(SpecialMove::Castling(rook_from, rook_to)) => {
+                self.set(from, piece);
+                if let Some(rook) = self.remove(index(rook_to)) {
+                    self.set(index(rook_from), rook);
+                }
+            }
+            _ => {
+                self.set(from, piece);
+            }
+        }
+        if let Some(captured) = undo.captured {
+            let captured_at = mv.captured().map_or(to, |(_, pos)| index(pos));
+            self.set(captured_at, captured);
+        }
+    }
+
+    // Counts the move sequences of the given length from this position, like
+    // Board::perft but with make and unmake instead of copies
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|mv| {
+                let undo = self.make_move(mv);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move(mv, undo);
+                nodes
+            })
+            .sum()
+    }
+
+    // The perft numbers split up by the first move
+    pub fn perft_divide(&mut self, depth: usize) -> Vec<(Move, u64)> {
+        self.legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let undo = self.make_move(&mv);
+                let nodes = self.perft(depth.saturating_sub(1));
+                self.unmake_move(&mv, undo);
+                (mv, nodes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::board::Board;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn perft(fen: &str, depth: usize) -> u64 {
+        BitboardGame::from_fen(fen).unwrap().perft(depth)
+    }
+
+    #[test]
+    fn test_perft() {
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8902), (4, 197281)] {
+            assert_eq!(
+                perft(START, depth),
+                nodes,
+                "start position at depth {depth}"
+            );
+        }
+        let board = Board::new(None).unwrap();
+        assert_eq!(perft(START, 3), board.perft(3));
+
+        for (depth, nodes) in [(1, 48), (2, 2039), (3, 97862)] {
+            assert_eq!(perft(KIWIPETE, depth), nodes, "Kiwipete at depth {depth}");
+        }
+        // checks, pins and en passant along the rank of the king
+        let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        for (depth, nodes) in [(1, 14), (2, 191), (3, 2812), (4, 43238)] {
+            assert_eq!(perft(fen, depth), nodes, "{fen} at depth {depth}");
+        }
+    }
+
+    #[test]
+    fn test_perft_like_mailbox() {
+        // the mailbox castles out of and through check, so it only gets the numbers
+        // right where castling does not come up that way
+        let fens = [
+            START,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/b7/8/3n4/4P3/4K3 w - - 0 1",
+            "4k3/4r3/8/8/1b6/8/3NB3/4K3 w - - 0 1",
+        ];
+        for fen in fens {
+            let board = Board::new(Some(fen.into())).unwrap();
+            assert_eq!(perft(fen, 3), board.perft(3), "{fen}");
+        }
+    }
+
+    // The moves as the mailbox would generate them, sorted to compare
+    fn sorted(moves: impl IntoIterator<Item = Move>) -> Vec<String> {
+        let mut moves: Vec<String> = moves.into_iter().map(|mv| format!("{mv:?}")).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn test_legal_moves_like_mailbox() {
+        let castling = |mv: &Move| matches!(mv.special, Some(SpecialMove::Castling(_, _)));
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..30 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..80 {
+                let game = BitboardGame::from(&board);
+                let fen = board.get_fen();
+                let (ours, our_castling): (Vec<Move>, Vec<Move>) =
+                    game.legal_moves().into_iter().partition(|mv| !castling(mv));
+                let mailbox = board.legal_moves(board.current_turn());
+                let (theirs, their_castling): (Vec<Move>, Vec<Move>) =
+                    mailbox.iter().partition(|mv| !castling(mv));
+                assert_eq!(sorted(ours), sorted(theirs), "{fen}");
+                // the mailbox lets the king castle through an attacked square
+                assert!(
+                    our_castling.iter().all(|mv| their_castling.contains(mv)),
+                    "{fen}"
+                );
+
+                let Some(next) = mailbox.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(next);
+            }
+        }
+    }
+
+    // Takes a while, run with cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn test_perft_deep() {
+        assert_eq!(perft(START, 5), 4865609);
+        assert_eq!(perft(START, 6), 119060324);
+        assert_eq!(perft(KIWIPETE, 4), 4085603);
+        assert_eq!(perft(KIWIPETE, 5), 193690690);
+    }
+
+    #[test]
+    fn test_make_unmake() {
+        let fens = [
+            START,
+            KIWIPETE,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+        for fen in fens {
+            let mut game = BitboardGame::from_fen(fen).unwrap();
+            let before = game;
+            for mv in game.pseudo_legal_moves() {
+                let undo = game.make_move(&mv);
+                assert_eq!(game.turn(), !before.turn());
+                game.unmake_move(&mv, undo);
+                assert_eq!(game, before, "{fen} after {mv:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_make_unmake_random() {
+        // the mailbox moves of random games, made on the converted boards
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..10 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..60 {
+                let moves = board.legal_moves(board.current_turn());
+                let Some(&next) = moves.choose(&mut rng) else {
+                    break;
+                };
+                let mut game = BitboardGame::from(&board);
+                let before = game;
+                for mv in moves {
+                    let undo = game.make_move(&mv);
+                    let after = board.apply(&mv);
+                    assert_eq!(game.mismatches(&after), [], "{} {mv:?}", board.get_fen());
+                    game.unmake_move(&mv, undo);
+                    assert_eq!(game, before, "{} {mv:?}", board.get_fen());
+                }
+                board = board.apply(&next);
+            }
+        }
+    }
+
+    #[test]
+    fn test_halfmoves() {
+        let mut game = BitboardGame::from_fen("4k3/8/8/8/8/8/4P3/4K1N1 w - - 7 30").unwrap();
+        let knight = Move {
+            from: square(6),
+            to: square(21),
+            special: None,
+        };
+        let undo = game.make_move(&knight);
+        assert_eq!(game.halfmoves(), 8);
+        game.unmake_move(&knight, undo);
+        assert_eq!(game.halfmoves(), 7);
+
+        let pawn = Move {
+            from: square(12),
+            to: square(28),
+            special: None,
+        };
+        game.make_move(&pawn);
+        assert_eq!(game.halfmoves(), 0);
+        assert_eq!(game.en_passant(), Some(20));
+    }
+
+    #[test]
+    fn test_castling() {
+        let mut game = BitboardGame::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(game.castling_moves(Color::White).len(), 2);
+        let castle = game
+            .castling_moves(Color::White)
+            .into_iter()
+            .find(|mv| mv.to == Position::from(6))
+            .unwrap();
+        game.make_move(&castle);
+        assert_eq!(game.get(5), Some((Color::White, PieceType::Rook)));
+        assert_eq!(game.castling(), BLACK_KINGSIDE | BLACK_QUEENSIDE);
+
+        // not through an attacked square, and not with a piece in between
+        let game = BitboardGame::from_fen("r3k2r/8/8/8/8/8/5r2/RN2K2R w KQ - 0 1").unwrap();
+        assert_eq!(game.castling_moves(Color::White), []);
+    }
+}
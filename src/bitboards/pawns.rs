@@ -0,0 +1,255 @@
+use crate::{
+    board::Position,
+    pieces::{Color, Move, PieceType, SpecialMove, PROMOTIONS},
+};
+
+use super::{Bitboard, BitboardGame};
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+const RANK_1: u64 = 0xff;
+const RANK_3: u64 = RANK_1 << 16;
+const RANK_6: u64 = RANK_1 << 40;
+const RANK_8: u64 = RANK_1 << 56;
+
+// Moves every bit by the given number of squares, up the board when positive
+fn shift(bitboard: u64, by: i32) -> u64 {
+    if by > 0 {
+        bitboard << by
+    } else {
+        bitboard >> -by
+    }
+}
+
+// The squares the pawns of the given color attack
+pub(super) fn pawn_attacks(pawns: u64, color: Color) -> u64 {
+    let up = match color {
+        Color::White => 8,
+        Color::Black => -8,
+    };
+    shift(pawns & !FILE_A, up - 1) | shift(pawns & !FILE_H, up + 1)
+}
+
+impl BitboardGame {
+    // The pawn moves of the given color, without checking whether they leave the
+    // king in check. A pawn reaching the last rank can become any of the four pieces.
+    pub fn pawn_moves(&self, color: Color) -> Vec<Move> {
+        let pawns = self.pieces((color, PieceType::Pawn));
+        let empty = !self.occupancy_all();
+        let enemies = self.occupancy(!color);
+        // where a single step from the start lands, the rank the opponent's pawns
+        // skip with a double step, and where pawns promote
+        let (up, double_rank, en_passant_rank, last_rank) = match color {
+            Color::White => (8, RANK_3, RANK_6, RANK_8),
+            Color::Black => (-8, RANK_6, RANK_3, RANK_1),
+        };
+        // to the a-file side and to the h-file side, leaving out the pawns that
+        // would wrap around to the other edge of the board
+        let captures = [(up - 1, pawns & !FILE_A), (up + 1, pawns & !FILE_H)];
+
+        let mut moves = Vec::new();
+        let single = shift(pawns, up) & empty;
+        let double = shift(single & double_rank, up) & empty;
+        for (targets, offset) in [(single, up), (double, 2 * up)] {
+            for to in Bitboard(targets) {
+                self.push_pawn_move(&mut moves, (to as i32 - offset) as u32, to, last_rank);
+            }
+        }
+        for (offset, pawns) in captures {
+            for to in Bitboard(shift(pawns, offset) & enemies) {
+                self.push_pawn_move(&mut moves, (to as i32 - offset) as u32, to, last_rank);
+            }
+        }
+
+        // the square is only for the opponent of the pawn that skipped it
+        let en_passant = self
+            .en_passant()
+            .filter(|target| en_passant_rank & (1 << target) != 0);
+        if let Some(target) = en_passant {
+            for (offset, pawns) in captures {
+                if shift(pawns, offset) & (1 << target) != 0 {
+                    moves.push(Move {
+                        from: square((target as i32 - offset) as u32),
+                        to: square(target),
+                        special: Some(SpecialMove::EnPassant(square((target as i32 - up) as u32))),
+                    });
+                }
+            }
+        }
+        moves
+    }
+
+    // The pawns of the given color with no enemy pawn ahead of them on their own
+    // file or the files next to it, like Board::is_passed_pawn
+    pub fn passed_pawns(&self, color: Color) -> u64 {
+        let enemies = self.pieces((!color, PieceType::Pawn));
+        let mut passed = 0;
+        for pawn in Bitboard(self.pieces((color, PieceType::Pawn))) {
+            let (file, rank) = (pawn % 8, pawn / 8);
+            // on the edge, the file next to it is the own file again
+            let files = (FILE_A << file)
+                | (FILE_A << file.saturating_sub(1))
+                | (FILE_A << (file + 1).min(7));
+            let ahead = match color {
+                Color::White => (!0u64).checked_shl(8 * (rank + 1)).unwrap_or(0),
+                Color::Black => (1u64 << (8 * rank)) - 1,
+            };
+            if enemies & files & ahead == 0 {
+                passed |= 1 << pawn;
+            }
+        }
+        passed
+    }
+
+    fn push_pawn_move(&self, moves: &mut Vec<Move>, from: u32, to: u32, last_rank: u64) {
+        let (from, to_square) = (square(from), square(to));
+        if last_rank & (1 << to) != 0 {
+            let captured = self.get(to).map(|(_, typ)| typ);
+            moves.extend(PROMOTIONS.map(|typ| Move {
+                from,
+                to: to_square,
+                special: Some(SpecialMove::Promotion(typ, captured)),
+            }));
+            return;
+        }
+        moves.push(Move {
+            from,
+            to: to_square,
+            special: self
+                .get(to)
+                .map(|(_, typ)| SpecialMove::Capture(typ, to_square)),
+        });
+    }
+}
+
+fn square(idx: u32) -> Position {
+    Position::from(idx as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::board::Board;
+
+    fn mailbox_moves(board: &Board, color: Color) -> Vec<Move> {
+        board
+            .get_pieces(color)
+            .into_iter()
+            .filter(|(_, piece)| piece.typ == PieceType::Pawn)
+            .flat_map(|(pos, piece)| piece.get_moves(board, &pos))
+            .collect()
+    }
+
+    fn bitboard_moves(board: &Board, color: Color) -> Vec<Move> {
+        BitboardGame::from(board).pawn_moves(color)
+    }
+
+    fn check_moves(board: &Board) {
+        for color in [Color::White, Color::Black] {
+            let mut expected = mailbox_moves(board, color);
+            let mut moves = bitboard_moves(board, color);
+            expected.sort_by_key(|mv| format!("{mv:?}"));
+            moves.sort_by_key(|mv| format!("{mv:?}"));
+            assert_eq!(moves, expected, "{:?} in {}", color, board.get_fen());
+        }
+    }
+
+    #[test]
+    fn test_pawn_moves() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "1n2k3/P1P4P/8/p6p/P6P/8/p1p4p/1N2K3 w - - 0 1",
+        ];
+        for fen in fens {
+            check_moves(&Board::new(Some(fen.into())).unwrap());
+        }
+
+        // and in random games, which also have en passant now and then
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..20 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..80 {
+                check_moves(&board);
+                let moves = board.legal_moves(board.current_turn());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..80 {
+                let game = BitboardGame::from(&board);
+                for color in [Color::White, Color::Black] {
+                    let passed: Vec<Position> = Bitboard(game.passed_pawns(color))
+                        .into_iter()
+                        .map(square)
+                        .collect();
+                    assert_eq!(passed, board.passed_pawns(color), "{}", board.get_fen());
+                }
+                let moves = board.legal_moves(board.current_turn());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edges() {
+        // pawns on the edge files do not capture around the board
+        let game = BitboardGame::from_fen("4k3/8/8/p6p/P6P/8/8/4K3 w - - 0 1").unwrap();
+        assert!(game.pawn_moves(Color::White).is_empty());
+        assert!(game.pawn_moves(Color::Black).is_empty());
+        let game = BitboardGame::from_fen("4k3/8/8/7p/P7/8/8/4K3 w - - 0 1").unwrap();
+        let moves: Vec<String> = game
+            .pawn_moves(Color::White)
+            .iter()
+            .map(|mv| mv.to.to_string())
+            .collect();
+        assert_eq!(moves, ["a5"]);
+
+        // every promotion, also when capturing
+        let game = BitboardGame::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves = game.pawn_moves(Color::White);
+        assert_eq!(moves.len(), 8);
+        for typ in PROMOTIONS {
+            for (to, captured) in [(b"a8", None), (b"b8", Some(PieceType::Knight))] {
+                let promotion = Move {
+                    from: Position::from(b"a7"),
+                    to: Position::from(to),
+                    special: Some(SpecialMove::Promotion(typ, captured)),
+                };
+                assert!(moves.contains(&promotion), "{promotion:?}");
+            }
+        }
+
+        // en passant on the edge of the board, only from the one side
+        let board = Board::new(Some("4k3/p7/8/1P6/8/8/8/4K3 b - - 0 1".into()))
+            .unwrap()
+            .apply_san("a5")
+            .unwrap();
+        let game = BitboardGame::from(&board);
+        assert_eq!(game.en_passant(), Some(40)); // a6
+        let moves = game.pawn_moves(Color::White);
+        assert_eq!(moves.len(), 2);
+        assert!(moves.contains(&Move {
+            from: Position::from(b"b5"),
+            to: Position::from(b"a6"),
+            special: Some(SpecialMove::EnPassant(Position::from(b"a5"))),
+        }));
+    }
+}
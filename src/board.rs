@@ -4,8 +4,9 @@ use std::{
 };
 
 use anyhow::{bail, Context};
+use thiserror::Error;
 
-use crate::pieces::{Color, Move, Piece, PieceType, SpecialMove};
+use crate::pieces::{Color, Move, PawnInfo, Piece, PieceType, SpecialMove};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Position(usize);
@@ -90,6 +91,19 @@ impl Position {
         Some((rank, file).into())
     }
 
+    // Chebyshev distance, the number of king moves between the squares
+    pub fn distance(&self, other: &Position) -> usize {
+        self.rank()
+            .abs_diff(other.rank())
+            .max(self.file().abs_diff(other.file()))
+    }
+
+    // Chebyshev distance to the four center squares, 0 in the center and 3 on the edge
+    pub fn center_distance(&self) -> usize {
+        let from_center = |x: usize| if x < 4 { 3 - x } else { x - 4 };
+        from_center(self.rank()).max(from_center(self.file()))
+    }
+
     pub fn iterate_offset(&self, file_offset: i32, rank_offset: i32) -> Vec<Position> {
         // can be at most 7 steps in any direction
         (1..8)
@@ -110,79 +124,205 @@ impl Display for Position {
 
 pub type Squares = [Option<Piece>; 64];
 
+// Random keys for Zobrist hashing, one per piece and square, one for the side to
+// move, one per file for en passant and one per castling right
+const ZOBRIST_KEYS: [u64; 12 * 64 + 1 + 8 + 4] = {
+    // splitmix64, with a fixed seed so that keys are the same across runs
+    let mut keys = [0; 12 * 64 + 1 + 8 + 4];
+    let mut state: u64 = 0x5eed;
+    let mut i = 0;
+    while i < keys.len() {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+};
+pub(crate) const ZOBRIST_BLACK_TO_MOVE: u64 = ZOBRIST_KEYS[12 * 64];
+const ZOBRIST_EN_PASSANT: usize = 12 * 64 + 1; // the key for the a-file
+const ZOBRIST_CASTLING: usize = 12 * 64 + 1 + 8; // kingside then queenside, white first
+
+pub(crate) fn zobrist_key(position: Position, piece: &Piece) -> u64 {
+    let piece_idx = piece.color as usize * 6 + piece.typ as usize;
+    ZOBRIST_KEYS[piece_idx * 64 + position.0]
+}
+
+// The key for a pawn that can be taken en passant on the file
+pub(crate) fn zobrist_en_passant(file: usize) -> u64 {
+    ZOBRIST_KEYS[ZOBRIST_EN_PASSANT + file]
+}
+
+// The key for a castling right, kingside when not queenside
+pub(crate) fn zobrist_castling(color: Color, queenside: bool) -> u64 {
+    ZOBRIST_KEYS[ZOBRIST_CASTLING + 2 * color as usize + queenside as usize]
+}
+
+// The single step from one square toward another when they share a rank, file or
+// diagonal, and whether that is a diagonal
+fn line_direction(from: Position, to: Position) -> Option<((i32, i32), bool)> {
+    let files = to.file() as i32 - from.file() as i32;
+    let ranks = to.rank() as i32 - from.rank() as i32;
+    let step = (files.signum(), ranks.signum());
+    if step == (0, 0) {
+        None
+    } else if files == 0 || ranks == 0 {
+        Some((step, false))
+    } else if files.abs() == ranks.abs() {
+        Some((step, true))
+    } else {
+        None
+    }
+}
+
+// Whether a piece of the type slides along diagonals or along ranks and files
+fn slides_along(typ: PieceType, diagonal: bool) -> bool {
+    matches!(
+        (typ, diagonal),
+        (PieceType::Queen, _) | (PieceType::Bishop, true) | (PieceType::Rook, false)
+    )
+}
+
+// The material value of a piece on the given square, pawns gain value as they advance
+pub fn piece_value(position: Position, piece: &Piece) -> i32 {
+    match piece.typ {
+        PieceType::King => 10000, // technically infinite, but this will probably suffice
+        PieceType::Queen => 900,
+        PieceType::Rook => 500,
+        PieceType::Bishop => 300,
+        PieceType::Knight => 275,
+        PieceType::Pawn => {
+            // values for white
+            const PAWN_VALUES: [i32; 8] = [0, 100, 105, 110, 125, 160, 200, 900];
+            let idx = match piece.color {
+                Color::White => position.rank(),
+                Color::Black => 7 - position.rank(),
+            };
+            PAWN_VALUES[idx]
+        }
+    }
+}
+
+// The 50-move rule counts the moves of both sides
+pub const FIFTY_MOVE_PLIES: usize = 100;
+
+// The 4 bit codes of Board::to_bytes are 6 per color in this order, then the codes
+// for kings and rooks that have moved
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+const MOVED_ROOK: u8 = 12;
+const MOVED_KING: u8 = 14;
+
+// Why a FEN string could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FenError {
+    #[error("expected 6 fields, found {0}")]
+    FieldCount(usize),
+    #[error("expected 8 ranks, found {0}")]
+    RankCount(usize),
+    #[error("rank {0} does not have 8 squares")]
+    RankLength(usize), // counted from 1, as in the notation
+    #[error("unknown piece {0}")]
+    UnknownPiece(char),
+    #[error("invalid side to move {0}")]
+    ActiveColor(String),
+    #[error("could not parse half-moves {0}")]
+    Halfmoves(String),
+    #[error("could not parse full-moves {0}")]
+    Fullmoves(String),
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Board {
     pub squares: Squares,
     pub ply: usize,
-    pub last_pawn_move: usize,
+    pub last_pawn_move: usize, // the ply after the last pawn move or capture
     pub last_move: Option<Move>,
+    hash: u64,          // Zobrist hash, updated with every change to the squares
+    material: [i32; 2], // summed piece values per color, kept up to date like the hash
+    piece_counts: [u8; 2],
+    pieces_besides_pawns: [u8; 2], // and besides the king
 }
 
 impl Board {
-    pub fn new(fen: Option<String>) -> anyhow::Result<Self> {
+    pub fn new(fen: Option<String>) -> Result<Self, FenError> {
         const STARTING_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
         let fen = fen.unwrap_or(STARTING_POSITION.into());
 
         let parts: Vec<&str> = fen.split(" ").collect();
 
         if parts.len() != 6 {
-            bail!("Incorrect number of parts");
-        }
-
-        fn sq(color: Color, typ: PieceType) -> Option<Piece> {
-            Some(Piece {
-                typ,
-                color,
-                most_recent_move: None,
-            })
+            return Err(FenError::FieldCount(parts.len()));
         }
 
         let active = match parts[1] {
             "w" => 0,
             "b" => 1,
-            _ => bail!("invalid active field"),
+            other => return Err(FenError::ActiveColor(other.into())),
+        };
+        let halfmoves: usize = parts[4]
+            .parse()
+            .map_err(|_| FenError::Halfmoves(parts[4].into()))?;
+        let fullmoves: usize = match parts[5].parse() {
+            Ok(fullmoves) if fullmoves > 0 => fullmoves,
+            _ => return Err(FenError::Fullmoves(parts[5].into())),
         };
-        let halfmoves: usize = parts[4].parse().context("could not parse half-moves")?;
-        let fullmoves: usize = parts[5].parse().context("could not parse full-moves")?;
         let ply = (fullmoves - 1) * 2 + active;
         let mut board = Board {
             squares: [None; 64],
             ply,
-            last_pawn_move: ply - halfmoves,
+            // set up positions may claim more half-moves than have been played
+            last_pawn_move: ply.saturating_sub(halfmoves),
             last_move: None,
+            hash: if active == 1 {
+                ZOBRIST_BLACK_TO_MOVE
+            } else {
+                0
+            },
+            material: [0; 2],
+            piece_counts: [0; 2],
+            pieces_besides_pawns: [0; 2],
         };
 
-        let pieces = parts[0];
-        for (rank, rank_fen) in pieces.split("/").enumerate() {
+        let ranks: Vec<&str> = parts[0].split("/").collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        for (rank, rank_fen) in ranks.into_iter().enumerate() {
             let mut file: usize = 0;
 
             let letters: Vec<&str> = rank_fen.split("").collect();
             for ch in letters {
                 if let Ok(offset) = ch.parse::<usize>() {
-                    file += offset
+                    file += offset;
+                    if file > 8 {
+                        return Err(FenError::RankLength(8 - rank));
+                    }
                 } else {
-                    let piece = match ch {
-                        "" => None,
-                        "P" => sq(Color::White, PieceType::Pawn),
-                        "N" => sq(Color::White, PieceType::Knight),
-                        "B" => sq(Color::White, PieceType::Bishop),
-                        "R" => sq(Color::White, PieceType::Rook),
-                        "Q" => sq(Color::White, PieceType::Queen),
-                        "K" => sq(Color::White, PieceType::King),
-                        "p" => sq(Color::Black, PieceType::Pawn),
-                        "n" => sq(Color::Black, PieceType::Knight),
-                        "b" => sq(Color::Black, PieceType::Bishop),
-                        "r" => sq(Color::Black, PieceType::Rook),
-                        "q" => sq(Color::Black, PieceType::Queen),
-                        "k" => sq(Color::Black, PieceType::King),
-                        _ => bail!("unknown piece"),
+                    let piece = match ch.chars().next() {
+                        None => None,
+                        Some(ch) => Some(Piece::from_char(ch).ok_or(FenError::UnknownPiece(ch))?),
                     };
                     if piece.is_some() {
-                        board[(7 - rank, file)] = piece;
+                        if file >= 8 {
+                            return Err(FenError::RankLength(8 - rank));
+                        }
+                        board.set((7 - rank, file).into(), piece);
                         file += 1;
                     }
                 }
             }
+            if file != 8 {
+                return Err(FenError::RankLength(8 - rank));
+            }
         }
 
         // todo: implement castling and enpassant
@@ -205,18 +345,305 @@ impl Board {
     }
 
     pub fn count_pieces(&self) -> (u8, u8) {
-        let mut white: u8 = 0;
-        let mut black: u8 = 0;
-        for sq in self.squares {
-            match sq {
-                Some(p) => match p.color {
-                    Color::White => white += 1,
-                    Color::Black => black += 1,
-                },
-                None => {}
+        (self.piece_counts[0], self.piece_counts[1])
+    }
+
+    pub fn king_position(&self, color: Color) -> Option<Position> {
+        self.squares
+            .iter()
+            .position(|sq| sq.is_some_and(|p| p.color == color && p.typ == PieceType::King))
+            .map(Position)
+    }
+
+    // The moves of the given color that do not leave its own king attacked
+    pub fn legal_moves(&self, color: Color) -> Vec<Move> {
+        self.check_candidates(color)
+            .into_iter()
+            .filter(|mv| !self.apply(mv).is_in_check(color))
+            .collect()
+    }
+
+    // The moves that still have to be applied to see if they are legal. In check
+    // only king moves and the moves that take the checker or block it are left.
+    fn check_candidates(&self, color: Color) -> Vec<Move> {
+        let king = self.king_position(color);
+        let answers = self.check_answers(color);
+        self.get_pieces(color)
+            .into_iter()
+            .flat_map(|(pos, piece)| piece.get_moves(self, &pos))
+            .filter(|mv| {
+                let Some(squares) = &answers else {
+                    return true;
+                };
+                Some(mv.from) == king
+                    || squares.contains(&mv.to)
+                    || mv.captured().is_some_and(|(_, pos)| squares.contains(&pos))
+            })
+            .collect()
+    }
+
+    // The squares of the pieces giving check to the king of the given color
+    pub fn checkers(&self, color: Color) -> Vec<Position> {
+        let Some(king) = self.king_position(color) else {
+            return Vec::new();
+        };
+        self.get_pieces(!color)
+            .into_iter()
+            .filter(|(from, piece)| piece.attacks(self, from).contains(&king))
+            .map(|(from, _)| from)
+            .collect()
+    }
+
+    // When in check, the squares other pieces than the king can move to or capture
+    // on to get out of it: the checker and the squares between it and the king.
+    // None when not in check, and none at all in double check.
+    fn check_answers(&self, color: Color) -> Option<Vec<Position>> {
+        let king = self.king_position(color)?;
+        match self.checkers(color)[..] {
+            [] => None,
+            [checker] => {
+                let mut squares = match line_direction(king, checker) {
+                    Some(((file, rank), _)) => king
+                        .iterate_offset(file, rank)
+                        .into_iter()
+                        .take_while(|pos| *pos != checker)
+                        .collect(),
+                    None => Vec::new(),
+                };
+                squares.push(checker);
+                Some(squares)
             }
+            _ => Some(Vec::new()),
         }
-        (white, black)
+    }
+
+    // Whether the king of the given color is attacked
+    pub fn is_in_check(&self, color: Color) -> bool {
+        self.king_position(color)
+            .is_some_and(|king| self.is_square_attacked(&king, !color))
+    }
+
+    // Whether the move puts the other king in check, worked out without applying it:
+    // either the moved piece attacks the king from where it lands, or it uncovers a
+    // slider behind it. Castling and en passant are rare enough to simply be applied.
+    pub fn gives_check(&self, mv: &Move) -> bool {
+        let Some(mut piece) = self[mv.from] else {
+            return false;
+        };
+        let Some(king) = self.king_position(!piece.color) else {
+            return false;
+        };
+        match mv.special {
+            Some(SpecialMove::Castling(_, _) | SpecialMove::EnPassant(_)) => {
+                return self.apply(mv).is_in_check(!piece.color);
+            }
+            Some(SpecialMove::Promotion(typ, _)) => piece.typ = typ,
+            _ => {}
+        }
+        if mv.to == king {
+            return false;
+        }
+
+        // the squares as they are after the move
+        let occupied = |pos: &Position| *pos == mv.to || (*pos != mv.from && self[pos].is_some());
+        let first_occupied = |start: Position, (file, rank): (i32, i32)| {
+            start.iterate_offset(file, rank).into_iter().find(occupied)
+        };
+
+        let direct = match piece.typ {
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen => line_direction(mv.to, king)
+                .is_some_and(|(step, diagonal)| {
+                    slides_along(piece.typ, diagonal) && first_occupied(mv.to, step) == Some(king)
+                }),
+            _ => piece.attacks(self, &mv.to).contains(&king),
+        };
+        if direct {
+            return true;
+        }
+
+        let Some((step, diagonal)) = line_direction(king, mv.from) else {
+            return false;
+        };
+        match first_occupied(king, step) {
+            Some(pos) if pos != mv.to => {
+                self[pos].is_some_and(|p| p.color == piece.color && slides_along(p.typ, diagonal))
+            }
+            _ => false,
+        }
+    }
+
+    // Counts the move sequences of the given length from this position, to check
+    // the move generation against known numbers
+    pub fn perft(&self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves(self.current_turn());
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves.iter().map(|mv| self.apply(mv).perft(depth - 1)).sum()
+    }
+
+    // The perft numbers split up by the first move, to narrow down where they go wrong
+    pub fn perft_divide(&self, depth: usize) -> Vec<(Move, u64)> {
+        self.legal_moves(self.current_turn())
+            .into_iter()
+            .map(|mv| (mv, self.apply(&mv).perft(depth.saturating_sub(1))))
+            .collect()
+    }
+
+    // The legal moves of the given color along with their SAN, e.g. "Nf3"
+    pub fn legal_moves_san(&self, color: Color) -> Vec<(Move, String)> {
+        self.legal_moves(color)
+            .into_iter()
+            .map(|mv| (mv, mv.san(self)))
+            .collect()
+    }
+
+    // A line of moves from this position in SAN, e.g. "Qxa8+ Kh7"
+    pub fn line_san(&self, line: &[Move]) -> String {
+        let mut board = *self;
+        let mut san = Vec::new();
+        for mv in line {
+            san.push(mv.san(&board));
+            board = board.apply(mv);
+        }
+        san.join(" ")
+    }
+
+    // Whether any piece of the given color attacks the square
+    pub fn is_square_attacked(&self, position: &Position, by: Color) -> bool {
+        self.get_pieces(by)
+            .into_iter()
+            .any(|(from, piece)| piece.attacks(self, &from).contains(position))
+    }
+
+    // Whether a pawn of the given color attacks the square
+    pub fn is_attacked_by_pawn(&self, position: &Position, by: Color) -> bool {
+        // such a pawn stands diagonally behind the square, seen from its side
+        let back = -PawnInfo::for_color(by).direction;
+        [position.offset(-1, back), position.offset(1, back)]
+            .into_iter()
+            .flatten()
+            .any(|from| self.is_occupied_by(from, Some(by), Some(PieceType::Pawn)))
+    }
+
+    // Whether a pawn of the given color attacks the square or could later by
+    // advancing, that is, one stands on a neighboring file behind the square
+    pub fn can_pawn_attack(&self, position: &Position, by: Color) -> bool {
+        let forward = PawnInfo::for_color(by).direction;
+        self.get_pieces(by).into_iter().any(|(from, piece)| {
+            piece.typ == PieceType::Pawn
+                && from.file().abs_diff(position.file()) == 1
+                && (position.rank() as i32 - from.rank() as i32) * forward > 0
+        })
+    }
+
+    // Whether the square holds a pawn that no enemy pawn can stop, that is, none
+    // stands ahead of it on its own file or a neighboring one
+    pub fn is_passed_pawn(&self, position: Position) -> bool {
+        let Some(pawn) = self[position].filter(|piece| piece.typ == PieceType::Pawn) else {
+            return false;
+        };
+        let forward = PawnInfo::for_color(pawn.color).direction;
+        !self
+            .get_pieces(!pawn.color)
+            .into_iter()
+            .any(|(from, piece)| {
+                piece.typ == PieceType::Pawn
+                    && from.file().abs_diff(position.file()) <= 1
+                    && (from.rank() as i32 - position.rank() as i32) * forward > 0
+            })
+    }
+
+    pub fn passed_pawns(&self, color: Color) -> Vec<Position> {
+        self.get_pieces(color)
+            .into_iter()
+            .filter(|(pos, _)| self.is_passed_pawn(*pos))
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    // Whether the position could arise in a game: one king per side, no pawns on
+    // the back ranks and the side that just moved not left in check
+    pub fn is_legal_position(&self) -> bool {
+        for color in [Color::White, Color::Black] {
+            let kings = self
+                .get_pieces(color)
+                .iter()
+                .filter(|(_, piece)| piece.typ == PieceType::King)
+                .count();
+            if kings != 1 {
+                return false;
+            }
+        }
+
+        let back_rank_pawn = self.squares.iter().enumerate().any(|(idx, sq)| {
+            let rank = Position(idx).rank();
+            sq.is_some_and(|p| p.typ == PieceType::Pawn) && (rank == 0 || rank == 7)
+        });
+        if back_rank_pawn {
+            return false;
+        }
+
+        let color = self.current_turn();
+        match self.king_position(!color) {
+            Some(king) => !self.is_square_attacked(&king, color),
+            None => false,
+        }
+    }
+
+    // Whether the given color lacks the material to ever deliver mate: a bare king, a
+    // single minor piece against a bare king, a knight against a knight, or only
+    // bishops when all bishops on the board stand on squares of one color and
+    // nothing else is left. Dead positions that take more than counting, such as
+    // locked pawn chains, are not recognised.
+    pub fn is_insufficient_material(&self, color: Color) -> bool {
+        let pieces = |color| -> Vec<PieceType> {
+            self.get_pieces(color)
+                .into_iter()
+                .map(|(_, piece)| piece.typ)
+                .filter(|typ| *typ != PieceType::King)
+                .collect()
+        };
+        // any other piece of the opponent can block a square next to its king
+        match (pieces(color).as_slice(), pieces(!color).as_slice()) {
+            ([], _) | ([PieceType::Knight | PieceType::Bishop], []) => return true,
+            ([PieceType::Knight], [PieceType::Knight]) => return true,
+            _ => {}
+        }
+
+        // bishops on one color can never attack the squares of the other color, and
+        // a king in the corner always has one of those to go to
+        let mut bishop_squares = Vec::new();
+        for (position, piece) in self
+            .get_pieces(Color::White)
+            .into_iter()
+            .chain(self.get_pieces(Color::Black))
+        {
+            match piece.typ {
+                PieceType::King => {}
+                PieceType::Bishop => bishop_squares.push((position.rank() + position.file()) % 2),
+                _ => return false,
+            }
+        }
+        bishop_squares.windows(2).all(|pair| pair[0] == pair[1])
+    }
+
+    // Whether neither side can mate any more, so the game is drawn
+    pub fn is_dead_position(&self) -> bool {
+        self.is_insufficient_material(Color::White) && self.is_insufficient_material(Color::Black)
+    }
+
+    // The squares whose occupant differs from the other board, with the piece here
+    // and the piece there, for debugging and tests of move application
+    pub fn diff(&self, other: &Board) -> Vec<(Position, Option<Piece>, Option<Piece>)> {
+        (0..64)
+            .map(Position)
+            .filter(|&pos| self[pos] != other[pos])
+            .map(|pos| (pos, self[pos], other[pos]))
+            .collect()
     }
 
     pub fn is_occupied_by(
@@ -261,27 +688,30 @@ impl Board {
 
         if let Some(mut piece) = res[mv.from].clone() {
             piece.most_recent_move = Some(res.ply);
-            res[mv.to] = Some(piece);
-            res[mv.from] = None;
+            if let Some((_, captured)) = mv.captured() {
+                res.set(captured, None);
+            }
+            res.set(mv.to, Some(piece));
+            res.set(mv.from, None);
 
-            if piece.typ == PieceType::Pawn {
-                // Update the 50-move counter with the current ply
-                res.last_pawn_move = self.ply
+            if piece.typ == PieceType::Pawn || mv.captured().is_some() {
+                // Restart the 50-move counter from the position after this move
+                res.last_pawn_move = self.ply + 1;
             }
 
             if let Some(special) = mv.special {
                 match special {
-                    SpecialMove::Capture(_) => {}
-                    SpecialMove::EnPassant(pos) => res[pos] = None,
-                    SpecialMove::Promotion(new_typ) => {
+                    // the captured piece has already been removed
+                    SpecialMove::Capture(_, _) | SpecialMove::EnPassant(_) => {}
+                    SpecialMove::Promotion(new_typ, _) => {
                         piece.typ = new_typ;
-                        res[mv.to] = Some(piece); // second assign is needed
+                        res.set(mv.to, Some(piece)); // second assign is needed
                     }
                     SpecialMove::Castling(rook_from, rook_to) => {
                         if let Some(mut rook) = res[rook_from].clone() {
                             rook.most_recent_move = Some(res.ply);
-                            res[rook_to] = Some(rook);
-                            res[rook_from] = None
+                            res.set(rook_to, Some(rook));
+                            res.set(rook_from, None);
                         }
                     }
                 }
@@ -289,10 +719,280 @@ impl Board {
         }
 
         res.ply += 1;
+        res.hash ^= ZOBRIST_BLACK_TO_MOVE;
+        debug_assert_eq!(res.material, res.count_material());
 
         res
     }
 
+    // Places a piece (or nothing) on the square, keeping the hash up to date
+    fn set(&mut self, position: Position, piece: Option<Piece>) {
+        if let Some(old) = self[position] {
+            self.hash ^= zobrist_key(position, &old);
+            self.material[old.color as usize] -= piece_value(position, &old);
+            self.piece_counts[old.color as usize] -= 1;
+            if !matches!(old.typ, PieceType::Pawn | PieceType::King) {
+                self.pieces_besides_pawns[old.color as usize] -= 1;
+            }
+        }
+        if let Some(new) = piece {
+            self.hash ^= zobrist_key(position, &new);
+            self.material[new.color as usize] += piece_value(position, &new);
+            self.piece_counts[new.color as usize] += 1;
+            if !matches!(new.typ, PieceType::Pawn | PieceType::King) {
+                self.pieces_besides_pawns[new.color as usize] += 1;
+            }
+        }
+        self[position] = piece;
+    }
+
+    // Whether the side has more than its king and pawns
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        self.pieces_besides_pawns[color as usize] > 0
+    }
+
+    // Whether the side to move could be in zugzwang, so that passing would not be
+    // the worst it can do and a null move tells nothing about the position
+    pub fn zugzwang_likely(&self) -> bool {
+        !self.has_non_pawn_material(self.current_turn())
+    }
+
+    // The summed value of the given side's pieces
+    pub fn material(&self, color: Color) -> i32 {
+        self.material[color as usize]
+    }
+
+    // How far the given side is ahead in material, in centipawns. Unlike an engine
+    // evaluation this depends on nothing but the pieces on the board.
+    pub fn material_balance(&self, perspective: Color) -> i32 {
+        self.material(perspective) - self.material(!perspective)
+    }
+
+    // The material for both sides computed from scratch, to check the running totals
+    fn count_material(&self) -> [i32; 2] {
+        let mut material = [0; 2];
+        for (idx, square) in self.squares.iter().enumerate() {
+            if let Some(piece) = square {
+                material[piece.color as usize] += piece_value(Position(idx), piece);
+            }
+        }
+        material
+    }
+
+    // Identifies the position for repetition detection: piece placement, side to
+    // move and whether en passant is possible. The halfmove clock does not count.
+    pub fn position_key(&self) -> u64 {
+        match self.en_passant_target() {
+            Some(target) => self.hash ^ zobrist_en_passant(target.file()),
+            None => self.hash,
+        }
+    }
+
+    // The position key plus the castling rights, so that a king that went away and
+    // came back does not repeat the position. The game and the search both count
+    // repetitions with it, so they agree on when a line is drawn.
+    pub fn repetition_key(&self) -> u64 {
+        let mut key = self.position_key();
+        for color in [Color::White, Color::Black] {
+            let Some(king) = self.king_position(color) else {
+                continue;
+            };
+            if self[king].is_some_and(|king| king.most_recent_move.is_some()) {
+                continue;
+            }
+            for (queenside, direction) in [(false, 1), (true, -1)] {
+                let unmoved_rook = king.iterate_offset(direction, 0).iter().any(|pos| {
+                    self[pos].is_some_and(|piece| {
+                        piece.color == color
+                            && piece.typ == PieceType::Rook
+                            && piece.most_recent_move.is_none()
+                    })
+                });
+                if unmoved_rook {
+                    key ^= zobrist_castling(color, queenside);
+                }
+            }
+        }
+        key
+    }
+
+    // The square a pawn can capture en passant on right now. A pawn that just made a
+    // double step only makes this a different position when it can actually be taken.
+    pub fn en_passant_target(&self) -> Option<Position> {
+        let last = self.last_move?;
+        if last.from.rank().abs_diff(last.to.rank()) != 2
+            || !self.is_occupied_by(last.to, None, Some(PieceType::Pawn))
+        {
+            return None;
+        }
+        let color = self.current_turn();
+        [-1, 1]
+            .iter()
+            .filter_map(|&file| last.to.offset(file, 0))
+            .filter(|pos| self.is_occupied_by(*pos, Some(color), Some(PieceType::Pawn)))
+            .flat_map(|pos| self.get_moves(&pos).unwrap_or_default())
+            .find(|mv| {
+                matches!(mv.special, Some(SpecialMove::EnPassant(_)))
+                    && !self.apply(mv).is_in_check(color)
+            })
+            .map(|mv| mv.to)
+    }
+
+    // Parses a move in UCI notation, e.g. "e2e4" or "e7e8q", among the moves available on the board
+    pub fn parse_move(&self, uci: &str) -> anyhow::Result<Move> {
+        let square = |sq: &[u8]| -> anyhow::Result<Position> {
+            match sq {
+                [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok((&[*file, *rank]).into()),
+                _ => bail!("invalid square in move {uci}"),
+            }
+        };
+
+        let bytes = uci.as_bytes();
+        if bytes.len() != 4 && bytes.len() != 5 {
+            bail!("invalid move {uci}");
+        }
+        let from = square(&bytes[0..2])?;
+        let to = square(&bytes[2..4])?;
+        let promotion = match bytes.get(4) {
+            Some(letter) => match PieceType::from_san_letter(letter.to_ascii_uppercase() as char) {
+                Some(typ) if typ != PieceType::King => Some(typ),
+                _ => bail!("invalid promotion in move {uci}"),
+            },
+            None => None,
+        };
+
+        // without a promotion piece, the queen comes first
+        self.get_moves(&from)
+            .and_then(|moves| {
+                moves.into_iter().find(|mv| {
+                    mv.to == to
+                        && match mv.special {
+                            Some(SpecialMove::Promotion(typ, _)) => {
+                                promotion.is_none_or(|promotion| promotion == typ)
+                            }
+                            _ => promotion.is_none(),
+                        }
+                })
+            })
+            .with_context(|| format!("move {uci} is not possible"))
+    }
+
+    // The board after the moves one after the other, without checking them
+    pub fn apply_moves(&self, moves: &[Move]) -> Board {
+        moves.iter().fold(*self, |board, mv| board.apply(mv))
+    }
+
+    // Like apply_moves, but fails on the first move that is not possible
+    pub fn apply_moves_checked(&self, moves: &[Move]) -> anyhow::Result<Board> {
+        moves
+            .iter()
+            .try_fold(*self, |board, mv| board.apply_checked(mv))
+    }
+
+    // Like apply, but fails if the move is not available to the side to move
+    pub fn apply_checked(&self, mv: &Move) -> anyhow::Result<Board> {
+        let piece = self[mv.from].with_context(|| format!("no piece on {}", mv.from))?;
+        if piece.color != self.current_turn() {
+            bail!("it is not {:?}'s turn to move", piece.color);
+        }
+        if !piece.get_moves(self, &mv.from).contains(mv) {
+            bail!("move {mv} is not possible");
+        }
+        if !self.legal_moves(piece.color).contains(mv) {
+            bail!("move {mv} would leave the king in check");
+        }
+        Ok(self.apply(mv))
+    }
+
+    // Applies a legal move given in SAN, e.g. "Nf3", "exd5", "e8=Q" or "O-O"
+    pub fn apply_san(&self, san: &str) -> anyhow::Result<Board> {
+        Ok(self.apply(&self.parse_san(san)?))
+    }
+
+    // Finds the legal move given in SAN
+    pub fn parse_san(&self, san: &str) -> anyhow::Result<Move> {
+        let color = self.current_turn();
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        let candidates: Vec<Move> = match text.replace('0', "O").as_str() {
+            castling @ ("O-O" | "O-O-O") => {
+                let file = if castling == "O-O" { 6 } else { 2 };
+                self.legal_moves(color)
+                    .into_iter()
+                    .filter(|mv| matches!(mv.special, Some(SpecialMove::Castling(_, _))))
+                    .filter(|mv| mv.to.file() == file)
+                    .collect()
+            }
+            _ => {
+                let (text, promotion) = match text.split_once('=') {
+                    Some((text, piece)) => {
+                        let mut letters = piece.chars();
+                        match (
+                            letters.next().and_then(PieceType::from_san_letter),
+                            letters.next(),
+                        ) {
+                            (Some(typ), None) => (text, Some(typ)),
+                            _ => bail!("invalid promotion in {san}"),
+                        }
+                    }
+                    None => (text, None),
+                };
+                // a capture is marked before the destination, never after it
+                if text.ends_with('x') {
+                    bail!("invalid move {san}");
+                }
+                let mut letters: Vec<char> = text.chars().filter(|c| *c != 'x').collect();
+                let typ = match letters
+                    .first()
+                    .copied()
+                    .and_then(PieceType::from_san_letter)
+                {
+                    Some(typ) => {
+                        letters.remove(0);
+                        typ
+                    }
+                    None => PieceType::Pawn,
+                };
+                if letters.len() < 2 || letters.len() > 4 {
+                    bail!("invalid move {san}");
+                }
+                let to: String = letters.split_off(letters.len() - 2).into_iter().collect();
+                let to = match to.as_bytes() {
+                    [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Position::from(&[*file, *rank]),
+                    _ => bail!("invalid square in move {san}"),
+                };
+                // what is left tells apart pieces that could go to the same square
+                let mut from_file = None;
+                let mut from_rank = None;
+                for letter in letters {
+                    match letter {
+                        'a'..='h' => from_file = Some(letter as usize - 'a' as usize),
+                        '1'..='8' => from_rank = Some(letter as usize - '1' as usize),
+                        _ => bail!("invalid move {san}"),
+                    }
+                }
+
+                self.legal_moves(color)
+                    .into_iter()
+                    .filter(|mv| mv.to == to)
+                    .filter(|mv| mv.moving_piece(self) == Some(typ))
+                    .filter(|mv| from_file.is_none_or(|file| mv.from.file() == file))
+                    .filter(|mv| from_rank.is_none_or(|rank| mv.from.rank() == rank))
+                    .filter(|mv| match mv.special {
+                        Some(SpecialMove::Promotion(promoted, _)) => promotion == Some(promoted),
+                        _ => promotion.is_none(),
+                    })
+                    .collect()
+            }
+        };
+
+        match candidates.as_slice() {
+            [mv] => Ok(*mv),
+            [] => bail!("move {san} is not legal"),
+            _ => bail!("move {san} is ambiguous"),
+        }
+    }
+
     pub fn get_fen_pieces(&self) -> String {
         let mut res: String = String::new();
 
@@ -305,20 +1005,7 @@ impl Board {
                             res.push_str(format!("{}", ws).as_str());
                             ws = 0;
                         }
-                        res.push_str(match (p.color, p.typ) {
-                            (Color::White, PieceType::King) => "K",
-                            (Color::White, PieceType::Queen) => "Q",
-                            (Color::White, PieceType::Rook) => "R",
-                            (Color::White, PieceType::Knight) => "N",
-                            (Color::White, PieceType::Bishop) => "B",
-                            (Color::White, PieceType::Pawn) => "P",
-                            (Color::Black, PieceType::King) => "k",
-                            (Color::Black, PieceType::Queen) => "q",
-                            (Color::Black, PieceType::Rook) => "r",
-                            (Color::Black, PieceType::Knight) => "n",
-                            (Color::Black, PieceType::Bishop) => "b",
-                            (Color::Black, PieceType::Pawn) => "p",
-                        })
+                        res.push(p.fen_char())
                     }
                     None => ws += 1,
                 }
@@ -351,6 +1038,125 @@ impl Board {
 
         format!("{pieces} {active} {castling} {enpassant} {halfmoves} {fullmoves}")
     }
+
+    // A compact encoding of the position: a bitmap of the occupied squares, 4 bits
+    // for each piece on them, then the en passant file, the ply and the halfmove
+    // clock. Kings and rooks that have moved get their own codes, as they can no
+    // longer castle. At most 28 bytes, with all 32 pieces on the board.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut occupied: u64 = 0;
+        let mut codes = Vec::with_capacity(32);
+        for (idx, square) in self.squares.iter().enumerate() {
+            let Some(piece) = square else {
+                continue;
+            };
+            occupied |= 1 << idx;
+            let color = piece.color as u8;
+            let moved = piece.most_recent_move.is_some();
+            codes.push(match piece.typ {
+                PieceType::Rook if moved => MOVED_ROOK + color,
+                PieceType::King if moved => MOVED_KING + color,
+                typ => 6 * color + PIECE_TYPES.iter().position(|t| *t == typ).unwrap() as u8,
+            });
+        }
+        let mut bytes = occupied.to_le_bytes().to_vec();
+        bytes.extend(
+            codes
+                .chunks(2)
+                .map(|pair| pair[0] | pair.get(1).unwrap_or(&0) << 4),
+        );
+
+        // the file of a pawn that just made a double step, counting from 1
+        let double_step = self.last_move.filter(|last| {
+            last.from.rank().abs_diff(last.to.rank()) == 2
+                && self.is_occupied_by(last.to, None, Some(PieceType::Pawn))
+        });
+        bytes.push(double_step.map_or(0, |last| last.to.file() as u8 + 1));
+        let ply = u16::try_from(self.ply).unwrap_or(u16::MAX);
+        bytes.extend(ply.to_le_bytes());
+        let halfmoves = self.ply - self.last_pawn_move;
+        bytes.push(u8::try_from(halfmoves).unwrap_or(u8::MAX));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Board> {
+        let Some((occupied, rest)) = bytes.split_first_chunk::<8>() else {
+            bail!("expected at least 8 bytes, got {}", bytes.len());
+        };
+        let occupied = u64::from_le_bytes(*occupied);
+        let pieces = occupied.count_ones() as usize;
+        let expected = pieces.div_ceil(2) + 4;
+        if rest.len() != expected {
+            bail!(
+                "expected {} bytes for {pieces} pieces, got {}",
+                expected + 8,
+                bytes.len()
+            );
+        }
+        let (codes, rest) = rest.split_at(pieces.div_ceil(2));
+        let [en_passant, ply_low, ply_high, halfmoves] = rest else {
+            unreachable!("the length was checked");
+        };
+
+        let ply = u16::from_le_bytes([*ply_low, *ply_high]) as usize;
+        let mut board = Board {
+            squares: [None; 64],
+            ply,
+            last_pawn_move: ply.saturating_sub(*halfmoves as usize),
+            last_move: None,
+            hash: if ply % 2 == 1 {
+                ZOBRIST_BLACK_TO_MOVE
+            } else {
+                0
+            },
+            material: [0; 2],
+            piece_counts: [0; 2],
+            pieces_besides_pawns: [0; 2],
+        };
+        let squares = (0..64).filter(|idx| occupied & (1 << idx) != 0);
+        for (i, idx) in squares.enumerate() {
+            let code = (codes[i / 2] >> (4 * (i % 2))) & 0xf;
+            let (typ, color, moved) = match code {
+                0..MOVED_ROOK => (PIECE_TYPES[code as usize % 6], code / 6, false),
+                MOVED_ROOK..MOVED_KING => (PieceType::Rook, code - MOVED_ROOK, true),
+                _ => (PieceType::King, code - MOVED_KING, true),
+            };
+            let piece = Piece {
+                typ,
+                color: if color == 0 {
+                    Color::White
+                } else {
+                    Color::Black
+                },
+                // the ply of the move is not kept, only that there was one
+                most_recent_move: moved.then_some(0),
+            };
+            board.set(Position(idx), Some(piece));
+        }
+
+        if *en_passant != 0 {
+            if *en_passant > 8 {
+                bail!("invalid en passant file {en_passant}");
+            }
+            // the opponent's pawn made the double step
+            let color = !board.current_turn();
+            let (from, to) = match color {
+                Color::White => (1, 3),
+                Color::Black => (6, 4),
+            };
+            let file = *en_passant as usize - 1;
+            let to = Position::from((to, file));
+            if !board.is_occupied_by(to, Some(color), Some(PieceType::Pawn)) {
+                bail!("no pawn on {to} for en passant");
+            }
+            board.last_move = Some(Move {
+                from: (from, file).into(),
+                to,
+                special: None,
+            });
+        }
+        Ok(board)
+    }
 }
 
 impl<T> Index<T> for Board
@@ -375,27 +1181,178 @@ where
     }
 }
 
-impl Display for Board {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Board {
+    // The board with the given squares marked, e.g. where a piece can move: empty
+    // squares get a "·" and occupied ones an "x" in front of the piece. Flipped
+    // shows it from black's side.
+    pub fn render(&self, markers: &[Position], flipped: bool) -> String {
+        let mut out = String::new();
+        self.write_board(&mut out, markers, flipped)
+            .expect("writing to a String does not fail");
+        out
+    }
+
+    // What each side has taken, going by the pieces missing from a full set, and who
+    // is ahead in the usual points, e.g. "White took ♟♞, black took ♙, white is up 3"
+    pub fn material_line(&self) -> String {
+        const FULL_SET: [(PieceType, usize); 5] = [
+            (PieceType::Queen, 1),
+            (PieceType::Rook, 2),
+            (PieceType::Bishop, 2),
+            (PieceType::Knight, 2),
+            (PieceType::Pawn, 8),
+        ];
+        let points = |typ| match typ {
+            PieceType::Queen => 9,
+            PieceType::Rook => 5,
+            PieceType::Bishop | PieceType::Knight => 3,
+            PieceType::Pawn => 1,
+            PieceType::King => 0,
+        };
+
+        let mut taken = [String::new(), String::new()]; // by white and by black
+        let mut score = [0; 2];
+        for color in [Color::White, Color::Black] {
+            let pieces = self.get_pieces(color);
+            for (typ, full) in FULL_SET {
+                let left = pieces.iter().filter(|(_, piece)| piece.typ == typ).count();
+                score[color as usize] += left as i32 * points(typ);
+                for _ in left..full {
+                    let piece = Piece {
+                        color,
+                        typ,
+                        most_recent_move: None,
+                    };
+                    taken[!color as usize].push_str(&piece.to_string());
+                }
+            }
+        }
+
+        let taken = |color: Color| match taken[color as usize].as_str() {
+            "" => "nothing".to_string(),
+            pieces => pieces.to_string(),
+        };
+        let balance = match score[0] - score[1] {
+            0 => "material is even".to_string(),
+            lead if lead > 0 => format!("white is up {lead}"),
+            lead => format!("black is up {}", -lead),
+        };
+        format!(
+            "White took {}, black took {}, {balance}",
+            taken(Color::White),
+            taken(Color::Black)
+        )
+    }
+
+    // Who is to move, after which move and whether they are in check, e.g.
+    // "White to move after 2... Qh4+, in check". The board before the last move
+    // is needed to write it in SAN.
+    pub fn status_line(&self, previous: Option<&Board>) -> String {
+        let color = self.current_turn();
+        let mut line = match color {
+            Color::White => "White to move".to_string(),
+            Color::Black => "Black to move".to_string(),
+        };
+        if let (Some(mv), Some(previous)) = (self.last_move, previous) {
+            line.push_str(&format!(" after {}", mv.numbered_san(previous)));
+        }
+        if self.is_in_check(color) {
+            line.push_str(", in check");
+        }
+        line
+    }
+
+    // The board as 8 rows of FEN letters with "." for empty squares, white at the bottom
+    pub fn render_ascii(&self) -> String {
+        self.get_fen_pieces()
+            .split('/')
+            .map(|rank| {
+                rank.chars()
+                    .map(|ch| match ch.to_digit(10) {
+                        Some(empty) => ".".repeat(empty as usize),
+                        None => ch.to_string(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Reads a board as written by render_ascii. Rank numbers, a line of file letters
+    // and spaces are ignored. White moves unless another side is given.
+    pub fn from_ascii(text: &str, active: Option<Color>) -> Result<Self, FenError> {
+        let ranks: Vec<String> = text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .filter(|ch| !ch.is_whitespace() && !ch.is_ascii_digit())
+                    .collect::<String>()
+            })
+            .filter(|line| !line.is_empty() && line != "abcdefgh")
+            .map(|line| {
+                // runs of empty squares become counts, as in FEN
+                let mut rank = String::new();
+                let mut empty = 0;
+                for ch in line.chars() {
+                    if ch == '.' {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        rank.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    rank.push(ch);
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect();
+        let active = match active.unwrap_or(Color::White) {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        Board::new(Some(format!("{} {active} - - 0 1", ranks.join("/"))))
+    }
+
+    fn write_board(
+        &self,
+        f: &mut impl std::fmt::Write,
+        markers: &[Position],
+        flipped: bool,
+    ) -> std::fmt::Result {
         const FILES: &[u8; 8] = b"abcdefgh";
+        // black on top, unless flipped
+        let files: Vec<usize> = match flipped {
+            false => (0..8).collect(),
+            true => (0..8).rev().collect(),
+        };
+        let ranks: Vec<usize> = match flipped {
+            false => (0..8).rev().collect(),
+            true => (0..8).collect(),
+        };
         write!(f, " ")?;
-        for file in 0..8 {
+        for &file in files.iter() {
             write!(f, " {}", FILES[file] as char)?;
         }
         writeln!(f, "")?;
-        for rank in (0..8).rev() {
-            // print black on top
+        for &rank in ranks.iter() {
             write!(f, "{}", rank + 1)?;
-            for file in 0..8 {
+            for &file in files.iter() {
                 let pos: Position = (rank, file).into();
                 let square = self[pos];
+                let marked = markers.contains(&pos);
                 let prefix = match self.last_move {
+                    _ if marked && square.is_some() => "x",
                     Some(mv) if mv.to == pos => ">",
                     Some(mv) if mv.from == pos => ">",
                     _ => " ",
                 };
                 match square {
                     Some(piece) => write!(f, "{}{}", prefix, piece)?,
+                    None if marked => write!(f, "{}·", prefix)?,
                     None => write!(f, "{} ", prefix)?,
                 }
             }
@@ -405,9 +1362,90 @@ impl Display for Board {
     }
 }
 
+impl Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_board(f, &[], false)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    #[test]
+    fn test_render() {
+        let destinations = |board: &Board, from: &[u8; 2]| -> Vec<Position> {
+            board
+                .legal_moves(board.current_turn())
+                .iter()
+                .filter(|mv| mv.from == from.into())
+                .map(|mv| mv.to)
+                .collect()
+        };
+
+        let board = Board::new(None).unwrap();
+        let expected = [
+            "  a b c d e f g h",
+            "8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜",
+            "7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟",
+            "6                ",
+            "5                ",
+            "4                ",
+            "3           ·   ·",
+            "2 ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙",
+            "1 ♖ ♘ ♗ ♕ ♔ ♗ ♘ ♖",
+            "",
+        ];
+        let markers = destinations(&board, b"g1");
+        assert_eq!(board.render(&markers, false), expected.join("\n"));
+
+        // castling to g1, taking the knight, but not stepping next to it onto f2
+        let fen = "4k3/8/8/8/8/8/8/R2nK2R w KQ - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let expected = [
+            "  a b c d e f g h",
+            "8         ♚      ",
+            "7                ",
+            "6                ",
+            "5                ",
+            "4                ",
+            "3                ",
+            "2       · ·      ",
+            "1 ♖    x♞ ♔ · · ♖",
+            "",
+        ];
+        let markers = destinations(&board, b"e1");
+        assert_eq!(board.render(&markers, false), expected.join("\n"));
+        // without markers it is the usual board
+        assert_eq!(board.render(&[], false), board.to_string());
+
+        let expected = [
+            "  h g f e d c b a",
+            "1 ♖ · · ♔x♞     ♖",
+            "2       · ·      ",
+            "3                ",
+            "4                ",
+            "5                ",
+            "6                ",
+            "7                ",
+            "8       ♚        ",
+            "",
+        ];
+        assert_eq!(board.render(&markers, true), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_distance() {
+        let e4: Position = b"e4".into();
+        assert_eq!(e4.distance(&b"e4".into()), 0);
+        assert_eq!(e4.distance(&b"a1".into()), 4);
+        assert_eq!(e4.distance(&b"g8".into()), 4);
+        assert_eq!(e4.center_distance(), 0);
+        assert_eq!(Position::from(b"b7").center_distance(), 2);
+        assert_eq!(Position::from(b"h1").center_distance(), 3);
+        assert_eq!(Position::from(b"d8").center_distance(), 3);
+    }
 
     #[test]
     fn test_fen_1() {
@@ -417,6 +1455,149 @@ mod tests {
         assert_eq!(board.ply, 3);
     }
 
+    fn check_perft(board: &Board, depth: usize, expected: u64) {
+        let divide = board.perft_divide(depth);
+        let total: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+        if total != expected {
+            for (mv, nodes) in divide {
+                println!("{mv}: {nodes}");
+            }
+            panic!("perft({depth}) found {total} nodes, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn test_perft() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(board.perft(0), 1);
+        check_perft(&board, 1, 20);
+        check_perft(&board, 2, 400);
+        check_perft(&board, 3, 8902);
+
+        // a position with many checks, and en passant
+        let board = Board::new(Some("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1".into())).unwrap();
+        check_perft(&board, 1, 14);
+        check_perft(&board, 2, 191);
+        check_perft(&board, 3, 2812);
+        check_perft(&board, 4, 43238);
+    }
+
+    #[test]
+    fn test_check_candidates() {
+        // the moves applied to filter out illegal ones, with and without the
+        // shortcut in check, over the whole tree of the perft position
+        fn applied(board: &Board, depth: usize) -> (usize, usize) {
+            let color = board.current_turn();
+            let all = board
+                .get_pieces(color)
+                .into_iter()
+                .flat_map(|(pos, piece)| piece.get_moves(board, &pos))
+                .count();
+            let mut counts = (board.check_candidates(color).len(), all);
+            if depth > 1 {
+                for mv in board.legal_moves(color) {
+                    let (candidates, all) = applied(&board.apply(&mv), depth - 1);
+                    counts = (counts.0 + candidates, counts.1 + all);
+                }
+            }
+            counts
+        }
+
+        let board = Board::new(Some("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1".into())).unwrap();
+        // 3516 fewer moves to apply, about 6%, for the 43238 positions at depth 4
+        assert_eq!(applied(&board, 4), (51239, 54755));
+    }
+
+    // Slow, run with cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn test_perft_5() {
+        let board = Board::new(None).unwrap();
+        check_perft(&board, 4, 197281);
+        check_perft(&board, 5, 4865609);
+    }
+
+    #[test]
+    fn test_ascii() {
+        let board = Board::new(None).unwrap();
+        let ascii = board.render_ascii();
+        assert_eq!(
+            ascii,
+            "rnbqkbnr\npppppppp\n........\n........\n........\n........\nPPPPPPPP\nRNBQKBNR"
+        );
+        assert_eq!(Board::from_ascii(&ascii, None).unwrap(), board);
+
+        let fen = "7k/R7/4n1K1/8/8/8/8/8 b - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let ascii = board.render_ascii();
+        assert_eq!(
+            Board::from_ascii(&ascii, Some(Color::Black)).unwrap(),
+            board
+        );
+
+        // with coordinates
+        let labelled = "
+            8 . . . . . . . k
+            7 R . . . . . . .
+            6 . . . . n . K .
+            5 . . . . . . . .
+            4 . . . . . . . .
+            3 . . . . . . . .
+            2 . . . . . . . .
+            1 . . . . . . . .
+              a b c d e f g h
+        ";
+        assert_eq!(
+            Board::from_ascii(labelled, Some(Color::Black)).unwrap(),
+            board
+        );
+
+        assert_eq!(
+            Board::from_ascii("........", None).unwrap_err(),
+            FenError::RankCount(1)
+        );
+        let wide = ascii.replacen(".", "..", 1);
+        assert_eq!(
+            Board::from_ascii(&wide, None).unwrap_err(),
+            FenError::RankLength(8)
+        );
+        let unknown = ascii.replacen(".", "x", 1);
+        assert_eq!(
+            Board::from_ascii(&unknown, None).unwrap_err(),
+            FenError::UnknownPiece('x')
+        );
+    }
+
+    #[test]
+    fn test_fen_errors() {
+        let error = |fen: &str| Board::new(Some(fen.into())).unwrap_err();
+        assert_eq!(error("8/8/8/8/8/8/8/8 w - - 0"), FenError::FieldCount(5));
+        assert_eq!(error("8/8/8/8/8/8/8 w - - 0 1"), FenError::RankCount(7));
+        assert_eq!(error("8/8/8/8/8/8/8/7 w - - 0 1"), FenError::RankLength(1));
+        assert_eq!(error("8/8/8/8/8/8/8/8K w - - 0 1"), FenError::RankLength(1));
+        assert_eq!(error("9/8/8/8/8/8/8/8 w - - 0 1"), FenError::RankLength(8));
+        assert_eq!(
+            error("8/8/8/8/8/8/8/7X w - - 0 1"),
+            FenError::UnknownPiece('X')
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 x - - 0 1"),
+            FenError::ActiveColor("x".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w - - a 1"),
+            FenError::Halfmoves("a".into())
+        );
+        assert_eq!(
+            error("8/8/8/8/8/8/8/8 w - - 0 0"),
+            FenError::Fullmoves("0".into())
+        );
+
+        // still usable as an anyhow error, with the message
+        let err: anyhow::Error = error("8/8/8/8/8/8/8/8 w - - 0").into();
+        assert_eq!(err.to_string(), "expected 6 fields, found 5");
+    }
+
     #[test]
     fn test_fen_2() {
         let fen = "7Q/p1pbkppp/1p2pq2/3p4/2PP4/2P2N2/P3PPPP/R3KB1R b KQ - 0 11";
@@ -429,7 +1610,7 @@ mod tests {
     fn test_fen_pieces() {
         let pieces = "7Q/p1pbkppp/1p2pq2/3p4/2PP4/2P2N2/P3PPPP/R3KB1R";
         let fen = format!("{pieces} b KQ - 0 11");
-        let board = Board::new(Some(fen.into())).unwrap();
+        let board = Board::new(Some(fen)).unwrap();
 
         let new_pieces = board.get_fen_pieces();
         assert_eq!(pieces, new_pieces)
@@ -443,4 +1624,661 @@ mod tests {
         let new_fen = board.get_fen();
         assert_eq!(fen, new_fen)
     }
+
+    #[test]
+    fn test_position_key() {
+        let board = Board::new(None).unwrap();
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let same = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.position_key(), same.position_key());
+
+        // same pieces, other side to move
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        let other = Board::new(Some(fen.into())).unwrap();
+        assert_ne!(board.position_key(), other.position_key());
+    }
+
+    #[test]
+    fn test_repetition_key() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(
+            board.repetition_key(),
+            Board::new(Some(fen.into())).unwrap().repetition_key()
+        );
+
+        // back where it started, without the right to castle
+        let back = ["Kd1", "Kd8", "Ke1", "Ke8"]
+            .iter()
+            .try_fold(board, |board, san| board.apply_san(san))
+            .unwrap();
+        assert_eq!(back.position_key(), board.position_key());
+        assert_ne!(back.repetition_key(), board.repetition_key());
+
+        // only the rook on h1 moved, castling queenside stays possible
+        let rook = ["Rh2", "Kd8", "Rh1", "Ke8"]
+            .iter()
+            .try_fold(board, |board, san| board.apply_san(san))
+            .unwrap();
+        assert_ne!(rook.repetition_key(), board.repetition_key());
+        assert_ne!(rook.repetition_key(), back.repetition_key());
+    }
+
+    #[test]
+    fn test_position_key_en_passant() {
+        let after_double_step = |fen: &str, mv: &str| {
+            let board = Board::new(Some(fen.into())).unwrap();
+            board.apply(&board.parse_move(mv).unwrap())
+        };
+
+        // exd6 is possible, which sets the position apart
+        let board = after_double_step("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1", "d7d5");
+        assert_eq!(board.en_passant_target(), Some(b"d6".into()));
+        let fresh = Board::new(Some("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 2".into())).unwrap();
+        assert_ne!(board.position_key(), fresh.position_key());
+
+        // no pawn next to it
+        let board = after_double_step("4k3/p7/8/4P3/8/8/8/4K3 b - - 0 1", "a7a5");
+        assert_eq!(board.en_passant_target(), None);
+        let fresh = Board::new(Some("4k3/8/8/p3P3/8/8/8/4K3 w - - 0 2".into())).unwrap();
+        assert_eq!(board.position_key(), fresh.position_key());
+
+        // bxc6 would leave the king to the rook
+        let board = after_double_step("8/2p5/8/KP5r/8/8/8/4k3 b - - 0 1", "c7c5");
+        assert_eq!(board.en_passant_target(), None);
+        let fresh = Board::new(Some("8/8/8/KPp4r/8/8/8/4k3 w - - 0 2".into())).unwrap();
+        assert_eq!(board.position_key(), fresh.position_key());
+    }
+
+    #[test]
+    fn test_apply_checked() {
+        let board = Board::new(None).unwrap();
+        let mv = board.parse_move("e2e4").unwrap();
+        assert_eq!(mv.from, b"e2".into());
+        assert_eq!(mv.to, b"e4".into());
+        assert!(board.apply_checked(&mv).is_ok());
+
+        assert!(board.parse_move("e2e5").is_err());
+        assert!(board.parse_move("e2").is_err());
+        assert!(board.parse_move("i2i4").is_err());
+
+        // black can not move first
+        let mv = board.parse_move("e7e5").unwrap();
+        assert!(board.apply_checked(&mv).is_err());
+
+        // the knight is pinned to the king by the bishop
+        let board = Board::new(Some("4k3/8/8/b7/8/2N5/8/4K3 w - - 0 1".into())).unwrap();
+        let mv = board.parse_move("c3e4").unwrap();
+        let err = board.apply_checked(&mv).unwrap_err();
+        assert!(err.to_string().contains("king in check"), "{err}");
+        assert!(board.apply_moves_checked(&[mv]).is_err());
+        assert!(board
+            .apply_checked(&board.parse_move("e1e2").unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_apply_moves() -> anyhow::Result<()> {
+        let start = Board::new(None)?;
+        let mut board = start;
+        let mut moves = Vec::new();
+        for uci in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            let mv = board.parse_move(uci)?;
+            board = board.apply(&mv);
+            moves.push(mv);
+        }
+        let expected = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R";
+        assert_eq!(start.apply_moves(&moves).get_fen_pieces(), expected);
+        assert_eq!(start.apply_moves(&moves), board);
+        assert_eq!(start.apply_moves_checked(&moves)?, board);
+        assert_eq!(start.apply_moves(&moves).current_turn(), Color::White);
+        assert_eq!(start.apply_moves(&[]), start);
+
+        // the second move is white's again
+        let twice = [moves[0], moves[2]];
+        assert!(start.apply_moves_checked(&twice).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_san() -> anyhow::Result<()> {
+        // scholar's mate
+        let board = Board::new(None)?
+            .apply_san("e4")?
+            .apply_san("e5")?
+            .apply_san("Bc4")?
+            .apply_san("Nc6")?
+            .apply_san("Qh5")?
+            .apply_san("Nf6")?
+            .apply_san("Qxf7#")?;
+        assert_eq!(
+            board.get_fen_pieces(),
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR"
+        );
+        assert!(board.legal_moves(Color::Black).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_san_special_moves() -> anyhow::Result<()> {
+        let fen = "4k3/3p4/8/4P3/8/8/8/4K2R b - - 0 1";
+        let board = Board::new(Some(fen.into()))?.apply_san("d5")?;
+        let board = board.apply_san("exd6")?; // en passant
+        assert!(board[b"d5"].is_none());
+
+        let board = board.apply_san("Kf7")?.apply_san("O-O")?;
+        assert!(board[b"g1"].is_some_and(|p| p.typ == PieceType::King));
+        assert!(board[b"f1"].is_some_and(|p| p.typ == PieceType::Rook));
+
+        let fen = "8/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into()))?;
+        let promoted = board.apply_san("e8=Q+")?;
+        assert!(promoted[b"e8"].is_some_and(|p| p.typ == PieceType::Queen));
+        assert!(board.apply_san("e8").is_err());
+        assert!(board.apply_san("e8=K").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> anyhow::Result<()> {
+        // the squares that changed, with the piece types before and after
+        fn changes(before: &Board, after: &Board) -> Vec<(String, Option<char>, Option<char>)> {
+            before
+                .diff(after)
+                .into_iter()
+                .map(|(pos, old, new)| {
+                    let letter = |piece: Option<Piece>| piece.map(|p| p.typ.san_letter());
+                    (pos.to_string(), letter(old), letter(new))
+                })
+                .collect()
+        }
+        let board = Board::new(None)?;
+        assert!(board.diff(&board).is_empty());
+
+        let fen = "4k3/3p4/8/4P3/8/8/8/4K2R b - - 0 1";
+        let board = Board::new(Some(fen.into()))?.apply_san("d5")?;
+        let after = board.apply_san("exd6")?;
+        assert_eq!(
+            changes(&board, &after),
+            [
+                ("d5".into(), Some('P'), None),
+                ("e5".into(), Some('P'), None),
+                ("d6".into(), None, Some('P')),
+            ]
+        );
+
+        let board = after.apply_san("Kf7")?;
+        let after = board.apply_san("O-O")?;
+        assert_eq!(
+            changes(&board, &after),
+            [
+                ("e1".into(), Some('K'), None),
+                ("f1".into(), None, Some('R')),
+                ("g1".into(), None, Some('K')),
+                ("h1".into(), Some('R'), None),
+            ]
+        );
+
+        let fen = "3r4/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into()))?;
+        let after = board.apply_san("exd8=Q")?;
+        assert_eq!(
+            changes(&board, &after),
+            [
+                ("e7".into(), Some('P'), None),
+                ("d8".into(), Some('R'), Some('Q')),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_san_errors() -> anyhow::Result<()> {
+        let board = Board::new(None)?;
+        assert!(board.apply_san("e5").is_err());
+        assert!(board.apply_san("Nf4").is_err());
+        assert!(board.apply_san("O-O").is_err());
+        assert!(board.apply_san("e9").is_err());
+        assert!(board.apply_san("hello").is_err());
+        assert!(board.apply_san("").is_err());
+
+        let fen = "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1";
+        let board = Board::new(Some(fen.into()))?;
+        let err = board.apply_san("Nd2").unwrap_err();
+        assert_eq!(err.to_string(), "move Nd2 is ambiguous");
+        assert!(board.apply_san("Nbd2").is_ok());
+        assert!(board.apply_san("Nb1d2").is_ok());
+        assert!(board.apply_san("N1d2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_position_key_incremental() {
+        // the same position reached by different move orders
+        let board = Board::new(None).unwrap();
+        let moves = ["e2e4", "e7e5", "g1f3", "b8c6"];
+        let a = moves.iter().fold(board, |board, mv| {
+            board.apply(&board.parse_move(mv).unwrap())
+        });
+        let moves = ["g1f3", "b8c6", "e2e4", "e7e5"];
+        let b = moves.iter().fold(board, |board, mv| {
+            board.apply(&board.parse_move(mv).unwrap())
+        });
+        assert_eq!(a.position_key(), b.position_key());
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let fresh = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(a.position_key(), fresh.position_key());
+
+        // a capture, and castling
+        let fen = "4k3/8/8/3p4/4P3/8/8/4K2R w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let board = board.apply(&board.parse_move("e4d5").unwrap());
+        let board = board.apply(&board.parse_move("e8d7").unwrap());
+        let board = board.apply(&board.parse_move("e1g1").unwrap());
+        let fen = "8/3k4/8/3P4/8/8/8/5RK1 b - - 0 2";
+        let fresh = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.position_key(), fresh.position_key());
+    }
+
+    #[test]
+    fn test_material() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(board.material(Color::White), board.material(Color::Black));
+        assert_eq!(board.count_pieces(), (16, 16));
+
+        // a pawn advancing gains value
+        let fen = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.material(Color::White), 10000 + 200);
+        assert_eq!(board.material(Color::Black), 10000 + 500);
+
+        // capturing while promoting
+        let board = board.apply(&board.parse_move("a7b8q").unwrap());
+        assert_eq!(board.material(Color::White), 10000 + 900);
+        assert_eq!(board.material(Color::Black), 10000);
+        assert_eq!(board.count_pieces(), (2, 1));
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        // the b-pawn is free to run, the e-pawn is blocked and the h-pawn has the
+        // g-pawn next to it
+        let board = Board::new(Some("4k3/4p1p1/8/1P6/4P2P/8/8/4K3 w - - 0 1".into())).unwrap();
+        assert!(board.is_passed_pawn(Position::from(b"b5")));
+        assert!(!board.is_passed_pawn(Position::from(b"e4")));
+        assert!(!board.is_passed_pawn(Position::from(b"h4")));
+        assert_eq!(board.passed_pawns(Color::White), [Position::from(b"b5")]);
+        // e4 and h4 stand in the way of the black pawns
+        assert_eq!(board.passed_pawns(Color::Black), []);
+        // a square without a pawn
+        assert!(!board.is_passed_pawn(Position::from(b"e1")));
+
+        let start = Board::new(None).unwrap();
+        assert_eq!(start.passed_pawns(Color::White), []);
+    }
+
+    #[test]
+    fn test_material_balance() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(board.material_balance(Color::White), 0);
+        assert_eq!(board.material_balance(Color::Black), 0);
+
+        // white is a rook up
+        let fen = "1nbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.material_balance(Color::White), 500);
+        assert_eq!(board.material_balance(Color::Black), -500);
+    }
+
+    #[test]
+    fn test_king_position() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(board.king_position(Color::White), Some(b"e1".into()));
+        assert_eq!(board.king_position(Color::Black), Some(b"e8".into()));
+
+        let fen = "7Q/p1pbkppp/1p2pq2/3p4/2PP4/2P2N2/P3PPPP/R3KB1R b KQ - 0 11";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.king_position(Color::White), Some(b"e1".into()));
+        assert_eq!(board.king_position(Color::Black), Some(b"e7".into()));
+
+        let fen = "7k/8/8/8/8/6q1/5q2/8 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(board.king_position(Color::White), None);
+        assert_eq!(board.king_position(Color::Black), Some(b"h8".into()));
+    }
+
+    #[test]
+    fn test_is_square_attacked() {
+        let fen = "4k3/8/8/3p4/8/8/8/R3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(board.is_square_attacked(&b"a8".into(), Color::White));
+        assert!(board.is_square_attacked(&b"d2".into(), Color::White));
+        assert!(!board.is_square_attacked(&b"h1".into(), Color::White));
+        assert!(board.is_square_attacked(&b"e4".into(), Color::Black));
+        assert!(!board.is_square_attacked(&b"d4".into(), Color::Black));
+    }
+
+    #[test]
+    fn test_pawn_attacks() {
+        let fen = "4k3/2p5/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(board.is_attacked_by_pawn(&b"d5".into(), Color::White));
+        assert!(board.is_attacked_by_pawn(&b"e4".into(), Color::Black));
+        assert!(board.is_attacked_by_pawn(&b"b6".into(), Color::Black));
+        // pawns only attack forwards
+        assert!(!board.is_attacked_by_pawn(&b"d3".into(), Color::White));
+        assert!(!board.is_attacked_by_pawn(&b"c6".into(), Color::Black));
+
+        // c7 can still come to attack d5 and d6, but not d7 or squares behind it
+        assert!(board.can_pawn_attack(&b"d5".into(), Color::Black));
+        assert!(board.can_pawn_attack(&b"b4".into(), Color::Black));
+        assert!(!board.can_pawn_attack(&b"d7".into(), Color::Black));
+        assert!(!board.can_pawn_attack(&b"c5".into(), Color::Black));
+        assert!(board.can_pawn_attack(&b"f8".into(), Color::White));
+        assert!(!board.can_pawn_attack(&b"f4".into(), Color::White));
+    }
+
+    #[test]
+    fn test_is_legal_position() {
+        assert!(Board::new(None).unwrap().is_legal_position());
+
+        let legal = |fen: &str| Board::new(Some(fen.into())).unwrap().is_legal_position();
+        assert!(legal("4k3/8/8/8/8/8/4q3/4K3 w - - 0 1")); // in check on our own move
+        assert!(!legal("4k3/8/8/8/8/8/4Q3/4K3 w - - 0 1")); // the opponent is in check
+        assert!(!legal("8/8/8/8/8/8/4Q3/4K3 w - - 0 1")); // no black king
+        assert!(!legal("4k3/8/8/8/8/8/8/3KK3 w - - 0 1")); // two white kings
+        assert!(!legal("4k2P/8/8/8/8/8/8/4K3 b - - 0 1")); // a pawn on the last rank
+        assert!(!legal("4k3/8/8/8/8/8/8/4K2p w - - 0 1"));
+    }
+
+    #[test]
+    fn test_legal_moves_san() {
+        let board = Board::new(None).unwrap();
+        let moves = board.legal_moves_san(Color::White);
+        assert_eq!(moves.len(), 20);
+        let san: Vec<&str> = moves.iter().map(|(_, san)| san.as_str()).collect();
+        assert!(san.contains(&"Nf3"));
+        assert!(san.contains(&"Na3"));
+        assert!(san.contains(&"e4"));
+        assert!(san.contains(&"h3"));
+
+        let (mv, _) = moves.iter().find(|(_, san)| san == "e4").unwrap();
+        assert_eq!(mv.from, b"e2".into());
+
+        // the pinned knight may not move
+        let fen = "4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let moves = board.legal_moves(Color::White);
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|mv| mv.from == b"e1".into()));
+    }
+
+    #[test]
+    fn test_checkers() {
+        let board = Board::new(None).unwrap();
+        assert!(board.checkers(Color::White).is_empty());
+        assert!(board.checkers(Color::Black).is_empty());
+
+        // the rook checks, and can be taken or blocked
+        let board = Board::new(Some("4k3/8/8/8/4r3/8/8/3QK1N1 w - - 0 1".into())).unwrap();
+        assert_eq!(board.checkers(Color::White), [Position::from(b"e4")]);
+        let mut moves: Vec<String> = board
+            .legal_moves_san(Color::White)
+            .into_iter()
+            .map(|(_, san)| san)
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["Kd2", "Kf1", "Kf2", "Ne2", "Qe2"]);
+
+        // a knight and a bishop check at once, only the king can move
+        let board = Board::new(Some("4k3/8/8/b7/8/3n4/4P3/R3K2R w KQ - 0 1".into())).unwrap();
+        let mut checkers = board.checkers(Color::White);
+        checkers.sort_by_key(|pos| pos.to_string());
+        assert_eq!(checkers, [Position::from(b"a5"), Position::from(b"d3")]);
+        let moves = board.legal_moves(Color::White);
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|mv| mv.from == Position::from(b"e1")));
+
+        // a pawn giving check can be taken en passant
+        let board = Board::new(Some("8/8/8/4k3/4p3/8/3P4/4K3 w - - 0 1".into()))
+            .unwrap()
+            .apply_san("d4")
+            .unwrap();
+        assert_eq!(board.checkers(Color::Black), [Position::from(b"d4")]);
+        let exd3 = board.parse_san("exd3").unwrap();
+        assert!(board.legal_moves(Color::Black).contains(&exd3));
+
+        // the same moves as trying every one of them, in random games
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..60 {
+                let color = board.current_turn();
+                let all: Vec<Move> = board
+                    .get_pieces(color)
+                    .into_iter()
+                    .flat_map(|(pos, piece)| piece.get_moves(&board, &pos))
+                    .filter(|mv| !board.apply(mv).is_in_check(color))
+                    .collect();
+                let moves = board.legal_moves(color);
+                assert_eq!(moves, all, "{}", board.get_fen());
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gives_check() {
+        // discovered check by moving the knight off the file, and castling into check
+        let fen = "4k3/8/8/8/4N3/8/8/4R2K w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(board.gives_check(&board.parse_move("e4c3").unwrap()));
+        let fen = "8/8/8/8/8/8/8/R3K2k w Q - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(board.gives_check(&board.parse_san("O-O-O").unwrap()));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut checks = 0;
+        for _ in 0..30 {
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..80 {
+                let color = board.current_turn();
+                let moves = board.legal_moves(color);
+                for mv in &moves {
+                    let expected = board.apply(mv).is_in_check(!color);
+                    assert_eq!(
+                        board.gives_check(mv),
+                        expected,
+                        "{} in {}",
+                        mv.uci(),
+                        board.get_fen()
+                    );
+                    checks += expected as usize;
+                }
+                let Some(mv) = moves.choose(&mut rng) else {
+                    break;
+                };
+                board = board.apply(mv);
+            }
+        }
+        assert!(checks > 100);
+    }
+
+    #[test]
+    fn test_material_line() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(
+            board.material_line(),
+            "White took nothing, black took nothing, material is even"
+        );
+
+        let fen = "rnb1kb1r/pppp1ppp/8/8/8/8/PPPPPP1P/RNBQKBNR w KQkq - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(
+            board.material_line(),
+            "White took ♛♞♟, black took ♙, white is up 12"
+        );
+
+        // a promoted queen counts, even if nothing was taken for it
+        let fen = "4k3/8/8/8/8/8/8/QQ2K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(board.material_line().ends_with("white is up 18"));
+    }
+
+    #[test]
+    fn test_status_line() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(board.status_line(None), "White to move");
+
+        let mut previous = board;
+        let mut board = board;
+        for san in ["f3", "e5", "g4", "Qh4"] {
+            previous = board;
+            board = board.apply_san(san).unwrap();
+        }
+        assert_eq!(
+            board.status_line(Some(&previous)),
+            "White to move after 2... Qh4+, in check"
+        );
+        assert_eq!(board.status_line(None), "White to move, in check");
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let fen = "4k3/8/8/8/8/8/8/R3KB2 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(!board.is_insufficient_material(Color::White));
+        assert!(board.is_insufficient_material(Color::Black));
+
+        // the knight can block the king's way for the bishop, and the other way round
+        let fen = "4kn2/8/8/8/8/8/8/4KB2 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(!board.is_insufficient_material(Color::White));
+        assert!(!board.is_insufficient_material(Color::Black));
+
+        // bishops on opposite colors
+        let fen = "1b2k3/8/8/8/8/8/8/4KB2 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(!board.is_insufficient_material(Color::White));
+        assert!(!board.is_insufficient_material(Color::Black));
+    }
+
+    #[test]
+    fn test_non_pawn_material() {
+        let board = Board::new(Some("8/8/4k3/8/4P3/4K3/8/8 w - - 0 1".into())).unwrap();
+        assert!(!board.has_non_pawn_material(Color::White));
+        assert!(!board.has_non_pawn_material(Color::Black));
+        assert!(board.zugzwang_likely());
+
+        let board = Board::new(Some("8/8/4k3/8/4P3/4K3/8/6N1 b - - 0 1".into())).unwrap();
+        assert!(board.has_non_pawn_material(Color::White));
+        assert!(!board.has_non_pawn_material(Color::Black));
+        assert!(board.zugzwang_likely());
+        // the knight is gone after it is taken
+        let board = Board::new(Some("8/8/4k3/8/4P3/4K2n/8/6N1 b - - 0 1".into())).unwrap();
+        assert!(!board.zugzwang_likely());
+        let board = board.apply_san("Nxg1").unwrap();
+        assert!(!board.has_non_pawn_material(Color::White));
+        assert!(board.zugzwang_likely());
+
+        // a pawn that promotes becomes a piece
+        let board = Board::new(Some("8/4P3/8/8/8/8/8/k3K3 w - - 0 1".into())).unwrap();
+        assert!(!board.has_non_pawn_material(Color::White));
+        let board = board.apply_san("e8=Q").unwrap();
+        assert!(board.has_non_pawn_material(Color::White));
+    }
+
+    #[test]
+    fn test_dead_position() {
+        let dead = |fen: &str| Board::new(Some(fen.into())).unwrap().is_dead_position();
+        // bare kings
+        assert!(dead("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        // a single minor piece
+        assert!(dead("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"));
+        assert!(dead("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        // bishops on squares of the same color, on one side or on both
+        assert!(dead("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(dead("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1"));
+        assert!(dead("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(dead("1b2kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+
+        // bishops on both colors can mate together
+        assert!(!dead("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"));
+        assert!(!dead("1b2k3/8/8/8/8/8/8/4KB2 w - - 0 1"));
+        // and a pawn could become something else
+        assert!(!dead("4k3/8/8/8/8/8/P7/2B1KB2 w - - 0 1"));
+        assert!(!dead("4kb2/8/8/8/8/8/P7/3BK3 w - - 0 1"));
+        assert!(!dead("4k3/8/8/8/8/8/8/4KNN1 w - - 0 1"));
+        assert!(!dead("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        // a knight against a knight is dead, but not against a bishop
+        assert!(dead("4kn2/8/8/8/8/8/8/1N2K3 w - - 0 1"));
+        assert!(!dead("6nk/8/6K1/4B3/8/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let round_trip = |board: &Board| {
+            let bytes = board.to_bytes();
+            let decoded = Board::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.get_fen(), board.get_fen());
+            assert_eq!(decoded.position_key(), board.position_key());
+            assert_eq!(decoded.material, board.material);
+            let color = board.current_turn();
+            assert_eq!(decoded.legal_moves(color), board.legal_moves(color));
+            assert_eq!(decoded.to_bytes(), bytes);
+            bytes
+        };
+
+        let start = Board::new(None).unwrap();
+        assert_eq!(round_trip(&start).len(), 28);
+        // en passant stays possible
+        let board = start
+            .apply_san("e4")
+            .and_then(|board| board.apply_san("a6"))
+            .and_then(|board| board.apply_san("e5"))
+            .and_then(|board| board.apply_san("d5"))
+            .unwrap();
+        assert!(board.en_passant_target().is_some());
+        round_trip(&board);
+        // and castling does not after the king went back and forth
+        let board = Board::new(Some("r3k2r/8/8/8/8/8/8/R3K2R w - - 5 20".into())).unwrap();
+        let moved = ["Kd1", "Kd8", "Ke1", "Ke8", "Rb1", "Kf8"]
+            .iter()
+            .try_fold(board, |board, san| board.apply_san(san))
+            .unwrap();
+        let castles = |board: &Board, color| {
+            board
+                .legal_moves(color)
+                .iter()
+                .filter(|mv| matches!(mv.special, Some(SpecialMove::Castling(..))))
+                .count()
+        };
+        assert_eq!(castles(&board, Color::White), 2);
+        assert_eq!(castles(&moved, Color::White), 0);
+        round_trip(&board);
+        round_trip(&moved);
+        assert!(round_trip(&moved).len() < 34);
+
+        // random games end up where they end up
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut board = start;
+        for _ in 0..80 {
+            let moves = board.legal_moves(board.current_turn());
+            let Some(mv) = moves.choose(&mut rng) else {
+                break;
+            };
+            board = board.apply(mv);
+            round_trip(&board);
+        }
+
+        assert!(Board::from_bytes(&[0; 4]).is_err());
+        assert!(Board::from_bytes(&start.to_bytes()[..27]).is_err());
+        let mut bytes = start.to_bytes();
+        bytes[24] = 9;
+        assert!(Board::from_bytes(&bytes).is_err());
+    }
 }
@@ -0,0 +1,145 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{bail, Context};
+
+use crate::pieces::Color;
+
+// How many more moves to plan for when the number is not known
+pub const DEFAULT_MOVES_LEFT: u32 = 30;
+
+// How long to think about a single move: an even share of the remaining time plus
+// the increment, but never more than half of what is left
+pub fn move_budget(remaining: Duration, increment: Duration, moves_left: u32) -> Duration {
+    (remaining / moves_left.max(1) + increment).min(remaining / 2)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Clock {
+    pub white: Duration,
+    pub black: Duration,
+    pub increment: Duration,
+}
+
+impl Clock {
+    pub fn new(initial: Duration, increment: Duration) -> Self {
+        Clock {
+            white: initial,
+            black: initial,
+            increment,
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+
+    // The time to spend on the given color's next move
+    pub fn move_budget(&self, color: Color) -> Duration {
+        move_budget(self.remaining(color), self.increment, DEFAULT_MOVES_LEFT)
+    }
+
+    // Deducts the time spent on a move and adds the increment.
+    // Returns false if the flag fell, which it does when no time is left, so a
+    // player with none can not move at all.
+    pub fn spend(&mut self, color: Color, elapsed: Duration) -> bool {
+        let remaining = match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        };
+        match remaining
+            .checked_sub(elapsed)
+            .filter(|left| !left.is_zero())
+        {
+            Some(left) => {
+                *remaining = left + self.increment;
+                true
+            }
+            None => {
+                *remaining = Duration::ZERO;
+                false
+            }
+        }
+    }
+}
+
+impl FromStr for Clock {
+    type Err = anyhow::Error;
+
+    // Parses time controls such as "3+2", meaning 3 minutes plus 2 seconds per move
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((minutes, increment)) = s.split_once('+') else {
+            bail!("time control should look like 3+2");
+        };
+        let minutes: f64 = minutes.trim().parse().context("could not parse minutes")?;
+        let increment: f64 = increment
+            .trim()
+            .parse()
+            .context("could not parse increment")?;
+        if minutes < 0.0 || increment < 0.0 {
+            bail!("time control can not be negative");
+        }
+        // also rejects infinity, NaN and times too long to represent
+        let initial =
+            Duration::try_from_secs_f64(minutes * 60.0).context("minutes out of range")?;
+        let increment = Duration::try_from_secs_f64(increment).context("increment out of range")?;
+
+        Ok(Clock::new(initial, increment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let clock: Clock = "3+2".parse().unwrap();
+        assert_eq!(clock.white, Duration::from_secs(180));
+        assert_eq!(clock.black, Duration::from_secs(180));
+        assert_eq!(clock.increment, Duration::from_secs(2));
+
+        assert!("3".parse::<Clock>().is_err());
+        assert!("a+2".parse::<Clock>().is_err());
+        assert!("inf+0".parse::<Clock>().is_err());
+        assert!("nan+0".parse::<Clock>().is_err());
+        assert!("3+inf".parse::<Clock>().is_err());
+        assert!("1e300+0".parse::<Clock>().is_err());
+    }
+
+    #[test]
+    fn test_spend() {
+        let mut clock = Clock::new(Duration::from_secs(10), Duration::from_secs(1));
+        assert!(clock.spend(Color::White, Duration::from_secs(4)));
+        assert_eq!(clock.remaining(Color::White), Duration::from_secs(7));
+        assert_eq!(clock.remaining(Color::Black), Duration::from_secs(10));
+
+        assert!(!clock.spend(Color::Black, Duration::from_secs(11)));
+        assert_eq!(clock.remaining(Color::Black), Duration::ZERO);
+
+        // using up all the time is too much
+        let mut clock = Clock::new(Duration::from_secs(10), Duration::from_secs(1));
+        assert!(!clock.spend(Color::White, Duration::from_secs(10)));
+        let mut clock = Clock::new(Duration::ZERO, Duration::from_secs(1));
+        assert!(!clock.spend(Color::White, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_move_budget() {
+        let secs = Duration::from_secs;
+        assert_eq!(move_budget(secs(300), secs(0), 30), secs(10));
+        assert_eq!(move_budget(secs(300), secs(2), 30), secs(12));
+        assert_eq!(move_budget(secs(300), secs(0), 10), secs(30));
+        // close to the end, the increment would use up more than half the clock
+        assert_eq!(move_budget(secs(4), secs(3), 30), secs(2));
+        assert_eq!(move_budget(Duration::ZERO, secs(3), 30), Duration::ZERO);
+        assert_eq!(move_budget(secs(10), secs(0), 0), secs(5));
+
+        let mut clock = Clock::new(secs(60), secs(1));
+        clock.spend(Color::Black, secs(31));
+        assert_eq!(clock.move_budget(Color::White), secs(3));
+        assert_eq!(clock.move_budget(Color::Black), secs(2));
+    }
+}
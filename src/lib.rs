@@ -0,0 +1,11 @@
+// The modules of the chess binary, also built as a library for the benchmarks
+pub mod analyze;
+pub mod bitboards;
+pub mod board;
+pub mod clock;
+pub mod observer;
+pub mod pgn;
+pub mod pieces;
+pub mod play;
+pub mod players;
+pub mod tournament;
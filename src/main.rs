@@ -1,13 +1,25 @@
-use board::{Board, Squares};
-use clap::{Parser, Subcommand};
-use play::Game;
-use players::{EnginePlayer, PrintBoard, TerminalPlayer};
+use std::{
+    io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-mod bitboards;
-mod board;
-mod pieces;
-mod play;
-mod players;
+use anyhow::{bail, Context};
+use chess::{
+    analyze,
+    bitboards::BitboardGame,
+    board::{Board, Squares},
+    clock::Clock,
+    observer::{ConsoleObserver, JsonlObserver},
+    pgn::append_pgn,
+    play::{Game, TimeoutPolicy},
+    players::{
+        make_player, AnalysisWrapper, Backend, BookPlayer, EnginePlayer, ManualStep, OpeningBook,
+        Player, PrintBoard, SearchParams, TimedPlayer,
+    },
+    tournament::Tournament,
+};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 struct Cli {
@@ -15,10 +27,192 @@ struct Cli {
     command: Option<Command>,
 }
 
+// The starting position, given in one of three ways
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+#[group(multiple = false)]
+struct FenArgs {
+    /// The position as FEN, defaults to the starting position
+    #[arg(value_name = "FEN")]
+    position: Option<String>,
+    /// The position as FEN, quoted as a single argument
+    #[arg(long)]
+    fen: Option<String>,
+    /// Read the position as FEN from standard input
+    #[arg(long)]
+    fen_stdin: bool,
+}
+
+impl FenArgs {
+    fn resolve(self) -> anyhow::Result<Option<String>> {
+        if self.fen_stdin {
+            let mut fen = String::new();
+            io::stdin()
+                .read_line(&mut fen)
+                .context("could not read FEN from stdin")?;
+            return Ok(Some(fen.trim().into()));
+        }
+        Ok(self.fen.or(self.position))
+    }
+}
+
 #[derive(Subcommand, Clone)]
 enum Command {
-    Eval { fen: Option<String> },
-    Play { fen: Option<String> },
+    Eval {
+        #[command(flatten)]
+        fen: FenArgs,
+        /// Which board the engine searches on
+        #[arg(long, value_enum, default_value_t = Backend::Mailbox)]
+        backend: Backend,
+    },
+    Play {
+        #[command(flatten)]
+        fen: FenArgs,
+        /// Moves already played from the start position, e.g. "e2e4,e7e5"
+        #[arg(long, value_delimiter = ',')]
+        moves: Vec<String>,
+        /// Time control in minutes plus increment in seconds, e.g. "3+2"
+        #[arg(long)]
+        tc: Option<Clock>,
+        /// Declare a draw after this many plies
+        #[arg(long)]
+        max_plies: Option<usize>,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+        #[command(flatten)]
+        timeout: TimeoutArgs,
+        /// The white player, e.g. "terminal", "terminal:hint_ms=1000" to allow hints,
+        /// "random:seed=7", "greedy", "mcts:iters=2000", "engine:depth=3",
+        /// "engine:skill=0" (up to 9) for a weaker engine, "uci:path=stockfish",
+        /// "net:listen=0.0.0.0:9999" and
+        /// "net:connect=192.168.1.2:9999" for playing someone on another computer,
+        /// "phased:open=mcts,middle=engine,end=engine:depth=6" for a player per phase,
+        /// or "consensus:engine+mcts+greedy" for the move most of them choose
+        #[arg(long, default_value = "engine")]
+        white: String,
+        /// The black player, in the same form as --white
+        #[arg(long, default_value = "engine")]
+        black: String,
+        /// Seed for the players' random choices, to replay a game exactly
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Append every move as a JSON object to this file, and the result at the end
+        #[arg(long, value_name = "PATH")]
+        log_jsonl: Option<PathBuf>,
+        /// An opening book both players play from while it knows the position, with
+        /// lines such as "<FEN> => e4 3, d4 2"
+        #[arg(long, value_name = "PATH")]
+        book: Option<PathBuf>,
+        /// Append the finished game to this PGN file
+        #[arg(long, value_name = "PATH")]
+        pgn: Option<PathBuf>,
+        /// Print the players' evaluation and the line they expect with every move
+        #[arg(long)]
+        analysis: bool,
+        /// Wait for enter before every move, with commands to look at the position
+        /// in the meantime
+        #[arg(long)]
+        step: bool,
+        /// Say how long every move took, and how long each side thought in total
+        #[arg(long)]
+        timed: bool,
+        /// Clear the screen and print the board before every move, so that it stays
+        /// in place
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Reads commands such as "move e4", "undo" and "go depth 6" to look into a
+    /// position, type "help" for all of them
+    Analyze {
+        #[command(flatten)]
+        fen: FenArgs,
+    },
+    /// Counts the move sequences of the given length, split up by the first move
+    Perft {
+        #[command(flatten)]
+        fen: FenArgs,
+        #[arg(long, default_value_t = 4)]
+        depth: usize,
+        /// Which move generator to count with
+        #[arg(long, value_enum, default_value_t = Backend::Mailbox)]
+        backend: Backend,
+    },
+    /// Plays every player against every other, e.g. "engine:depth=3 engine random"
+    Tournament {
+        #[arg(required = true, num_args = 2..)]
+        players: Vec<String>,
+        /// Games per pairing, with colors alternating
+        #[arg(long, default_value_t = 2)]
+        games: usize,
+        /// Declare a draw after this many plies
+        #[arg(long, default_value_t = 200)]
+        max_plies: usize,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+        #[command(flatten)]
+        timeout: TimeoutArgs,
+        /// Seed for the players' random choices, to replay a tournament exactly
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Append the moves of every game as JSON objects to this file
+        #[arg(long, value_name = "PATH")]
+        log_jsonl: Option<PathBuf>,
+        /// Append every finished game to this PGN file
+        #[arg(long, value_name = "PATH")]
+        pgn: Option<PathBuf>,
+    },
+}
+
+// Ending engine games early on the players' evaluations
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+struct AdjudicationArgs {
+    /// Adjudicate a win once both players see one side ahead by this many centipawns
+    #[arg(long, value_name = "CENTIPAWNS")]
+    adjudicate_win: Option<i32>,
+    /// Adjudicate a draw after move 40 once both players see the game within this
+    /// many centipawns of even
+    #[arg(long, value_name = "CENTIPAWNS")]
+    adjudicate_draw: Option<i32>,
+    /// For how many plies in a row the evaluations have to agree
+    #[arg(long, default_value_t = 8)]
+    adjudicate_plies: usize,
+}
+
+impl AdjudicationArgs {
+    fn apply(&self, game: &mut Game) {
+        if let Some(threshold) = self.adjudicate_win {
+            game.set_adjudication(threshold, self.adjudicate_plies);
+        }
+        if let Some(margin) = self.adjudicate_draw {
+            game.set_draw_adjudication(margin, self.adjudicate_plies);
+        }
+    }
+}
+
+// Limiting how long a player may think about a single move
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+struct TimeoutArgs {
+    /// Stop waiting for a player's move after this many seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    move_timeout: Option<Duration>,
+    /// What happens to a player who does not move in time
+    #[arg(long, value_enum, default_value_t = TimeoutPolicy::Loss)]
+    timeout_policy: TimeoutPolicy,
+}
+
+impl TimeoutArgs {
+    fn apply(&self, game: &mut Game) {
+        if let Some(timeout) = self.move_timeout {
+            game.set_move_timeout(timeout, self.timeout_policy);
+        }
+    }
+}
+
+fn parse_seconds(s: &str) -> anyhow::Result<Duration> {
+    let seconds: f64 = s.trim().parse().context("could not parse seconds")?;
+    if seconds <= 0.0 {
+        bail!("the timeout has to be positive");
+    }
+    Duration::try_from_secs_f64(seconds).context("seconds out of range")
 }
 
 fn main() -> anyhow::Result<()> {
@@ -26,8 +220,97 @@ fn main() -> anyhow::Result<()> {
 
     match cli.command {
         Some(cmd) => match cmd {
-            Command::Eval { fen } => eval(fen),
-            Command::Play { fen } => play(fen),
+            Command::Eval { fen, backend } => eval(fen.resolve()?, backend),
+            Command::Play {
+                fen,
+                moves,
+                tc,
+                max_plies,
+                adjudication,
+                timeout,
+                white,
+                black,
+                seed,
+                log_jsonl,
+                book,
+                pgn,
+                analysis,
+                step,
+                timed,
+                clear,
+            } => {
+                let mut white = make_player(&white).context("invalid white player")?;
+                let mut black = make_player(&black).context("invalid black player")?;
+                if let Some(path) = book {
+                    let book = OpeningBook::load(path)?;
+                    white = Box::new(BookPlayer::wrap(white, book.clone()));
+                    black = Box::new(BookPlayer::wrap(black, book));
+                }
+                if analysis {
+                    white = Box::new(AnalysisWrapper::wrap(white));
+                    black = Box::new(AnalysisWrapper::wrap(black));
+                }
+                if step {
+                    white = Box::new(ManualStep::wrap(white));
+                    black = Box::new(ManualStep::wrap(black));
+                }
+                if timed {
+                    white = Box::new(TimedPlayer::wrap(white));
+                    black = Box::new(TimedPlayer::wrap(black));
+                }
+                if clear {
+                    white = Box::new(PrintBoard::wrap_with(white, true));
+                    black = Box::new(PrintBoard::wrap_with(black, true));
+                }
+                // a remote host decides where the game starts
+                let fen = match (fen.resolve()?, white.start_fen().or(black.start_fen())) {
+                    (Some(fen), Some(remote)) if fen != remote => {
+                        bail!("the other player wants to start from {remote}")
+                    }
+                    (fen, remote) => remote.or(fen),
+                };
+                play(
+                    fen,
+                    moves,
+                    tc,
+                    max_plies,
+                    adjudication,
+                    timeout,
+                    white,
+                    black,
+                    seed,
+                    log_jsonl,
+                    pgn,
+                )
+            }
+            Command::Analyze { fen } => {
+                let board = Board::new(fen.resolve()?)?;
+                analyze::repl(board, io::stdin().lock(), io::stdout())
+            }
+            Command::Perft {
+                fen,
+                depth,
+                backend,
+            } => perft(fen.resolve()?, depth, backend),
+            Command::Tournament {
+                players,
+                games,
+                max_plies,
+                adjudication,
+                timeout,
+                seed,
+                log_jsonl,
+                pgn,
+            } => tournament(
+                players,
+                games,
+                max_plies,
+                adjudication,
+                timeout,
+                seed,
+                log_jsonl,
+                pgn,
+            ),
         },
         None => {
             println!("No command given");
@@ -36,7 +319,7 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
-fn eval(fen: Option<String>) -> anyhow::Result<()> {
+fn eval(fen: Option<String>, backend: Backend) -> anyhow::Result<()> {
     // let fen = "7Q/p1pbkppp/1p2pq2/3p4/2PP4/2P2N2/P3PPPP/R3KB1R b KQ - 0 11";
     // let fen = "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1"; // M1 for white
     // let fen = "7k/8/8/8/8/6q1/5q2/1K6 w - - 0 1"; // M1 for black
@@ -45,33 +328,251 @@ fn eval(fen: Option<String>) -> anyhow::Result<()> {
     let board = Board::new(fen)?;
 
     println!("{board}");
-    let (eval, best_move) = EnginePlayer::evaluate(&board);
+    let start = Instant::now();
+    let params = SearchParams {
+        backend,
+        ..SearchParams::default()
+    };
+    let (eval, best_move) = EnginePlayer::evaluate_with(&board, params)?;
     println!("Eval: {} ({})", eval, best_move);
+    println!("Took {:.2}s", start.elapsed().as_secs_f64());
 
     Ok(())
 }
 
-fn play(fen: Option<String>) -> anyhow::Result<()> {
-    // let white = TerminalPlayer;
-    // let white = RandomPlayer;
-    let white = EnginePlayer;
-    // let white = PrintMoves::wrap(white);
-    let white = PrintBoard::wrap(white);
-    // let white = ManualStep::wrap(white);
-
-    // let black = RandomPlayer;
-    let black = EnginePlayer;
-    // let black = PrintMoves::wrap(black);
-    let black = PrintBoard::wrap(black);
-    // let black = ManualStep::wrap(black);
-
-    let mut game = Game::new(
-        fen,
-        //"7k/8/8/8/8/6q1/5q2/1K6 w - - 0 1", // m1
-        //"7Q/p1pbkppp/1p2pq2/3p4/2PP4/2P2N2/P3PPPP/R3KB1R b KQ - 0 11",
-        //"rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
-        white, black,
-    )?;
+#[allow(clippy::too_many_arguments)]
+fn play(
+    fen: Option<String>,
+    moves: Vec<String>,
+    tc: Option<Clock>,
+    max_plies: Option<usize>,
+    adjudication: AdjudicationArgs,
+    timeout: TimeoutArgs,
+    white: Box<dyn Player>,
+    black: Box<dyn Player>,
+    seed: Option<u64>,
+    log_jsonl: Option<PathBuf>,
+    pgn: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+    let mut game = Game::from_moves_boxed(fen, &moves, white, black)?;
+    game.add_observer(Box::new(ConsoleObserver));
+    if let Some(path) = log_jsonl {
+        let log = JsonlObserver::create(&path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        game.add_observer(Box::new(log));
+    }
+    if let Some(clock) = tc {
+        game.set_clock(clock);
+    }
+    if let Some(max_plies) = max_plies {
+        game.set_max_plies(max_plies);
+    }
+    adjudication.apply(&mut game);
+    timeout.apply(&mut game);
+    if let Some(seed) = seed {
+        game.set_seed(seed);
+    }
     game.start();
+    if let Some(path) = pgn {
+        append_pgn(&path, &game.to_pgn())
+            .with_context(|| format!("could not write {}", path.display()))?;
+    }
+
+    let record = game.record();
+    for (side, times) in [("White", record.times[0]), ("Black", record.times[1])] {
+        if let Some(times) = times {
+            println!("{side} took {times}");
+        }
+    }
     Ok(())
 }
+
+fn perft(fen: Option<String>, depth: usize, backend: Backend) -> anyhow::Result<()> {
+    let board = Board::new(fen.clone())?;
+    let start = Instant::now();
+    let divide = match backend {
+        Backend::Mailbox => board.perft_divide(depth),
+        // the board does not read castling rights and en passant from the FEN
+        Backend::Bitboard => match fen {
+            Some(fen) => BitboardGame::from_fen(&fen)?.perft_divide(depth),
+            None => BitboardGame::from(&board).perft_divide(depth),
+        },
+    };
+    let elapsed = start.elapsed();
+    let mut total = 0;
+    for (mv, nodes) in divide {
+        println!("{}: {nodes}", mv.san(&board));
+        total += nodes;
+    }
+    println!("Total: {total}");
+    println!(
+        "Took {:.2}s, {:.0} nodes/s",
+        elapsed.as_secs_f64(),
+        total as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tournament(
+    players: Vec<String>,
+    games: usize,
+    max_plies: usize,
+    adjudication: AdjudicationArgs,
+    timeout: TimeoutArgs,
+    seed: Option<u64>,
+    log_jsonl: Option<PathBuf>,
+    pgn: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut tournament = Tournament::new(players)?
+        .with_games_per_pair(games)
+        .with_max_plies(max_plies);
+    let plies = adjudication.adjudicate_plies;
+    if let Some(threshold) = adjudication.adjudicate_win {
+        tournament = tournament.with_adjudication(threshold, plies);
+    }
+    if let Some(margin) = adjudication.adjudicate_draw {
+        tournament = tournament.with_draw_adjudication(margin, plies);
+    }
+    if let Some(move_timeout) = timeout.move_timeout {
+        tournament = tournament.with_move_timeout(move_timeout, timeout.timeout_policy);
+    }
+    if let Some(seed) = seed {
+        tournament = tournament.with_seed(seed);
+    }
+    if let Some(path) = log_jsonl {
+        tournament = tournament.with_log(path);
+    }
+    if let Some(path) = pgn {
+        tournament = tournament.with_pgn(path);
+    }
+    let table = tournament.run()?;
+    println!("{table}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fen_args(args: &[&str]) -> FenArgs {
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Eval { fen, .. }) | Some(Command::Play { fen, .. }) => fen,
+            _ => panic!("expected eval or play"),
+        }
+    }
+
+    #[test]
+    fn test_fen_flag() {
+        let fen = "7k/8/8/8/8/6q1/5q2/1K6 w - - 0 1";
+        let args = fen_args(&["chess", "eval", "--fen", fen]);
+        assert_eq!(args.clone().resolve().unwrap(), Some(fen.into()));
+        assert!(!args.fen_stdin);
+
+        let args = fen_args(&["chess", "play", "--fen", fen, "--max-plies", "10"]);
+        assert_eq!(args.resolve().unwrap(), Some(fen.into()));
+
+        // the positional form still works
+        let args = fen_args(&["chess", "eval", fen]);
+        assert_eq!(args.resolve().unwrap(), Some(fen.into()));
+
+        let args = fen_args(&["chess", "eval"]);
+        assert_eq!(args.resolve().unwrap(), None);
+
+        assert!(Cli::try_parse_from(["chess", "eval", fen, "--fen", fen]).is_err());
+        assert!(Cli::try_parse_from(["chess", "eval", "--fen", fen, "--fen-stdin"]).is_err());
+    }
+
+    #[test]
+    fn test_player_flags() {
+        let args = [
+            "chess",
+            "play",
+            "--white",
+            "random",
+            "--black",
+            "engine:depth=2",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Play { white, black, .. }) => {
+                assert_eq!(white, "random");
+                assert_eq!(black, "engine:depth=2");
+            }
+            _ => panic!("expected play"),
+        }
+
+        match Cli::try_parse_from(["chess", "play"]).unwrap().command {
+            Some(Command::Play { white, black, .. }) => {
+                assert_eq!(white, "engine");
+                assert_eq!(black, "engine");
+            }
+            _ => panic!("expected play"),
+        }
+    }
+
+    #[test]
+    fn test_adjudication_flags() {
+        let args = ["chess", "play", "--adjudicate-draw", "10"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Play { adjudication, .. }) => {
+                assert_eq!(adjudication.adjudicate_win, None);
+                assert_eq!(adjudication.adjudicate_draw, Some(10));
+                assert_eq!(adjudication.adjudicate_plies, 8);
+            }
+            _ => panic!("expected play"),
+        }
+
+        let args = [
+            "chess",
+            "tournament",
+            "engine",
+            "random",
+            "--adjudicate-win",
+            "800",
+            "--adjudicate-plies",
+            "4",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Tournament { adjudication, .. }) => {
+                assert_eq!(adjudication.adjudicate_win, Some(800));
+                assert_eq!(adjudication.adjudicate_plies, 4);
+            }
+            _ => panic!("expected tournament"),
+        }
+    }
+    #[test]
+    fn test_timeout_flags() {
+        let args = ["chess", "play", "--move-timeout", "0.5"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Play { timeout, .. }) => {
+                assert_eq!(timeout.move_timeout, Some(Duration::from_millis(500)));
+                assert_eq!(timeout.timeout_policy, TimeoutPolicy::Loss);
+            }
+            _ => panic!("expected play"),
+        }
+
+        let args = [
+            "chess",
+            "tournament",
+            "engine",
+            "random",
+            "--move-timeout",
+            "2",
+            "--timeout-policy",
+            "fallback",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Tournament { timeout, .. }) => {
+                assert_eq!(timeout.move_timeout, Some(Duration::from_secs(2)));
+                assert_eq!(timeout.timeout_policy, TimeoutPolicy::Fallback);
+            }
+            _ => panic!("expected tournament"),
+        }
+
+        for seconds in ["0", "-1", "inf", "NaN", "soon"] {
+            let args = ["chess", "play", "--move-timeout", seconds];
+            assert!(Cli::try_parse_from(args).is_err(), "{seconds}");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    board::Board,
+    pgn::result_tag,
+    pieces::Color,
+    play::{GameResult, PlyRecord},
+    players::Evaluation,
+};
+
+// A move as it was played, for observers
+pub struct MoveEvent<'a> {
+    pub before: &'a Board,
+    pub record: &'a PlyRecord, // the move with its SAN and the mover's evaluation
+    pub after: &'a Board,
+    pub ply: usize, // counted from 1 in this game
+    pub player: &'a str,
+    pub elapsed: Duration,
+}
+
+// Observes a game as it is played, without taking part in it
+pub trait GameObserver {
+    fn on_game_start(&mut self, _fen: &str) {}
+
+    fn on_move(&mut self, _event: &MoveEvent) {}
+
+    fn on_game_end(&mut self, _board: &Board, _result: GameResult) {}
+}
+
+// ConsoleObserver prints the board and every move to stdout
+pub struct ConsoleObserver;
+
+impl GameObserver for ConsoleObserver {
+    fn on_game_start(&mut self, fen: &str) {
+        println!("{fen}");
+        println!();
+    }
+
+    fn on_move(&mut self, event: &MoveEvent) {
+        println!("{}", event.record.mv);
+        println!("{}", event.after);
+        println!("{}", event.after.get_fen());
+        println!();
+    }
+
+    fn on_game_end(&mut self, board: &Board, result: GameResult) {
+        println!("{}", board);
+        println!("Game over: {} after {} moves", result, 1 + board.ply / 2);
+    }
+}
+
+// JsonlObserver appends one JSON object per move to a file, and one with the
+// result when the game ends
+pub struct JsonlObserver {
+    file: File,
+}
+
+// A line of the log for a move
+#[derive(Serialize)]
+struct MoveRecord<'a> {
+    ply: usize,
+    color: &'a str,
+    player: &'a str,
+    fen: &'a str,
+    uci: String,
+    san: &'a str,
+    elapsed_ms: u128,
+    eval: Option<i32>, // in centipawns for the mover, a draw is 0
+    mate: Option<i64>, // plies to mate, negative when the mover gets mated
+}
+
+// The last line of the log
+#[derive(Serialize)]
+struct ResultRecord<'a> {
+    result: &'a str,
+    termination: String,
+    fen: String,
+}
+
+impl JsonlObserver {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonlObserver { file })
+    }
+
+    fn write(&mut self, record: &impl Serialize) {
+        let written = serde_json::to_writer(&mut self.file, record)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(self.file))
+            .and_then(|_| self.file.flush());
+        if let Err(err) = written {
+            eprintln!("Could not write the game log: {err}");
+        }
+    }
+}
+
+impl GameObserver for JsonlObserver {
+    fn on_move(&mut self, event: &MoveEvent) {
+        let color = match event.before.current_turn() {
+            Color::White => "white",
+            Color::Black => "black",
+        };
+        let record = event.record;
+        let (eval, mate) = match record.eval {
+            Some(Evaluation::Eval(eval)) => (Some(eval), None),
+            Some(Evaluation::Draw) => (Some(0), None),
+            Some(Evaluation::Win(plies)) => (None, Some(plies as i64)),
+            Some(Evaluation::Loss(plies)) => (None, Some(-(plies as i64))),
+            None => (None, None),
+        };
+        self.write(&MoveRecord {
+            ply: event.ply,
+            color,
+            player: event.player,
+            fen: &record.fen_before,
+            uci: record.mv.uci(),
+            san: &record.san,
+            elapsed_ms: event.elapsed.as_millis(),
+            eval,
+            mate,
+        });
+    }
+
+    fn on_game_end(&mut self, board: &Board, result: GameResult) {
+        self.write(&ResultRecord {
+            result: result_tag(Some(result)),
+            termination: result.termination().to_string(),
+            fen: board.get_fen(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::play::Game;
+    use crate::players::{EnginePlayer, RandomPlayer};
+
+    #[test]
+    fn test_jsonl_observer() {
+        let path = std::env::temp_dir().join(format!("chess-log-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut game = Game::new(None, EnginePlayer::with_depth(1), RandomPlayer::new()).unwrap();
+        game.set_seed(1);
+        game.set_max_plies(6);
+        game.add_observer(Box::new(JsonlObserver::create(&path).unwrap()));
+        game.start();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 6 + 1);
+
+        let first = &lines[0];
+        assert_eq!(first["ply"], 1);
+        assert_eq!(first["color"], "white");
+        assert_eq!(first["player"], "EnginePlayer depth 1");
+        assert_eq!(first["fen"], Board::new(None).unwrap().get_fen());
+        assert!(first["uci"].is_string() && first["san"].is_string());
+        assert!(first["elapsed_ms"].is_u64());
+        // only the engine evaluates its moves
+        assert!(first["eval"].is_i64() || first["mate"].is_i64());
+        assert!(lines[1]["eval"].is_null() && lines[1]["mate"].is_null());
+        assert_eq!(lines[1]["color"], "black");
+
+        let last = &lines[6];
+        assert_eq!(last["result"], "1/2-1/2");
+        assert_eq!(last["termination"], "move limit");
+        assert!(last["fen"].is_string());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    board::Board,
+    pieces::{Color, Move},
+    play::{GameResult, Termination},
+};
+
+// The descriptive tags of a game, as written to the PGN headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameInfo {
+    pub event: String,
+    pub site: String,
+    pub date: String, // YYYY.MM.DD
+    pub round: String,
+    pub white: Option<String>, // filled in from the player's name if missing
+    pub black: Option<String>,
+}
+
+impl Default for GameInfo {
+    fn default() -> Self {
+        GameInfo {
+            event: "?".into(),
+            site: "?".into(),
+            date: today(),
+            round: "?".into(),
+            white: None,
+            black: None,
+        }
+    }
+}
+
+impl GameInfo {
+    pub fn with_event(self, event: impl Into<String>) -> Self {
+        GameInfo {
+            event: event.into(),
+            ..self
+        }
+    }
+
+    pub fn with_round(self, round: impl Into<String>) -> Self {
+        GameInfo {
+            round: round.into(),
+            ..self
+        }
+    }
+
+    pub fn with_white(self, white: impl Into<String>) -> Self {
+        GameInfo {
+            white: Some(white.into()),
+            ..self
+        }
+    }
+
+    pub fn with_black(self, black: impl Into<String>) -> Self {
+        GameInfo {
+            black: Some(black.into()),
+            ..self
+        }
+    }
+}
+
+// Today's date in UTC, formatted for the PGN Date tag
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // converts days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+pub(crate) fn result_tag(result: Option<GameResult>) -> &'static str {
+    match result.map(|result| result.winner()) {
+        None => "*",
+        Some(Some(Color::White)) => "1-0",
+        Some(Some(Color::Black)) => "0-1",
+        Some(None) => "1/2-1/2",
+    }
+}
+
+// The standard values of the Termination tag
+fn termination_tag(reason: Termination) -> &'static str {
+    match reason {
+        Termination::Timeout => "time forfeit",
+        Termination::Forfeit => "rules infraction",
+        Termination::Adjudication | Termination::DrawByAdjudication | Termination::MaxMoves => {
+            "adjudication"
+        }
+        Termination::Checkmate
+        | Termination::Resignation
+        | Termination::Stalemate
+        | Termination::Repetition
+        | Termination::FiftyMove
+        | Termination::InsufficientMaterial
+        | Termination::Agreement => "normal",
+    }
+}
+
+// Writes the headers and the moves in SAN, played from the given start position
+pub fn write_pgn(
+    info: &GameInfo,
+    white: &str,
+    black: &str,
+    start: &Board,
+    moves: &[Move],
+    result: Option<GameResult>,
+) -> String {
+    let termination = result.map(|result| termination_tag(result.termination()));
+    let result = result_tag(result);
+    let mut pgn = String::new();
+    let mut tag = |name: &str, value: &str| {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+    };
+    tag("Event", &info.event);
+    tag("Site", &info.site);
+    tag("Date", &info.date);
+    tag("Round", &info.round);
+    tag("White", white);
+    tag("Black", black);
+    tag("Result", result);
+    if let Some(termination) = termination {
+        tag("Termination", termination);
+    }
+
+    if Board::new(None).is_ok_and(|initial| initial != *start) {
+        tag("SetUp", "1");
+        tag("FEN", &start.get_fen());
+    }
+    pgn.push('\n');
+
+    let mut board = *start;
+    let mut movetext: Vec<String> = Vec::new();
+    for (i, mv) in moves.iter().enumerate() {
+        let number = board.ply / 2 + 1;
+        match board.current_turn() {
+            Color::White => movetext.push(format!("{number}.")),
+            Color::Black if i == 0 => movetext.push(format!("{number}...")),
+            Color::Black => {}
+        }
+        movetext.push(mv.san(&board));
+        board = board.apply(mv);
+    }
+    movetext.push(result.into());
+
+    pgn.push_str(&movetext.join(" "));
+    pgn.push('\n');
+    pgn
+}
+
+// Adds a game to the end of a PGN file, with a blank line after it
+pub fn append_pgn(path: impl AsRef<Path>, pgn: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{pgn}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_termination_tag() {
+        let board = Board::new(None).unwrap();
+        let info = GameInfo::default();
+        let termination = |result| {
+            let pgn = write_pgn(&info, "Alice", "Bob", &board, &[], Some(result));
+            let line = pgn.lines().find(|line| line.starts_with("[Termination "));
+            line.map(String::from)
+        };
+
+        let result = GameResult::Win(Color::White, Termination::Checkmate);
+        assert_eq!(termination(result).unwrap(), "[Termination \"normal\"]");
+        let result = GameResult::Win(Color::Black, Termination::Timeout);
+        assert_eq!(
+            termination(result).unwrap(),
+            "[Termination \"time forfeit\"]"
+        );
+        let result = GameResult::Win(Color::White, Termination::Forfeit);
+        assert_eq!(
+            termination(result).unwrap(),
+            "[Termination \"rules infraction\"]"
+        );
+        let result = GameResult::Win(Color::Black, Termination::Adjudication);
+        assert_eq!(
+            termination(result).unwrap(),
+            "[Termination \"adjudication\"]"
+        );
+        let result = GameResult::Draw(Termination::MaxMoves);
+        assert_eq!(
+            termination(result).unwrap(),
+            "[Termination \"adjudication\"]"
+        );
+        let result = GameResult::Draw(Termination::Repetition);
+        assert_eq!(termination(result).unwrap(), "[Termination \"normal\"]");
+
+        // an unfinished game has no termination
+        let pgn = write_pgn(&info, "Alice", "Bob", &board, &[], None);
+        assert!(!pgn.contains("[Termination "));
+    }
+
+    #[test]
+    fn test_today() {
+        let date = today();
+        assert_eq!(date.len(), 10);
+        assert!(date.as_str() > "2024.01.01");
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let board = Board::new(None).unwrap();
+        let e4 = board.parse_move("e2e4").unwrap();
+        let after = board.apply(&e4);
+        let e5 = after.parse_move("e7e5").unwrap();
+
+        let info = GameInfo {
+            date: "2025.01.31".into(),
+            ..GameInfo::default()
+        };
+        let pgn = write_pgn(&info, "Alice", "Bob", &board, &[e4, e5], None);
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"2025.01.31\"]\n[Round \"?\"]\n\
+             [White \"Alice\"]\n[Black \"Bob\"]\n[Result \"*\"]\n\n1. e4 e5 *\n"
+        );
+
+        // starting with black to move needs the position and an ellipsis
+        let pgn = write_pgn(&info, "Alice", "Bob", &after, &[e5], None);
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", after.get_fen())));
+        assert!(pgn.ends_with("1... e5 *\n"));
+    }
+}
@@ -2,7 +2,7 @@ use std::{fmt::Display, ops::Not};
 
 use crate::board::{Board, Position};
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum Color {
     White,
@@ -20,7 +20,7 @@ impl Not for Color {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
 #[repr(u8)]
 pub enum PieceType {
     King,
@@ -38,7 +38,7 @@ pub struct Piece {
     pub most_recent_move: Option<usize>,
 }
 
-const ALL_DIRECTIONS: [(i32, i32); 8] = [
+pub const ALL_DIRECTIONS: [(i32, i32); 8] = [
     (1, 0),
     (-1, 0),
     (0, 1),
@@ -49,9 +49,9 @@ const ALL_DIRECTIONS: [(i32, i32); 8] = [
     (-1, 1),
 ];
 
-const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
-const STRAIGHTS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
-const KNIGHT_MOVES: [(i32, i32); 8] = [
+pub const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+pub const STRAIGHTS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const KNIGHT_MOVES: [(i32, i32); 8] = [
     (2, 1),
     (-2, 1),
     (1, 2),
@@ -62,6 +62,35 @@ const KNIGHT_MOVES: [(i32, i32); 8] = [
     (-1, -2),
 ];
 
+// Everything about pawn moves that depends on the color, with ranks counted from 0
+// at white's back rank
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PawnInfo {
+    pub direction: i32,    // the rank offset of a step forward
+    pub start_rank: usize, // from where the pawn may step two squares
+    pub promotion_rank: usize,
+    pub en_passant_rank: usize, // where the pawn stands when capturing en passant
+}
+
+impl PawnInfo {
+    pub fn for_color(color: Color) -> Self {
+        match color {
+            Color::White => PawnInfo {
+                direction: 1,
+                start_rank: 1,
+                promotion_rank: 7,
+                en_passant_rank: 4,
+            },
+            Color::Black => PawnInfo {
+                direction: -1,
+                start_rank: 6,
+                promotion_rank: 0,
+                en_passant_rank: 3,
+            },
+        }
+    }
+}
+
 impl Piece {
     pub fn get_moves(&self, board: &Board, position: &Position) -> Vec<Move> {
         match self.typ {
@@ -74,11 +103,48 @@ impl Piece {
         }
     }
 
+    // The squares this piece attacks, whether they are empty or occupied by either color
+    pub fn attacks(&self, board: &Board, position: &Position) -> Vec<Position> {
+        match self.typ {
+            PieceType::Pawn => {
+                // unlike moves_pawn, the diagonals are attacked even when empty
+                let up = PawnInfo::for_color(self.color).direction;
+                [position.offset(-1, up), position.offset(1, up)]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            PieceType::Knight => KNIGHT_MOVES
+                .into_iter()
+                .filter_map(|(f, r)| position.offset(f, r))
+                .collect(),
+            PieceType::King => ALL_DIRECTIONS
+                .into_iter()
+                .filter_map(|(f, r)| position.offset(f, r))
+                .collect(),
+            PieceType::Bishop => Self::attacks_sliding(board, position, &DIAGONALS),
+            PieceType::Rook => Self::attacks_sliding(board, position, &STRAIGHTS),
+            PieceType::Queen => Self::attacks_sliding(board, position, &ALL_DIRECTIONS),
+        }
+    }
+
+    // Slides in each direction up to and including the first blocker
+    fn attacks_sliding(board: &Board, from: &Position, offsets: &[(i32, i32)]) -> Vec<Position> {
+        let mut attacks = Vec::new();
+        for (file, rank) in offsets {
+            for pos in from.iterate_offset(*file, *rank) {
+                attacks.push(pos);
+                if board[pos].is_some() {
+                    break;
+                }
+            }
+        }
+        attacks
+    }
+
     fn moves_pawn(&self, board: &Board, pawn: &Position) -> Vec<Move> {
-        let up = match self.color {
-            Color::Black => -1,
-            Color::White => 1,
-        };
+        let info = PawnInfo::for_color(self.color);
+        let up = info.direction;
         let mut moves = vec![];
 
         // normal move
@@ -88,7 +154,7 @@ impl Piece {
 
                 // starting move
                 if let Some(mv) = pawn.offset(0, 2 * up) {
-                    if self.most_recent_move.is_none() && board[mv].is_none() {
+                    if pawn.rank() == info.start_rank && board[mv].is_none() {
                         moves.push(mv);
                     }
                 }
@@ -109,29 +175,28 @@ impl Piece {
 
         let mut moves: Vec<Move> = moves
             .into_iter()
-            .map(|to| {
-                let special = if to.rank() == 7 || to.rank() == 0 {
-                    // TODO: other promotions
-                    Some(SpecialMove::Promotion(PieceType::Queen))
+            .flat_map(|to| {
+                if to.rank() == info.promotion_rank {
+                    let captured = board[to].map(|piece| piece.typ);
+                    PROMOTIONS
+                        .map(|typ| Move {
+                            from: *pawn,
+                            to,
+                            special: Some(SpecialMove::Promotion(typ, captured)),
+                        })
+                        .to_vec()
                 } else {
-                    self.is_capture(board, &to)
-                };
-
-                Move {
-                    from: *pawn,
-                    to,
-                    special,
+                    vec![Move {
+                        from: *pawn,
+                        to,
+                        special: self.is_capture(board, &to),
+                    }]
                 }
             })
             .collect();
 
-        // en passant
-        let enpassant_rank = match self.color {
-            Color::White => 4, // 0-indexed
-            Color::Black => 2,
-        };
-        // if we are on the fifth or third rank...
-        if pawn.rank() == enpassant_rank {
+        // en passant: if we are on the fifth rank, from our side...
+        if pawn.rank() == info.en_passant_rank {
             // and either square next to us...
             for file_offset in vec![-1, 1] {
                 if let Some(pos) = pawn.offset(file_offset, 0) {
@@ -139,13 +204,12 @@ impl Piece {
                     if let Some(piece) = board[pos] {
                         // by a pawn of the opposite color...
                         if piece.color == !self.color && piece.typ == PieceType::Pawn {
-                            // who just moved...
-                            if piece
-                                .most_recent_move
-                                .is_some_and(|ply| ply == board.ply - 1)
-                            {
+                            // who just moved two squares...
+                            if board.last_move.is_some_and(|mv| {
+                                mv.to == pos && mv.from.rank().abs_diff(pos.rank()) == 2
+                            }) {
                                 // and the target square...
-                                if let Some(to) = pawn.offset(-1, up) {
+                                if let Some(to) = pawn.offset(file_offset, up) {
                                     // is empty...
                                     if board[to].is_none() {
                                         // we can capture en passant
@@ -232,7 +296,7 @@ impl Piece {
     }
 
     fn is_capture(&self, board: &Board, pos: &Position) -> Option<SpecialMove> {
-        board[pos].map(|p| SpecialMove::Capture(p.typ))
+        board[pos].map(|p| SpecialMove::Capture(p.typ, *pos))
     }
 
     fn slide_helper(&self, board: &Board, from: &Position, offsets: Vec<(i32, i32)>) -> Vec<Move> {
@@ -269,31 +333,96 @@ impl Piece {
     }
 }
 
+impl PieceType {
+    pub fn san_letter(&self) -> char {
+        match self {
+            PieceType::King => 'K',
+            PieceType::Queen => 'Q',
+            PieceType::Rook => 'R',
+            PieceType::Knight => 'N',
+            PieceType::Bishop => 'B',
+            PieceType::Pawn => 'P',
+        }
+    }
+
+    pub fn from_san_letter(letter: char) -> Option<PieceType> {
+        match letter {
+            'K' => Some(PieceType::King),
+            'Q' => Some(PieceType::Queen),
+            'R' => Some(PieceType::Rook),
+            'N' => Some(PieceType::Knight),
+            'B' => Some(PieceType::Bishop),
+            _ => None,
+        }
+    }
+}
+
+impl Piece {
+    // The letter of the piece in a FEN, upper case for white
+    pub fn fen_char(&self) -> char {
+        let letter = self.typ.san_letter();
+        match self.color {
+            Color::White => letter,
+            Color::Black => letter.to_ascii_lowercase(),
+        }
+    }
+
+    // The piece for a letter of a FEN, not having moved yet
+    pub fn from_char(ch: char) -> Option<Piece> {
+        let typ = match ch.to_ascii_uppercase() {
+            'P' => PieceType::Pawn,
+            letter => PieceType::from_san_letter(letter)?,
+        };
+        let color = if ch.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(Piece {
+            color,
+            typ,
+            most_recent_move: None,
+        })
+    }
+
+    pub fn unicode(&self) -> char {
+        match (self.color, self.typ) {
+            (Color::White, PieceType::King) => '♔',
+            (Color::White, PieceType::Queen) => '♕',
+            (Color::White, PieceType::Rook) => '♖',
+            (Color::White, PieceType::Bishop) => '♗',
+            (Color::White, PieceType::Knight) => '♘',
+            (Color::White, PieceType::Pawn) => '♙',
+            (Color::Black, PieceType::King) => '♚',
+            (Color::Black, PieceType::Queen) => '♛',
+            (Color::Black, PieceType::Rook) => '♜',
+            (Color::Black, PieceType::Knight) => '♞',
+            (Color::Black, PieceType::Bishop) => '♝',
+            (Color::Black, PieceType::Pawn) => '♟',
+        }
+    }
+}
+
 impl Display for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let text = match (self.color, self.typ) {
-            (Color::White, PieceType::King) => "♔",
-            (Color::White, PieceType::Queen) => "♕",
-            (Color::White, PieceType::Rook) => "♖",
-            (Color::White, PieceType::Bishop) => "♗",
-            (Color::White, PieceType::Knight) => "♘",
-            (Color::White, PieceType::Pawn) => "♙",
-            (Color::Black, PieceType::King) => "♚",
-            (Color::Black, PieceType::Queen) => "♛",
-            (Color::Black, PieceType::Rook) => "♜",
-            (Color::Black, PieceType::Knight) => "♞",
-            (Color::Black, PieceType::Bishop) => "♝",
-            (Color::Black, PieceType::Pawn) => "♟",
-        };
-        write!(f, "{text}")
+        write!(f, "{}", self.unicode())
     }
 }
 
+// What a pawn can promote to, the queen first as the usual choice
+pub const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SpecialMove {
-    Capture(PieceType),           // captures a piece
+    Capture(PieceType, Position), // type and position of the captured piece
     EnPassant(Position),          // position of the pawn that is captured
-    Promotion(PieceType),         // type of piece to promote to
+    // type of piece to promote to, and of the piece captured on the destination
+    Promotion(PieceType, Option<PieceType>),
     Castling(Position, Position), // start and end position of the rook
 }
 
@@ -304,15 +433,120 @@ pub struct Move {
     pub special: Option<SpecialMove>,
 }
 
-impl Move {}
+impl Move {
+    // The type and position of the captured piece, if any
+    pub fn captured(&self) -> Option<(PieceType, Position)> {
+        match self.special {
+            Some(SpecialMove::Capture(typ, pos)) => Some((typ, pos)),
+            Some(SpecialMove::EnPassant(pos)) => Some((PieceType::Pawn, pos)),
+            Some(SpecialMove::Promotion(_, Some(typ))) => Some((typ, self.to)),
+            _ => None,
+        }
+    }
+
+    // The type of the piece that makes the move on the given board, None when its
+    // square is empty there
+    pub fn moving_piece(&self, board: &Board) -> Option<PieceType> {
+        board[self.from].map(|piece| piece.typ)
+    }
+
+    // The move in the long algebraic notation of UCI, e.g. "e2e4" or "e7e8q"
+    pub fn uci(&self) -> String {
+        match self.special {
+            Some(SpecialMove::Promotion(typ, _)) => format!(
+                "{}{}{}",
+                self.from,
+                self.to,
+                typ.san_letter().to_ascii_lowercase()
+            ),
+            _ => format!("{}{}", self.from, self.to),
+        }
+    }
+
+    // The SAN with the move number, e.g. "12. Nf3" or "12... Nf6"
+    pub fn numbered_san(&self, board: &Board) -> String {
+        let number = board.ply / 2 + 1;
+        match board.current_turn() {
+            Color::White => format!("{number}. {}", self.san(board)),
+            Color::Black => format!("{number}... {}", self.san(board)),
+        }
+    }
+
+    // Standard algebraic notation, e.g. "Nbd2" or "exd5+", for this move on the given board
+    pub fn san(&self, board: &Board) -> String {
+        let Some(piece) = board[self.from] else {
+            return format!("{}{}", self.from, self.to);
+        };
+
+        let mut san = match self.special {
+            Some(SpecialMove::Castling(_, _)) if self.to.file() > self.from.file() => "O-O".into(),
+            Some(SpecialMove::Castling(_, _)) => "O-O-O".into(),
+            _ => {
+                let mut san = String::new();
+                let capture = board[self.to].is_some()
+                    || matches!(self.special, Some(SpecialMove::EnPassant(_)));
+                if piece.typ == PieceType::Pawn {
+                    if capture {
+                        san.push_str(&self.from.to_string()[..1]);
+                    }
+                } else {
+                    san.push(piece.typ.san_letter());
+                    san.push_str(&self.disambiguation(board, &piece));
+                }
+                if capture {
+                    san.push('x');
+                }
+                san.push_str(&self.to.to_string());
+                if let Some(SpecialMove::Promotion(typ, _)) = self.special {
+                    san.push('=');
+                    san.push(typ.san_letter());
+                }
+                san
+            }
+        };
+
+        if board.gives_check(self) {
+            san.push('+');
+        }
+        san
+    }
+
+    // The file, rank or both of the origin square, when another piece of the
+    // same type could also move to the destination
+    fn disambiguation(&self, board: &Board, piece: &Piece) -> String {
+        let others: Vec<Position> = board
+            .get_pieces(piece.color)
+            .into_iter()
+            .filter(|(pos, other)| *pos != self.from && other.typ == piece.typ)
+            .filter(|(pos, other)| {
+                other
+                    .get_moves(board, pos)
+                    .iter()
+                    .any(|mv| mv.to == self.to)
+            })
+            .map(|(pos, _)| pos)
+            .collect();
+
+        let from = self.from.to_string();
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|pos| pos.file() != self.from.file()) {
+            from[..1].into()
+        } else if others.iter().all(|pos| pos.rank() != self.from.rank()) {
+            from[1..].into()
+        } else {
+            from
+        }
+    }
+}
 
 impl Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} -> {}", self.from, self.to)?;
         self.special.map(|sp| match sp {
-            SpecialMove::Capture(_) => write!(f, " (capture)"),
+            SpecialMove::Capture(_, _) => write!(f, " (capture)"),
             SpecialMove::EnPassant(_) => write!(f, " (en passant)"),
-            SpecialMove::Promotion(_) => write!(f, " (promotion)"),
+            SpecialMove::Promotion(_, _) => write!(f, " (promotion)"),
             SpecialMove::Castling(_, _) => write!(f, " (castle)"),
         });
         Ok(())
@@ -326,8 +560,8 @@ impl Ord for Move {
             (None, Some(_)) => std::cmp::Ordering::Greater,
             (Some(_), None) => std::cmp::Ordering::Less,
             (Some(sp1), Some(sp2)) => match (sp1, sp2) {
-                (SpecialMove::Capture(p1), SpecialMove::Capture(p2)) => p1.cmp(&p2),
-                (SpecialMove::Capture(_), _) => std::cmp::Ordering::Greater,
+                (SpecialMove::Capture(p1, _), SpecialMove::Capture(p2, _)) => p1.cmp(&p2),
+                (SpecialMove::Capture(_, _), _) => std::cmp::Ordering::Greater,
                 (_, _) => std::cmp::Ordering::Equal,
             },
         }
@@ -339,3 +573,242 @@ impl PartialOrd for Move {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fen_char() {
+        let types = [
+            PieceType::King,
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Pawn,
+        ];
+        for color in [Color::White, Color::Black] {
+            for typ in types {
+                let piece = Piece {
+                    color,
+                    typ,
+                    most_recent_move: None,
+                };
+                assert_eq!(Piece::from_char(piece.fen_char()), Some(piece));
+            }
+        }
+        assert_eq!(Piece::from_char('x'), None);
+        assert_eq!(Piece::from_char('1'), None);
+    }
+
+    #[test]
+    fn test_moving_piece() {
+        let board = Board::new(Some("4k3/1P6/8/3pP3/8/8/8/4K2R w K d6 0 2".into())).unwrap();
+        let moving = |san: &str| board.parse_san(san).unwrap().moving_piece(&board);
+        assert_eq!(moving("Kd2"), Some(PieceType::King));
+        assert_eq!(moving("O-O"), Some(PieceType::King));
+        assert_eq!(moving("Rh8+"), Some(PieceType::Rook));
+        assert_eq!(moving("b8=Q+"), Some(PieceType::Pawn));
+        assert_eq!(moving("e6"), Some(PieceType::Pawn));
+
+        // the piece has left the square once the move is made
+        let mv = board.parse_san("Kd2").unwrap();
+        assert_eq!(mv.moving_piece(&board.apply(&mv)), None);
+
+        // every generated move, from any square
+        let board = Board::new(Some(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".into(),
+        ))
+        .unwrap();
+        for mv in board.legal_moves(Color::White) {
+            let piece = board
+                .get_pieces(Color::White)
+                .into_iter()
+                .find(|(pos, _)| *pos == mv.from);
+            assert_eq!(mv.moving_piece(&board), piece.map(|(_, piece)| piece.typ));
+        }
+    }
+
+    #[test]
+    fn test_numbered_san() {
+        let board = Board::new(None).unwrap();
+        let e4 = board.parse_move("e2e4").unwrap();
+        assert_eq!(e4.numbered_san(&board), "1. e4");
+        let board = board.apply(&e4);
+        assert_eq!(
+            board.parse_move("e7e5").unwrap().numbered_san(&board),
+            "1... e5"
+        );
+
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 12";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let bb5 = board.parse_move("f1b5").unwrap();
+        assert_eq!(bb5.numbered_san(&board), "12. Bb5");
+        let board = board.apply(&bb5);
+        let d6 = board.parse_move("d7d6").unwrap();
+        assert_eq!(d6.numbered_san(&board), "12... d6");
+    }
+
+    #[test]
+    fn test_pawn_info() {
+        let white = PawnInfo::for_color(Color::White);
+        assert_eq!(white.direction, 1);
+        assert_eq!(white.start_rank, 1); // the second rank
+        assert_eq!(white.promotion_rank, 7);
+        assert_eq!(white.en_passant_rank, 4); // the fifth rank
+
+        let black = PawnInfo::for_color(Color::Black);
+        assert_eq!(black.direction, -1);
+        assert_eq!(black.start_rank, 6); // the seventh rank
+        assert_eq!(black.promotion_rank, 0);
+        assert_eq!(black.en_passant_rank, 3); // the fourth rank
+
+        // the same ranks, found by playing moves
+        let board = Board::new(None).unwrap();
+        let board = board.apply_san("e4").unwrap().apply_san("Nf6").unwrap();
+        let board = board.apply_san("e5").unwrap().apply_san("d5").unwrap();
+        assert!(board.apply_san("exd6").is_ok());
+        let board = Board::new(None).unwrap();
+        let board = board.apply_san("Nf3").unwrap().apply_san("e5").unwrap();
+        let board = board.apply_san("Ng1").unwrap().apply_san("e4").unwrap();
+        let board = board.apply_san("d4").unwrap();
+        assert!(board.apply_san("exd3").is_ok());
+    }
+
+    #[test]
+    fn test_double_step_only_from_start() {
+        // pawns set up by FEN have not moved, but may only step twice from their start
+        let board = Board::new(Some("4k3/8/3p4/8/4P3/8/8/4K3 w - - 0 1".into())).unwrap();
+        assert!(board.parse_move("e4e6").is_err());
+        let board = Board::new(Some("4k3/8/3p4/8/4P3/8/8/4K3 b - - 0 1".into())).unwrap();
+        assert!(board.parse_move("d6d4").is_err());
+        assert!(board.parse_move("d6d5").is_ok());
+    }
+
+    #[test]
+    fn test_pawn_attacks() {
+        let fen = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+
+        // one diagonal is occupied by a black pawn, the other is empty
+        let pawn = board[b"e4"].unwrap();
+        let attacks = pawn.attacks(&board, &b"e4".into());
+        assert_eq!(attacks.len(), 2);
+        assert!(attacks.contains(&b"d5".into()));
+        assert!(attacks.contains(&b"f5".into()));
+
+        let pawn = board[b"d5"].unwrap();
+        let attacks = pawn.attacks(&board, &b"d5".into());
+        assert_eq!(attacks.len(), 2);
+        assert!(attacks.contains(&b"c4".into()));
+        assert!(attacks.contains(&b"e4".into()));
+    }
+
+    #[test]
+    fn test_sliding_attacks() {
+        let fen = "4k3/8/8/8/1p6/8/8/R3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let rook = board[b"a1"].unwrap();
+        let attacks = rook.attacks(&board, &b"a1".into());
+
+        // the rook stops at its own king, but still defends it
+        assert!(attacks.contains(&b"e1".into()));
+        assert!(!attacks.contains(&b"f1".into()));
+        assert!(attacks.contains(&b"a8".into()));
+        assert_eq!(attacks.len(), 11);
+    }
+
+    #[test]
+    fn test_captured() {
+        let fen = "4k3/5p2/8/3pP3/8/2N5/8/4K3 b - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let board = board.apply(&Move {
+            from: b"f7".into(),
+            to: b"f5".into(),
+            special: None,
+        });
+
+        let knight = board.get_moves(&b"c3".into()).unwrap();
+        let capture = knight.iter().find(|mv| mv.to == b"d5".into()).unwrap();
+        assert_eq!(capture.captured(), Some((PieceType::Pawn, b"d5".into())));
+
+        let pawn = board.get_moves(&b"e5".into()).unwrap();
+        let enpassant = pawn.iter().find(|mv| mv.to == b"f6".into()).unwrap();
+        assert_eq!(enpassant.captured(), Some((PieceType::Pawn, b"f5".into())));
+        assert!(board.apply(enpassant)[b"f5"].is_none());
+
+        let quiet = pawn.iter().find(|mv| mv.to == b"e6".into()).unwrap();
+        assert_eq!(quiet.captured(), None);
+
+        // a promotion that captures knows what it takes, one that does not takes nothing
+        let board = Board::new(Some("3rk3/4P3/8/8/8/8/8/K7 w - - 0 1".into())).unwrap();
+        let pawn = board.get_moves(&b"e7".into()).unwrap();
+        let capture = pawn.iter().find(|mv| mv.to == b"d8".into()).unwrap();
+        assert_eq!(capture.captured(), Some((PieceType::Rook, b"d8".into())));
+        let board = Board::new(Some("3r4/4P3/8/8/8/8/8/k3K3 w - - 0 1".into())).unwrap();
+        let pawn = board.get_moves(&b"e7".into()).unwrap();
+        let push = pawn.iter().find(|mv| mv.to == b"e8".into()).unwrap();
+        assert_eq!(push.captured(), None);
+    }
+
+    #[test]
+    fn test_enpassant_only_after_double_step() {
+        let fen = "4k3/8/5p2/4P3/8/8/8/4K3 b - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let board = board.apply(&board.parse_move("f6f5").unwrap());
+
+        let pawn = board.get_moves(&b"e5".into()).unwrap();
+        assert!(pawn.iter().all(|mv| mv.to != b"f6".into()));
+    }
+
+    fn san(fen: &str, from: &[u8; 2], to: &[u8; 2]) -> String {
+        let board = Board::new(Some(fen.into())).unwrap();
+        let mv = board
+            .get_moves(&from.into())
+            .unwrap()
+            .into_iter()
+            .find(|mv| mv.to == to.into())
+            .unwrap();
+        mv.san(&board)
+    }
+
+    #[test]
+    fn test_san() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/3PP3/8/PPP2PPP/RNBQKBNR b KQkq - 0 2";
+        assert_eq!(san(fen, b"e5", b"d4"), "exd4");
+        assert_eq!(san(fen, b"g8", b"f6"), "Nf6");
+        assert_eq!(san(fen, b"f8", b"b4"), "Bb4+");
+
+        let fen = "4k3/1P6/8/8/8/8/8/4K2R w - - 0 1";
+        assert_eq!(san(fen, b"b7", b"b8"), "b8=Q+");
+        assert_eq!(san(fen, b"e1", b"g1"), "O-O");
+
+        // a promotion that captures
+        let fen = "3r4/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        assert_eq!(san(fen, b"e7", b"d8"), "exd8=Q");
+        assert_eq!(san(fen, b"e7", b"e8"), "e8=Q");
+
+        // en passant lands on an empty square
+        let board = Board::new(Some("4k3/5p2/8/4P3/8/8/8/4K3 b - - 0 1".into())).unwrap();
+        let board = board.apply(&board.parse_move("f7f5").unwrap());
+        assert_eq!(board.parse_move("e5f6").unwrap().san(&board), "exf6");
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        // file: the knights are on different files
+        let fen = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(san(fen, b"b1", b"d2"), "Nbd2");
+
+        // rank: the knights share a file
+        let fen = "4k3/8/8/8/8/1N6/8/1N2K3 w - - 0 1";
+        assert_eq!(san(fen, b"b1", b"d2"), "N1d2");
+
+        // both: another queen shares the file, and yet another the rank
+        let fen = "7K/8/k7/8/4Q2Q/8/8/7Q w - - 0 1";
+        assert_eq!(san(fen, b"h4", b"e1"), "Qh4e1");
+        assert_eq!(san(fen, b"e4", b"e1"), "Qee1");
+        assert_eq!(san(fen, b"h1", b"e1"), "Q1e1");
+    }
+}
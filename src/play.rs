@@ -1,87 +1,462 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Write},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    board::Board,
-    pieces::{Color, Move, PieceType},
-    players::Player,
+    board::{Board, FIFTY_MOVE_PLIES},
+    clock::Clock,
+    observer::{GameObserver, MoveEvent},
+    pgn::{write_pgn, GameInfo},
+    pieces::{Color, Move},
+    players::{Evaluation, MoveRequest, MoveTimes, Player, PlayerAction},
 };
 
+// How a game came to an end
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate, // the king was captured
+    Resignation,
+    Timeout,
+    Forfeit, // too many illegal moves
+    Stalemate,
+    Repetition,
+    FiftyMove,
+    InsufficientMaterial, // the flag fell, but the opponent could not have won
+    Adjudication,         // decided on the evaluation
+    DrawByAdjudication,   // the evaluations stayed even late in the game
+    MaxMoves,
+    Agreement, // a draw offer was accepted
+}
+
+impl Display for Termination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Resignation => "resignation",
+            Termination::Timeout => "time",
+            Termination::Forfeit => "forfeit",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "repetition",
+            Termination::FiftyMove => "50-move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Adjudication | Termination::DrawByAdjudication => "adjudication",
+            Termination::MaxMoves => "move limit",
+            Termination::Agreement => "agreement",
+        };
+        write!(f, "{text}")
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum GameResult {
-    WhiteWin,
-    DrawByRepetition,
-    DrawBy50MoveRule,
-    BlackWin,
+    Win(Color, Termination), // the winner
+    Draw(Termination),
+}
+
+impl GameResult {
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            GameResult::Win(color, _) => Some(*color),
+            GameResult::Draw(_) => None,
+        }
+    }
+
+    pub fn is_draw(&self) -> bool {
+        matches!(self, GameResult::Draw(_))
+    }
+
+    pub fn termination(&self) -> Termination {
+        match self {
+            GameResult::Win(_, reason) | GameResult::Draw(reason) => *reason,
+        }
+    }
+
+    // The points for the given color: 1 for a win, 0.5 for a draw and 0 for a loss
+    pub fn score(&self, perspective: Color) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == perspective => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
 }
 
 impl Display for GameResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            GameResult::WhiteWin => write!(f, "White won")?,
-            GameResult::DrawByRepetition => write!(f, "Draw by repetition")?,
-            GameResult::DrawBy50MoveRule => write!(f, "Draw by 50-move rule")?,
-            GameResult::BlackWin => write!(f, "Black won")?,
+            GameResult::Win(Color::White, Termination::Timeout) => write!(f, "White won on time"),
+            GameResult::Win(Color::Black, Termination::Timeout) => write!(f, "Black won on time"),
+            GameResult::Win(Color::White, reason) => write!(f, "White won by {reason}"),
+            GameResult::Win(Color::Black, reason) => write!(f, "Black won by {reason}"),
+            GameResult::Draw(reason) => write!(f, "Draw by {reason}"),
         }
-        Ok(())
     }
 }
 
-pub struct Game<W, B>
-where
-    W: Player,
-    B: Player,
-{
+// A finished (or ongoing) game, detached from the players who played it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub info: GameInfo,
+    pub white: String,
+    pub black: String,
+    pub start: Board,
+    pub moves: Vec<Move>,
+    pub result: Option<GameResult>,
+    pub hints: [usize; 2], // how often each color asked an engine for help
+    pub times: [Option<MoveTimes>; 2], // for players wrapped in TimedPlayer
+}
+
+impl GameRecord {
+    pub fn to_pgn(&self) -> String {
+        write_pgn(
+            &self.info,
+            &self.white,
+            &self.black,
+            &self.start,
+            &self.moves,
+            self.result,
+        )
+    }
+}
+
+// One ply of a game, as kept for analysis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlyRecord {
+    pub fen_before: String,
+    pub mv: Move,
+    pub san: String,
+    pub eval: Option<Evaluation>, // the mover's own evaluation, if it gave one
+}
+
+impl PlyRecord {
+    // The move as played on the board before it
+    pub fn new(before: &Board, mv: Move, eval: Option<Evaluation>) -> Self {
+        PlyRecord {
+            fen_before: before.get_fen(),
+            mv,
+            san: mv.san(before),
+            eval,
+        }
+    }
+}
+
+pub type MoveCallback = Box<dyn FnMut(&Board, &Move, Color)>;
+pub type ResultCallback = Box<dyn FnMut(&Board, GameResult)>;
+
+// How many illegal moves a player may make in a row before forfeiting
+const DEFAULT_MAX_RETRIES: usize = 3;
+
+// What happens when a player takes longer than the move timeout
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TimeoutPolicy {
+    Fallback, // play the first legal move instead
+    Loss,
+}
+
+// A player in a game. With a move timeout, the player thinks on a separate thread
+// that may still be running after the game has moved on.
+struct Seat {
+    player: Arc<Mutex<Box<dyn Player>>>,
+    name: String,
+}
+
+impl Seat {
+    fn new(player: Box<dyn Player>) -> Self {
+        Seat {
+            name: player.name(),
+            player: Arc::new(Mutex::new(player)),
+        }
+    }
+
+    // The player's action, or why it did not arrive: the timeout passed, or the
+    // player's thread panicked
+    fn make_move(
+        &self,
+        request: &MoveRequest,
+        timeout: Option<Duration>,
+    ) -> Result<PlayerAction, RecvTimeoutError> {
+        let Some(timeout) = timeout else {
+            return Ok(self.player.lock().unwrap().make_move(request));
+        };
+
+        let player = self.player.clone();
+        let (board, color, clock) = (*request.board, request.color, request.clock.copied());
+        let (repetitions, seed) = (request.repetitions.cloned(), request.seed);
+        let history = request
+            .history
+            .map(|(start, moves)| (*start, moves.to_vec()));
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut request = MoveRequest::new(&board).with_color(color);
+            if let Some(seed) = seed {
+                request = request.with_seed(seed);
+            }
+            if let Some(clock) = clock.as_ref() {
+                request = request.with_clock(clock);
+            }
+            if let Some(repetitions) = repetitions.as_ref() {
+                request = request.with_repetitions(repetitions);
+            }
+            if let Some((start, moves)) = history.as_ref() {
+                request = request.with_history(start, moves);
+            }
+            let action = player.lock().unwrap().make_move(&request);
+            // the game no longer listens if the move came too late
+            let _ = sender.send(action);
+        });
+
+        receiver.recv_timeout(timeout)
+    }
+
+    // None while the player is still thinking about a move that timed out
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.try_lock().ok()?.last_eval()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player
+            .try_lock()
+            .map_or(0, |player| player.hints_used())
+    }
+
+    // A player still thinking about a move that timed out can not accept
+    fn accepts_draw(&self, request: &MoveRequest) -> bool {
+        self.player
+            .try_lock()
+            .is_ok_and(|mut player| player.accepts_draw(request))
+    }
+
+    fn game_over(&self, request: &MoveRequest, result: GameResult) {
+        if let Ok(mut player) = self.player.try_lock() {
+            player.game_over(request, result);
+        }
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.try_lock().ok()?.move_times()
+    }
+}
+
+pub struct Game {
     board: Board,
-    previous_states: Vec<String>,
-    white: W,
-    black: B,
+    repetitions: HashMap<u64, u8>, // how often each position has occurred
+    history: Vec<Move>,
+    evals: Vec<Option<Evaluation>>, // for each move in the history
+    white: Seat,
+    black: Seat,
+    clock: Option<Clock>,
+    time_control: Option<Clock>, // the clock as it was before the game
+    start: Board,
+    max_plies: Option<usize>,
+    max_retries: usize,
+    adjudication: Option<(i32, usize)>, // eval threshold and for how many plies
+    draw_adjudication: Option<(i32, usize)>, // eval margin around 0 and for how many plies
+    move_timeout: Option<(Duration, TimeoutPolicy)>,
+    rngs: Option<[StdRng; 2]>, // per color, for seeding the players' random choices
+    on_move: Option<MoveCallback>,
+    on_result: Option<ResultCallback>,
+    observers: Vec<Box<dyn GameObserver>>,
+    out: Box<dyn Write>, // for messages about the game, stdout unless set
+    info: GameInfo,
+    result: Option<GameResult>,
 }
 
-impl<White: Player, Black: Player> Game<White, Black> {
+impl Game {
     pub fn new(
         fen: Option<String>,
-        white: White,
-        black: Black,
-    ) -> anyhow::Result<Game<White, Black>> {
+        white: impl Player + 'static,
+        black: impl Player + 'static,
+    ) -> anyhow::Result<Game> {
+        Game::new_boxed(fen, Box::new(white), Box::new(black))
+    }
+
+    // Allows the players to be chosen at runtime
+    pub fn new_boxed(
+        fen: Option<String>,
+        white: Box<dyn Player>,
+        black: Box<dyn Player>,
+    ) -> anyhow::Result<Game> {
+        let board = Board::new(fen)?;
         Ok(Game {
-            board: Board::new(fen)?,
-            previous_states: Vec::new(),
-            white,
-            black,
+            board,
+            repetitions: HashMap::from([(board.repetition_key(), 1)]),
+            history: Vec::new(),
+            evals: Vec::new(),
+            white: Seat::new(white),
+            black: Seat::new(black),
+            clock: None,
+            time_control: None,
+            start: board,
+            max_plies: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            adjudication: None,
+            draw_adjudication: None,
+            move_timeout: None,
+            rngs: None,
+            on_move: None,
+            on_result: None,
+            observers: Vec::new(),
+            out: Box::new(io::stdout()),
+            info: GameInfo::default(),
+            result: None,
         })
     }
 
-    fn get_next_move(&self) -> Move {
-        match self.board.current_turn() {
-            Color::White => self.white.make_move(&self.board),
-            Color::Black => self.black.make_move(&self.board),
+    // Resumes a game from the given start position after the given moves in UCI notation
+    pub fn from_moves(
+        fen: Option<String>,
+        moves: &[&str],
+        white: impl Player + 'static,
+        black: impl Player + 'static,
+    ) -> anyhow::Result<Game> {
+        Game::from_moves_boxed(fen, moves, Box::new(white), Box::new(black))
+    }
+
+    pub fn from_moves_boxed(
+        fen: Option<String>,
+        moves: &[&str],
+        white: Box<dyn Player>,
+        black: Box<dyn Player>,
+    ) -> anyhow::Result<Game> {
+        let mut game = Game::new_boxed(fen, white, black)?;
+        for uci in moves {
+            let mv = game.board.parse_move(uci)?;
+            let board = game.board.apply_checked(&mv).with_context(|| {
+                format!("could not play move {} ({uci})", game.history.len() + 1)
+            })?;
+            game.push_move(board, mv, None);
+        }
+        Ok(game)
+    }
+
+    // Plays the game with the given time control
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+        self.time_control = Some(clock);
+    }
+
+    // The game is declared a draw after this many plies
+    pub fn set_max_plies(&mut self, max_plies: usize) {
+        self.max_plies = Some(max_plies);
+    }
+
+    // A player forfeits after making more than this many illegal moves in a row
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        self.max_retries = max_retries;
+    }
+
+    // Declares a win once the players' evaluations have agreed for the given number of
+    // plies in a row that one side is ahead by at least the threshold
+    pub fn set_adjudication(&mut self, threshold: i32, plies: usize) {
+        self.adjudication = Some((threshold, plies));
+    }
+
+    // Declares a draw once the players' evaluations have stayed within the margin of
+    // even for the given number of plies in a row, all played after move 40
+    pub fn set_draw_adjudication(&mut self, margin: i32, plies: usize) {
+        self.draw_adjudication = Some((margin, plies));
+    }
+
+    // Limits how long a player may think about a single move. Unlike the clock,
+    // this does not wait for the player to finish.
+    pub fn set_move_timeout(&mut self, timeout: Duration, policy: TimeoutPolicy) {
+        self.move_timeout = Some((timeout, policy));
+    }
+
+    // Makes the players' random choices, and with that the whole game, repeatable
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rngs = Some(
+            [Color::White, Color::Black]
+                .map(|color| StdRng::seed_from_u64(seed.wrapping_add(color as u64))),
+        );
+    }
+
+    // Event, round, player names etc. for the PGN headers
+    pub fn set_info(&mut self, info: GameInfo) {
+        self.info = info;
+    }
+
+    // Where messages such as a declined draw offer go
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.out = Box::new(out);
+    }
+
+    pub fn add_observer(&mut self, observer: Box<dyn GameObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Called after each move with the new board, the move and the color who made it
+    pub fn on_move(&mut self, callback: impl FnMut(&Board, &Move, Color) + 'static) {
+        self.on_move = Some(Box::new(callback));
+    }
+
+    // Called once with the final board when the game is over
+    pub fn on_result(&mut self, callback: impl FnMut(&Board, GameResult) + 'static) {
+        self.on_result = Some(Box::new(callback));
+    }
+
+    fn get_next_action(&mut self) -> Result<PlayerAction, RecvTimeoutError> {
+        let color = self.board.current_turn();
+        let seed = self.rngs.as_mut().map(|rngs| rngs[color as usize].gen());
+        let mut request = MoveRequest::new(&self.board)
+            .with_color(color)
+            .with_repetitions(&self.repetitions)
+            .with_history(&self.start, &self.history);
+        if let Some(clock) = self.clock.as_ref() {
+            request = request.with_clock(clock);
+        }
+        if let Some(seed) = seed {
+            request = request.with_seed(seed);
+        }
+        let timeout = self.move_timeout.map(|(timeout, _)| timeout);
+        match color {
+            Color::White => self.white.make_move(&request, timeout),
+            Color::Black => self.black.make_move(&request, timeout),
         }
     }
 
     fn is_gameover(&self) -> Option<GameResult> {
-        let fen_pieces = self.board.get_fen_pieces();
         if self
-            .previous_states
-            .iter()
-            .filter(|prev| **prev == fen_pieces)
-            .count()
-            >= 2
+            .repetitions
+            .get(&self.board.repetition_key())
+            .is_some_and(|count| *count >= 3)
         {
-            // if we have seen the current gamestate twice before, it is a draw
-            Some(GameResult::DrawByRepetition)
-        } else if self.board.ply - self.board.last_pawn_move >= 50 {
-            // 50 moves since last pawn move, it is a draw
-            Some(GameResult::DrawBy50MoveRule)
+            // if we have seen the current position three times, it is a draw
+            Some(GameResult::Draw(Termination::Repetition))
+        } else if self.board.ply - self.board.last_pawn_move >= FIFTY_MOVE_PLIES {
+            // 50 moves each without a pawn move or capture, it is a draw
+            Some(GameResult::Draw(Termination::FiftyMove))
         } else {
-            let black = self.board.get_pieces(Color::Black);
-            if let None = black.iter().find(|(_, piece)| piece.typ == PieceType::King) {
+            if self.board.king_position(Color::Black).is_none() {
                 // The black king has been captured, white wins
-                return Some(GameResult::WhiteWin);
+                return Some(GameResult::Win(Color::White, Termination::Checkmate));
             }
-            let white = self.board.get_pieces(Color::White);
-            if let None = white.iter().find(|(_, piece)| piece.typ == PieceType::King) {
+            if self.board.king_position(Color::White).is_none() {
                 // The white king has been captured, black wins
-                return Some(GameResult::BlackWin);
+                return Some(GameResult::Win(Color::Black, Termination::Checkmate));
+            }
+
+            if self.board.is_dead_position() {
+                return Some(GameResult::Draw(Termination::InsufficientMaterial));
+            }
+
+            if self
+                .max_plies
+                .is_some_and(|max_plies| self.board.ply - self.start.ply >= max_plies)
+            {
+                return Some(GameResult::Draw(Termination::MaxMoves));
             }
 
             // the game is still ongoing
@@ -89,23 +464,1061 @@ impl<White: Player, Black: Player> Game<White, Black> {
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> GameResult {
+        let fen = self.board.get_fen();
+        for observer in self.observers.iter_mut() {
+            observer.on_game_start(&fen);
+        }
+
+        let mut retries = 0;
+        let mut ahead: Option<(Color, usize)> = None; // who the evaluations favor, and for how long
+        let mut even = 0; // plies in a row the evaluations called the game even
         loop {
-            let mv = self.get_next_move();
-            // For the purposes of determining a draw, we could clear this
-            // if we see a pawn move or capture
-            self.previous_states.push(self.board.get_fen_pieces());
-            self.board = self.board.apply(&mv);
+            let color = self.board.current_turn();
+            let started = Instant::now();
+            let action = self.get_next_action();
+            let elapsed = started.elapsed();
+
+            if let Some(clock) = self.clock.as_mut() {
+                if !clock.spend(color, elapsed) {
+                    // the flag fell
+                    return self.finish(self.time_forfeit(color));
+                }
+            }
+
+            let action = match (action, self.move_timeout) {
+                (Ok(action), _) => action,
+                (Err(RecvTimeoutError::Disconnected), _) => {
+                    // the player crashed while thinking
+                    return self.finish(GameResult::Win(!color, Termination::Forfeit));
+                }
+                (Err(RecvTimeoutError::Timeout), Some((_, TimeoutPolicy::Fallback))) => {
+                    match self.fallback_move() {
+                        Some(mv) => PlayerAction::Move(mv),
+                        None => return self.finish(no_moves_result(&self.board)),
+                    }
+                }
+                (Err(RecvTimeoutError::Timeout), _) => {
+                    return self.finish(self.time_forfeit(color))
+                }
+            };
+
+            let mv = match action {
+                PlayerAction::Move(mv) => mv,
+                PlayerAction::Undo(plies) => {
+                    if let Err(err) = self.undo(plies) {
+                        self.say(&err.to_string());
+                    }
+                    continue;
+                }
+                PlayerAction::Resign => {
+                    return self.finish(GameResult::Win(!color, Termination::Resignation));
+                }
+                PlayerAction::OfferDraw => {
+                    let request = MoveRequest::new(&self.board)
+                        .with_color(!color)
+                        .with_repetitions(&self.repetitions)
+                        .with_history(&self.start, &self.history);
+                    if self.seat(!color).accepts_draw(&request) {
+                        return self.finish(GameResult::Draw(Termination::Agreement));
+                    }
+                    self.say(&format!("{:?} declined the draw offer", !color));
+                    // the offering player still has to move
+                    continue;
+                }
+                PlayerAction::NoMoves => {
+                    let result = if !self.board.legal_moves(color).is_empty() {
+                        // there were moves, so this is just giving up
+                        GameResult::Win(!color, Termination::Forfeit)
+                    } else {
+                        no_moves_result(&self.board)
+                    };
+                    return self.finish(result);
+                }
+            };
+
+            if !self.board.legal_moves(color).contains(&mv) {
+                self.say(&format!("Illegal move by {:?} ({mv})", color));
+                retries += 1;
+                if retries > self.max_retries {
+                    return self.finish(GameResult::Win(!color, Termination::Forfeit));
+                }
+                // ask the same player again
+                continue;
+            }
+            retries = 0;
+            let board = self.board.apply(&mv);
+
+            let before = self.board;
+            let eval = self.seat(color).last_eval();
+            self.push_move(board, mv, eval);
+
+            let record = PlyRecord::new(&before, mv, eval);
+            let event = MoveEvent {
+                before: &before,
+                record: &record,
+                after: &self.board,
+                ply: self.history.len(),
+                player: match color {
+                    Color::White => &self.white.name,
+                    Color::Black => &self.black.name,
+                },
+                elapsed,
+            };
+            for observer in self.observers.iter_mut() {
+                observer.on_move(&event);
+            }
+
+            if let Some(on_move) = self.on_move.as_mut() {
+                on_move(&self.board, &mv, color);
+            }
 
             if let Some(result) = self.is_gameover() {
-                println!("{}", self.board);
-                println!(
-                    "Game over: {} after {} moves",
-                    result,
-                    1 + self.board.ply / 2
-                );
-                break;
+                return self.finish(result);
+            }
+
+            if let Some((threshold, plies)) = self.adjudication {
+                ahead = match (eval.and_then(|eval| favored(eval, color, threshold)), ahead) {
+                    (Some(winner), Some((previous, count))) if winner == previous => {
+                        Some((winner, count + 1))
+                    }
+                    (Some(winner), _) => Some((winner, 1)),
+                    (None, _) => None,
+                };
+                if let Some((winner, count)) = ahead {
+                    if count >= plies {
+                        return self.finish(GameResult::Win(winner, Termination::Adjudication));
+                    }
+                }
             }
+
+            if let Some((margin, plies)) = self.draw_adjudication {
+                let late = before.ply >= 2 * DRAW_ADJUDICATION_MOVE;
+                even = match eval {
+                    Some(eval) if late && is_even(eval, margin) => even + 1,
+                    _ => 0,
+                };
+                if even >= plies {
+                    return self.finish(GameResult::Draw(Termination::DrawByAdjudication));
+                }
+            }
+        }
+    }
+
+    // Takes back the given number of plies by replaying the game from the start
+    pub fn undo(&mut self, plies: usize) -> anyhow::Result<()> {
+        if plies > self.history.len() {
+            bail!(
+                "Can not undo {} plies, only {} have been played",
+                plies,
+                self.history.len()
+            );
         }
+
+        let remaining = self.history.len() - plies;
+        self.history.truncate(remaining);
+        self.evals.truncate(remaining);
+        self.repetitions = HashMap::from([(self.start.repetition_key(), 1)]);
+        self.board = self.start;
+        let history = std::mem::take(&mut self.history);
+        let evals = std::mem::take(&mut self.evals);
+        for (mv, eval) in history.into_iter().zip(evals) {
+            self.push_move(self.board.apply(&mv), mv, eval);
+        }
+        Ok(())
+    }
+
+    // The result when the given color runs out of time: a loss, unless the opponent
+    // could never have mated
+    fn time_forfeit(&self, color: Color) -> GameResult {
+        if self.board.is_insufficient_material(!color) {
+            GameResult::Draw(Termination::InsufficientMaterial)
+        } else {
+            GameResult::Win(!color, Termination::Timeout)
+        }
+    }
+
+    // The move played for a player who ran out of time to think, None when there
+    // is no legal move
+    fn fallback_move(&self) -> Option<Move> {
+        let color = self.board.current_turn();
+        self.board.legal_moves(color).first().copied()
+    }
+
+    // Records a move that has been made, leading to the given board
+    fn push_move(&mut self, board: Board, mv: Move, eval: Option<Evaluation>) {
+        self.board = board;
+        self.history.push(mv);
+        self.evals.push(eval);
+        *self.repetitions.entry(board.repetition_key()).or_default() += 1;
+    }
+
+    // The game so far in PGN, with player names taken from the players if not set
+    pub fn to_pgn(&self) -> String {
+        self.record().to_pgn()
+    }
+
+    // Every ply so far with the position before it, its SAN and the mover's evaluation
+    pub fn move_log(&self) -> Vec<PlyRecord> {
+        let mut board = self.start;
+        let mut log = Vec::new();
+        for (mv, eval) in self.history.iter().zip(&self.evals) {
+            log.push(PlyRecord::new(&board, *mv, *eval));
+            board = board.apply(mv);
+        }
+        log
+    }
+
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            info: self.info.clone(),
+            white: self
+                .info
+                .white
+                .clone()
+                .unwrap_or_else(|| self.white.name.clone()),
+            black: self
+                .info
+                .black
+                .clone()
+                .unwrap_or_else(|| self.black.name.clone()),
+            start: self.start,
+            moves: self.history.clone(),
+            result: self.result,
+            hints: [self.white.hints_used(), self.black.hints_used()],
+            times: [self.white.move_times(), self.black.move_times()],
+        }
+    }
+
+    // A new game from the same start position and settings, with the players swapping colors
+    pub fn rematch_swapped(mut self) -> Game {
+        std::mem::swap(&mut self.white, &mut self.black);
+        std::mem::swap(&mut self.info.white, &mut self.info.black);
+        self.board = self.start;
+        self.history.clear();
+        self.evals.clear();
+        self.repetitions = HashMap::from([(self.start.repetition_key(), 1)]);
+        self.clock = self.time_control;
+        self.result = None;
+        self
+    }
+
+    fn seat(&self, color: Color) -> &Seat {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    fn say(&mut self, message: &str) {
+        if let Err(err) = writeln!(self.out, "{message}") {
+            eprintln!("Could not write to the game output: {err}");
+        }
+    }
+
+    fn finish(&mut self, result: GameResult) -> GameResult {
+        self.result = Some(result);
+        for color in [Color::White, Color::Black] {
+            let request = MoveRequest::new(&self.board)
+                .with_color(color)
+                .with_history(&self.start, &self.history);
+            self.seat(color).game_over(&request, result);
+        }
+        if let Some(on_result) = self.on_result.as_mut() {
+            on_result(&self.board, result);
+        }
+        for observer in self.observers.iter_mut() {
+            observer.on_game_end(&self.board, result);
+        }
+        result
+    }
+}
+
+// The result when the side to move has no legal moves: checkmate if its king is
+// attacked, stalemate otherwise
+pub fn no_moves_result(board: &Board) -> GameResult {
+    let color = board.current_turn();
+    if board.is_in_check(color) {
+        GameResult::Win(!color, Termination::Checkmate)
+    } else {
+        GameResult::Draw(Termination::Stalemate)
+    }
+}
+
+// The side that an evaluation by the given color says is winning by at least the threshold
+fn favored(eval: Evaluation, color: Color, threshold: i32) -> Option<Color> {
+    if eval >= Evaluation::Eval(threshold) {
+        Some(color)
+    } else if eval <= Evaluation::Eval(-threshold) {
+        Some(!color)
+    } else {
+        None
+    }
+}
+
+// Draws are only adjudicated for moves after this one
+const DRAW_ADJUDICATION_MOVE: usize = 40;
+
+// Whether the evaluation is within the margin of even
+fn is_even(eval: Evaluation, margin: i32) -> bool {
+    Evaluation::Eval(-margin) <= eval && eval <= Evaluation::Eval(margin)
+}
+
+// Plays the game, then the rematch with the players swapping colors
+pub fn play_pair(mut game: Game) -> (GameRecord, GameRecord) {
+    game.start();
+    let first = game.record();
+
+    let mut game = game.rematch_swapped();
+    game.start();
+    (first, game.record())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::players::{EnginePlayer, RandomPlayer, ScriptedPlayer, SharedOutput};
+
+    #[derive(Debug, PartialEq)]
+    #[allow(clippy::large_enum_variant)]
+    enum Event {
+        Start(String),
+        Move(Board, Move, Board),
+        End(GameResult),
+    }
+
+    struct RecordingObserver {
+        events: Rc<RefCell<Vec<Event>>>,
+    }
+
+    impl GameObserver for RecordingObserver {
+        fn on_game_start(&mut self, fen: &str) {
+            self.events.borrow_mut().push(Event::Start(fen.into()));
+        }
+
+        fn on_move(&mut self, event: &MoveEvent) {
+            self.events.borrow_mut().push(Event::Move(
+                *event.before,
+                event.record.mv,
+                *event.after,
+            ));
+        }
+
+        fn on_game_end(&mut self, _board: &Board, result: GameResult) {
+            self.events.borrow_mut().push(Event::End(result));
+        }
+    }
+
+    // Thinks for too long, then plays like RandomPlayer
+    struct SlowPlayer;
+
+    impl Player for SlowPlayer {
+        fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+            thread::sleep(Duration::from_millis(50));
+            RandomPlayer::new().make_move(request)
+        }
+    }
+
+    struct CrashingPlayer;
+
+    impl Player for CrashingPlayer {
+        fn make_move(&mut self, _request: &MoveRequest) -> PlayerAction {
+            panic!("out of ideas");
+        }
+    }
+
+    // Makes the given number of illegal moves before playing like RandomPlayer
+    struct IllegalPlayer {
+        illegal_moves: usize,
+    }
+
+    impl IllegalPlayer {
+        fn new(illegal_moves: usize) -> Self {
+            IllegalPlayer { illegal_moves }
+        }
+    }
+
+    impl Player for IllegalPlayer {
+        fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+            if self.illegal_moves == 0 {
+                return RandomPlayer::new().make_move(request);
+            }
+            self.illegal_moves -= 1;
+
+            // a king leaping across the board
+            let from = request.board.king_position(request.board.current_turn());
+            Move {
+                from: from.unwrap(),
+                to: b"a5".into(),
+                special: None,
+            }
+            .into()
+        }
+    }
+
+    #[test]
+    fn test_move_timeout_fallback() {
+        let mut game = Game::new(None, SlowPlayer, RandomPlayer::new()).unwrap();
+        game.set_move_timeout(Duration::from_millis(10), TimeoutPolicy::Fallback);
+        game.set_max_plies(6);
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(game.history.len(), 6);
+        // the first legal move, as the slow player never got to move in time
+        let first = Board::new(None).unwrap().legal_moves(Color::White)[0];
+        assert_eq!(game.history[0], first);
+    }
+
+    #[test]
+    fn test_move_timeout_fallback_without_moves() {
+        // black is stalemated, so there is no move to fall back on
+        let fen = "k7/8/1Q6/8/8/8/8/7K b - - 0 1";
+        let mut game = Game::new(Some(fen.into()), RandomPlayer::new(), SlowPlayer).unwrap();
+        game.set_move_timeout(Duration::from_millis(10), TimeoutPolicy::Fallback);
+        assert_eq!(game.start(), GameResult::Draw(Termination::Stalemate));
+    }
+
+    #[test]
+    fn test_crashed_player_forfeits() {
+        let mut game = Game::new(None, RandomPlayer::new(), CrashingPlayer).unwrap();
+        game.set_move_timeout(Duration::from_secs(1), TimeoutPolicy::Loss);
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Forfeit)
+        );
+        assert_eq!(game.history.len(), 1);
+    }
+
+    #[test]
+    fn test_move_timeout_loss() {
+        let mut game = Game::new(None, RandomPlayer::new(), SlowPlayer).unwrap();
+        game.set_move_timeout(Duration::from_millis(10), TimeoutPolicy::Loss);
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Timeout)
+        );
+        assert_eq!(game.history.len(), 1);
+
+        // white has only a king, so it could not have won anyway
+        let fen = "r3k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        let mut game = Game::new(Some(fen.into()), RandomPlayer::new(), SlowPlayer).unwrap();
+        game.set_move_timeout(Duration::from_millis(10), TimeoutPolicy::Loss);
+        assert_eq!(
+            game.start(),
+            GameResult::Draw(Termination::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        // 49 moves each without a pawn move or capture
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 98 80";
+        let white = ScriptedPlayer::new(&["a1a2", "a2a3"]);
+        let black = ScriptedPlayer::new(&["e8d8", "d8e8"]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        assert_eq!(game.start(), GameResult::Draw(Termination::FiftyMove));
+        assert_eq!(game.history.len(), 2);
+
+        // the capture starts the count over
+        let fen = "4k2r/8/8/8/8/8/8/4K2R w - - 99 80";
+        let white = ScriptedPlayer::new(&["h1h8", "h8h1"]);
+        let black = ScriptedPlayer::new(&["e8d7"]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_max_plies(3);
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+    }
+
+    #[test]
+    fn test_illegal_move_retry() {
+        let mut game = Game::new(None, IllegalPlayer::new(3), RandomPlayer::new()).unwrap();
+        game.set_max_plies(2);
+        let out = SharedOutput::default();
+        game.set_output(out.clone());
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(game.history.len(), 2);
+        let messages = out.contents();
+        assert_eq!(messages.lines().count(), 3);
+        assert!(messages
+            .lines()
+            .all(|line| line.starts_with("Illegal move by White")));
+    }
+
+    #[test]
+    fn test_illegal_move_forfeit() {
+        let mut game = Game::new(None, RandomPlayer::new(), IllegalPlayer::new(2)).unwrap();
+        game.set_max_retries(1);
+        let out = SharedOutput::default();
+        game.set_output(out.clone());
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Forfeit)
+        );
+        assert_eq!(game.history.len(), 1);
+        assert_eq!(game.board.current_turn(), Color::Black);
+        let messages = out.contents();
+        assert_eq!(messages.lines().count(), 2);
+        assert!(messages.starts_with("Illegal move by Black"), "{messages}");
+    }
+
+    #[test]
+    fn test_illegal_move_into_check() {
+        // the knight is pinned to the king by the bishop
+        let fen = "4k3/8/8/b7/8/2N5/8/4K3 w - - 0 1";
+        let white = ScriptedPlayer::new(&["c3e4", "c3e4"]);
+        let black = ScriptedPlayer::new(&[]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_max_retries(1);
+        let out = SharedOutput::default();
+        game.set_output(out.clone());
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Forfeit)
+        );
+        assert!(game.history.is_empty());
+        assert!(out
+            .contents()
+            .starts_with("Illegal move by White (c3 -> e4)"));
+    }
+
+    // Plays like RandomPlayer, but can be told apart from others by name
+    struct NamedPlayer(&'static str);
+
+    impl Player for NamedPlayer {
+        fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+            RandomPlayer::new().make_move(request)
+        }
+
+        fn name(&self) -> String {
+            self.0.into()
+        }
+    }
+
+    #[test]
+    fn test_play_pair() {
+        let fen = "3qk3/8/8/8/8/8/8/4K3 w - - 0 1";
+        let game = Game::new(Some(fen.into()), NamedPlayer("A"), NamedPlayer("B")).unwrap();
+        let (first, second) = play_pair(game);
+
+        assert_eq!((first.white.as_str(), first.black.as_str()), ("A", "B"));
+        assert_eq!((second.white.as_str(), second.black.as_str()), ("B", "A"));
+        assert_eq!(first.start, second.start);
+        assert!(first.result.is_some());
+        assert!(second.result.is_some());
+
+        // both games were played from the start position
+        for record in [first, second] {
+            let mut board = record.start;
+            for mv in record.moves.iter() {
+                board = board.apply_checked(mv).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_rematch_keeps_settings() {
+        let mut game = Game::new(None, NamedPlayer("A"), NamedPlayer("B")).unwrap();
+        game.set_max_plies(4);
+        game.set_info(GameInfo::default().with_white("Alice"));
+        game.start();
+
+        let mut game = game.rematch_swapped();
+        assert_eq!(game.history.len(), 0);
+        assert_eq!(game.board, game.start);
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(game.history.len(), 4);
+
+        let record = game.record();
+        assert_eq!(record.white, "B");
+        assert_eq!(record.black, "Alice");
+        assert_eq!(record.hints, [0, 0]);
+    }
+
+    #[test]
+    fn test_result_api() {
+        let reasons = [
+            Termination::Checkmate,
+            Termination::Resignation,
+            Termination::Timeout,
+            Termination::Forfeit,
+            Termination::Stalemate,
+            Termination::Repetition,
+            Termination::FiftyMove,
+            Termination::InsufficientMaterial,
+            Termination::Adjudication,
+            Termination::DrawByAdjudication,
+            Termination::MaxMoves,
+            Termination::Agreement,
+        ];
+        for reason in reasons {
+            for color in [Color::White, Color::Black] {
+                let result = GameResult::Win(color, reason);
+                assert_eq!(result.winner(), Some(color));
+                assert!(!result.is_draw());
+                assert_eq!(result.termination(), reason);
+                assert_eq!(result.score(color), 1.0);
+                assert_eq!(result.score(!color), 0.0);
+            }
+
+            let result = GameResult::Draw(reason);
+            assert_eq!(result.winner(), None);
+            assert!(result.is_draw());
+            assert_eq!(result.termination(), reason);
+            assert_eq!(result.score(Color::White), 0.5);
+            assert_eq!(result.score(Color::Black), 0.5);
+        }
+    }
+
+    #[test]
+    fn test_boxed_players() {
+        // the knight can not stop the back rank mate
+        let fen = "3r3k/8/8/8/8/8/6PP/N6K w - - 0 1";
+        let white: Box<dyn Player> = Box::new(ScriptedPlayer::new(&["a1b3"]));
+        let black: Box<dyn Player> = Box::new(EnginePlayer::new());
+        let mut game = Game::new_boxed(Some(fen.into()), white, black).unwrap();
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Checkmate)
+        );
+        assert!(game.board[b"d1"].is_some_and(|p| p.color == Color::Black));
+    }
+
+    #[test]
+    fn test_adjudication() {
+        let fen = "k7/8/8/8/8/8/8/1QQQK3 w - - 0 1";
+        let white = EnginePlayer::with_depth(1);
+        let black = EnginePlayer::with_depth(1);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_adjudication(500, 2);
+        // unseeded, the evaluation noise can make white stalemate
+        game.set_seed(1);
+
+        let result = game.start();
+        assert_eq!(
+            result,
+            GameResult::Win(Color::White, Termination::Adjudication)
+        );
+        assert_eq!(result.to_string(), "White won by adjudication");
+        assert_eq!(game.history.len(), 2);
+    }
+
+    #[test]
+    fn test_scripted_adjudication() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 41";
+        let white_moves = ["e1d1", "d1e1", "e1d1"];
+        let black_moves = ["e8d8", "d8e8", "e8d8"];
+
+        // both sides see white well ahead
+        let white = ScriptedPlayer::new(&white_moves).with_evals(&[Evaluation::Eval(600); 3]);
+        let black = ScriptedPlayer::new(&black_moves).with_evals(&[Evaluation::Eval(-600); 3]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_adjudication(500, 3);
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Adjudication)
+        );
+        assert_eq!(game.history.len(), 3);
+
+        // after move 40, both sides see the game as even
+        let evals = [Evaluation::Eval(5), Evaluation::Draw, Evaluation::Eval(-5)];
+        let white = ScriptedPlayer::new(&white_moves).with_evals(&evals);
+        let black = ScriptedPlayer::new(&black_moves).with_evals(&evals);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_draw_adjudication(10, 4);
+        let result = game.start();
+        assert_eq!(result, GameResult::Draw(Termination::DrawByAdjudication));
+        assert_eq!(result.to_string(), "Draw by adjudication");
+        assert_eq!(game.history.len(), 4);
+
+        // an evaluation outside the margin starts the count over
+        let white = ScriptedPlayer::new(&white_moves).with_evals(&evals);
+        let black = ScriptedPlayer::new(&black_moves).with_evals(&[
+            Evaluation::Eval(0),
+            Evaluation::Eval(50),
+            Evaluation::Eval(0),
+        ]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_draw_adjudication(10, 4);
+        game.set_max_plies(6);
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+
+        // before move 40 the same evaluations do not end the game
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 20";
+        let white = ScriptedPlayer::new(&white_moves).with_evals(&evals);
+        let black = ScriptedPlayer::new(&black_moves).with_evals(&evals);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_draw_adjudication(10, 4);
+        game.set_max_plies(6);
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+    }
+
+    #[test]
+    fn test_favored() {
+        assert_eq!(
+            favored(Evaluation::Eval(600), Color::Black, 500),
+            Some(Color::Black)
+        );
+        assert_eq!(
+            favored(Evaluation::Eval(-600), Color::Black, 500),
+            Some(Color::White)
+        );
+        assert_eq!(
+            favored(Evaluation::Win(3), Color::White, 500),
+            Some(Color::White)
+        );
+        assert_eq!(favored(Evaluation::Eval(100), Color::White, 500), None);
+        assert_eq!(favored(Evaluation::Draw, Color::White, 500), None);
+    }
+
+    #[test]
+    fn test_is_even() {
+        assert!(is_even(Evaluation::Draw, 10));
+        assert!(is_even(Evaluation::Eval(-10), 10));
+        assert!(!is_even(Evaluation::Eval(11), 10));
+        assert!(!is_even(Evaluation::Win(5), 10));
+        assert!(!is_even(Evaluation::Loss(5), 10));
+    }
+
+    #[test]
+    fn test_same_player_both_sides() {
+        let player = Arc::new(Mutex::new(RandomPlayer::new()));
+        let mut game = Game::new(None, player.clone(), player).unwrap();
+        game.set_max_plies(40);
+
+        let expected = Rc::new(Cell::new(Color::White));
+        let turn = expected.clone();
+        game.on_move(move |board, mv, color| {
+            assert_eq!(color, turn.get());
+            assert!(board[mv.to].is_some_and(|p| p.color == color));
+            turn.set(!color);
+        });
+
+        game.start();
+        assert!(!game.history.is_empty());
+    }
+
+    #[test]
+    fn test_seed() {
+        let play = |seed| {
+            let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+            game.set_seed(seed);
+            game.set_max_plies(40);
+            game.start();
+            game.record()
+        };
+
+        assert_eq!(play(7), play(7));
+        assert_ne!(play(7).moves, play(8).moves);
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let play = |fen: &str| {
+            let mut game =
+                Game::new(Some(fen.into()), RandomPlayer::new(), RandomPlayer::new()).unwrap();
+            game.start()
+        };
+        assert_eq!(
+            play("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            GameResult::Draw(Termination::Stalemate)
+        );
+        assert_eq!(
+            play("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+            GameResult::Win(Color::White, Termination::Checkmate)
+        );
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        let moves = Rc::new(Cell::new(0));
+        let results = Rc::new(Cell::new(0));
+
+        let counter = moves.clone();
+        game.on_move(move |_, _, _| counter.set(counter.get() + 1));
+        let counter = results.clone();
+        game.on_result(move |_, _| counter.set(counter.get() + 1));
+
+        game.start();
+        assert_eq!(moves.get(), game.board.ply);
+        assert_eq!(results.get(), 1);
+    }
+
+    #[test]
+    fn test_loss_on_time() {
+        let mut game = Game::new(None, SlowPlayer, RandomPlayer::new()).unwrap();
+        game.set_clock(Clock::new(Duration::from_millis(10), Duration::ZERO));
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Timeout)
+        );
+        assert_eq!(game.board.ply, 0);
+    }
+
+    #[test]
+    fn test_no_time() {
+        let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        game.set_clock(Clock::new(Duration::ZERO, Duration::from_secs(1)));
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Timeout)
+        );
+        assert_eq!(game.board.ply, 0);
+
+        // black has only a king, so white running out of time is a draw
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        let mut game =
+            Game::new(Some(fen.into()), RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        game.set_clock(Clock::new(Duration::ZERO, Duration::ZERO));
+        assert_eq!(
+            game.start(),
+            GameResult::Draw(Termination::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn test_flag_against_insufficient_material() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        let mut game = Game::new(Some(fen.into()), SlowPlayer, RandomPlayer::new()).unwrap();
+        game.set_clock(Clock::new(Duration::from_millis(10), Duration::ZERO));
+
+        assert_eq!(
+            game.start(),
+            GameResult::Draw(Termination::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn test_dead_position() {
+        // bishops on both colors can still mate
+        let fen = "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1";
+        let white = ScriptedPlayer::new(&["f1b5"]);
+        let black = ScriptedPlayer::new(&["e8d8"]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_max_plies(2);
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+
+        // after Kxc7 only a bishop is left
+        let fen = "8/2B5/1k6/8/8/8/8/4K3 b - - 0 1";
+        let mut game = Game::new(
+            Some(fen.into()),
+            ScriptedPlayer::new(&[]),
+            ScriptedPlayer::new(&["b6c7"]),
+        )
+        .unwrap();
+        assert_eq!(
+            game.start(),
+            GameResult::Draw(Termination::InsufficientMaterial)
+        );
+    }
+
+    #[test]
+    fn test_max_plies() {
+        let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        game.set_max_plies(4);
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(game.board.ply, 4);
+    }
+
+    #[test]
+    fn test_observers() {
+        let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        game.set_max_plies(6);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        game.add_observer(Box::new(RecordingObserver {
+            events: events.clone(),
+        }));
+
+        let result = game.start();
+
+        let events = events.borrow();
+        assert_eq!(events.len(), game.history.len() + 2);
+        assert_eq!(events[0], Event::Start(Board::new(None).unwrap().get_fen()));
+        let mut board = Board::new(None).unwrap();
+        for (event, mv) in events[1..].iter().zip(game.history.iter()) {
+            let after = board.apply(mv);
+            assert_eq!(*event, Event::Move(board, *mv, after));
+            board = after;
+        }
+        assert_eq!(events.last(), Some(&Event::End(result)));
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        // both sides shuffle a knight back and forth, returning to the start twice
+        let white = ScriptedPlayer::new(&["g1f3", "f3g1", "g1f3", "f3g1"]);
+        let black = ScriptedPlayer::new(&["g8f6", "f6g8", "g8f6", "f6g8"]);
+        let mut game = Game::new(None, white, black).unwrap();
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::Repetition));
+        assert_eq!(game.board.ply, 8);
+        assert_eq!(game.repetitions[&game.start.repetition_key()], 3);
+    }
+
+    #[test]
+    fn test_repetition_needs_castling_rights() {
+        // the kings step away and back, so the pieces stand as at the start three
+        // times, but castling is only possible the first time
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1";
+        let white = ScriptedPlayer::new(&["e1d1", "d1e1", "e1d1", "d1e1"]);
+        let black = ScriptedPlayer::new(&["e8d8", "d8e8", "e8d8", "d8e8"]);
+        let mut game = Game::new(Some(fen.into()), white, black).unwrap();
+        game.set_max_plies(8);
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(game.repetitions[&game.board.repetition_key()], 2);
+        assert_eq!(game.board.position_key(), game.start.position_key());
+    }
+
+    #[test]
+    fn test_engine_sees_the_repetition() {
+        // a queen down, white takes the draw the game declares by going back to f3
+        // for the third time
+        let fen = "k2q4/8/8/8/8/5N2/8/7K b - - 0 1";
+        let moves = ["d8d7", "f3g1", "d7d8", "g1f3", "d8d7", "f3g1", "d7d8"];
+        let white = EnginePlayer::with_depth(2);
+        let mut game =
+            Game::from_moves(Some(fen.into()), &moves, white, ScriptedPlayer::new(&[])).unwrap();
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::Repetition));
+        assert_eq!(game.history.len(), 8);
+        assert_eq!(game.history.last().map(|mv| mv.uci()), Some("g1f3".into()));
+    }
+
+    #[test]
+    fn test_repetition_after_double_step() {
+        // e4 can not be taken en passant, so the position after it repeats when the
+        // knights come back
+        let white = ScriptedPlayer::new(&["e2e4", "g1f3", "f3g1", "g1f3", "f3g1"]);
+        let black = ScriptedPlayer::new(&["g8f6", "f6g8", "g8f6", "f6g8"]);
+        let mut game = Game::new(None, white, black).unwrap();
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::Repetition));
+        assert_eq!(game.board.ply, 9);
+    }
+
+    #[test]
+    fn test_move_log() {
+        let mut game = Game::new(None, EnginePlayer::with_depth(1), RandomPlayer::new()).unwrap();
+        game.set_seed(1);
+        game.set_max_plies(8);
+        game.start();
+
+        let log = game.move_log();
+        assert_eq!(log.len(), 8);
+        assert_eq!(log[0].fen_before, Board::new(None).unwrap().get_fen());
+        for ply in 1..log.len() {
+            let before = Board::new(Some(log[ply - 1].fen_before.clone())).unwrap();
+            let after = before.apply(&log[ply - 1].mv);
+            assert_eq!(log[ply].fen_before, after.get_fen());
+        }
+        for (ply, record) in log.iter().enumerate() {
+            let board = Board::new(Some(record.fen_before.clone())).unwrap();
+            assert_eq!(record.san, record.mv.san(&board));
+            // only the engine evaluates its moves
+            assert_eq!(record.eval.is_some(), ply % 2 == 0);
+        }
+
+        game.undo(2).unwrap();
+        assert_eq!(game.move_log(), log[..6]);
+    }
+
+    #[test]
+    fn test_from_moves() {
+        let moves = ["e2e4", "e7e5", "g1f3"];
+        let game =
+            Game::from_moves(None, &moves, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        assert_eq!(game.history.len(), 3);
+        assert_eq!(game.board.current_turn(), Color::Black);
+        assert!(game.board[b"f3"].is_some());
+
+        let moves = ["e2e4", "e2e4"];
+        assert!(Game::from_moves(None, &moves, RandomPlayer::new(), RandomPlayer::new()).is_err());
+    }
+
+    #[test]
+    fn test_repetition_after_from_moves() {
+        // the start position occurs for the second time after the preloaded moves
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let white = ScriptedPlayer::new(&["g1f3", "f3g1"]);
+        let black = ScriptedPlayer::new(&["g8f6", "f6g8"]);
+        let mut game = Game::from_moves(None, &moves, white, black).unwrap();
+
+        assert_eq!(game.start(), GameResult::Draw(Termination::Repetition));
+        assert_eq!(game.history.len(), 8);
+    }
+
+    #[test]
+    fn test_pgn_names() {
+        let white = ScriptedPlayer::new(&["g1f3", "f3g1", "g1f3", "f3g1"]);
+        let black = ScriptedPlayer::new(&["g8f6", "f6g8", "g8f6", "f6g8"]);
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_info(
+            GameInfo::default()
+                .with_event("Club championship")
+                .with_round("3")
+                .with_white("Alice")
+                .with_black("Bob"),
+        );
+        game.start();
+
+        let pgn = game.to_pgn();
+        assert!(pgn.starts_with("[Event \"Club championship\"]\n[Site \"?\"]\n"));
+        assert!(pgn.contains("[Round \"3\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n"));
+        assert!(pgn.contains("[Result \"1/2-1/2\"]\n"));
+        assert!(pgn.ends_with("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 Ng8 1/2-1/2\n"));
+    }
+
+    #[test]
+    fn test_pgn_default_names() {
+        let moves = ["e2e4"];
+        let game =
+            Game::from_moves(None, &moves, EnginePlayer::new(), RandomPlayer::new()).unwrap();
+
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[White \"EnginePlayer depth 4\"]\n"));
+        assert!(pgn.contains("[Black \"RandomPlayer\"]\n"));
+        assert!(pgn.contains("[Result \"*\"]\n"));
+        assert!(pgn.ends_with("1. e4 *\n"));
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut game = Game::new(None, RandomPlayer::new(), RandomPlayer::new()).unwrap();
+        game.set_max_plies(4);
+        game.start();
+        let boards: Vec<Board> = game
+            .history
+            .iter()
+            .scan(game.start, |board, mv| {
+                *board = board.apply(mv);
+                Some(*board)
+            })
+            .collect();
+        assert_eq!(game.repetitions.values().sum::<u8>(), 5);
+
+        game.undo(2).unwrap();
+        assert_eq!(game.board, boards[1]);
+        assert_eq!(game.history.len(), 2);
+        assert_eq!(game.repetitions.values().sum::<u8>(), 3);
+
+        assert!(game.undo(3).is_err());
+        assert_eq!(game.board, boards[1]);
+
+        game.undo(2).unwrap();
+        assert_eq!(game.board, game.start);
     }
 }
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{bail, Context};
+use rand::distributions::{Distribution, WeightedIndex};
+
+use crate::{board::Board, pieces::Move, play::GameResult};
+
+use super::{Evaluation, MoveRequest, MoveTimes, Player, PlayerAction};
+
+// Moves to play in known positions, each with a weight for how often to play it.
+// Read from text with one position per line, its FEN and then the moves in SAN or
+// UCI with optional weights:
+//
+//   rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 => e4 3, d4 2, c4
+//
+// Empty lines and lines starting with # are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    moves: HashMap<u64, Vec<(Move, u32)>>, // by position key
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read the book {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut book = OpeningBook::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            book.parse_line(line)
+                .with_context(|| format!("line {} of the book", number + 1))?;
+        }
+        Ok(book)
+    }
+
+    fn parse_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let (fen, moves) = line
+            .split_once("=>")
+            .context("expected a FEN, then => and the moves")?;
+        let board = Board::new(Some(fen.trim().into()))?;
+        for entry in moves.split(',') {
+            let (text, weight) = match entry.split_whitespace().collect::<Vec<_>>()[..] {
+                [text] => (text, 1),
+                [text, weight] => (text, weight.parse().context("could not parse weight")?),
+                _ => bail!(
+                    "expected a move and maybe a weight, not \"{}\"",
+                    entry.trim()
+                ),
+            };
+            let mv = board.parse_san(text).or_else(|_| board.parse_move(text))?;
+            self.add(&board, mv, weight)?;
+        }
+        Ok(())
+    }
+
+    // Adds a move to the book, or more weight to one that is in it already
+    pub fn add(&mut self, board: &Board, mv: Move, weight: u32) -> anyhow::Result<()> {
+        if !board.legal_moves(board.current_turn()).contains(&mv) {
+            bail!("{} is not legal in {}", mv, board.get_fen());
+        }
+        let moves = self.moves.entry(board.position_key()).or_default();
+        match moves.iter_mut().find(|(known, _)| *known == mv) {
+            Some((_, known)) => *known += weight,
+            None => moves.push((mv, weight)),
+        }
+        Ok(())
+    }
+
+    // The book moves for the position with their weights, if any
+    pub fn moves(&self, board: &Board) -> &[(Move, u32)] {
+        self.moves
+            .get(&board.position_key())
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+// BookPlayer plays a book move, picked at random by weight, whenever there is one
+// for the position, and lets the wrapped player choose otherwise
+pub struct BookPlayer<P: Player> {
+    player: P,
+    book: OpeningBook,
+    in_book: bool, // whether the last move came from the book
+}
+
+impl<P: Player> BookPlayer<P> {
+    pub fn wrap(player: P, book: OpeningBook) -> Self {
+        Self {
+            player,
+            book,
+            in_book: true,
+        }
+    }
+
+    fn book_move(&self, request: &MoveRequest) -> Option<Move> {
+        // the book only knows the moves of the side to move
+        if request.board.current_turn() != request.color {
+            return None;
+        }
+        let moves = self.book.moves(request.board);
+        let weights = WeightedIndex::new(moves.iter().map(|(_, weight)| *weight)).ok()?;
+        Some(moves[weights.sample(&mut request.rng())].0)
+    }
+}
+
+impl<P: Player> Player for BookPlayer<P> {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        if let Some(mv) = self.book_move(request) {
+            self.in_book = true;
+            return mv.into();
+        }
+        if self.in_book {
+            self.in_book = false;
+            eprintln!(
+                "{} left the book at move {}",
+                self.player.name(),
+                request.board.ply / 2 + 1
+            );
+        }
+        self.player.make_move(request)
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    // A book move comes without an evaluation
+    fn last_eval(&self) -> Option<Evaluation> {
+        match self.in_book {
+            true => None,
+            false => self.player.last_eval(),
+        }
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        match self.in_book {
+            true => None,
+            false => self.player.last_line(),
+        }
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.move_times()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        play::Game,
+        players::{RandomPlayer, ScriptedPlayer},
+    };
+
+    const BOOK: &str = "
+# the open game
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 => e4
+rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2 => g1f3 2, Nc3 0
+";
+
+    #[test]
+    fn test_parse() {
+        let book = OpeningBook::parse(BOOK).unwrap();
+        let start = Board::new(None).unwrap();
+        let e4 = start.parse_san("e4").unwrap();
+        assert_eq!(book.moves(&start), [(e4, 1)]);
+        assert!(book.moves(&start.apply(&e4)).is_empty());
+        let open = start.apply_san("e4").unwrap().apply_san("e5").unwrap();
+        let nf3 = open.parse_san("Nf3").unwrap();
+        let nc3 = open.parse_san("Nc3").unwrap();
+        assert_eq!(book.moves(&open), [(nf3, 2), (nc3, 0)]);
+
+        let err = OpeningBook::parse("\n8/8/8/8/8/8/8/k3K3 w - - 0 1 => e4").unwrap_err();
+        assert_eq!(err.to_string(), "line 2 of the book");
+        assert!(OpeningBook::parse("e4 e5").is_err());
+        assert!(OpeningBook::parse(&format!("{} => e4 many", start.get_fen())).is_err());
+    }
+
+    #[test]
+    fn test_plays_book_moves_first() {
+        let book = OpeningBook::parse(BOOK).unwrap();
+        // the scripted moves are only reached once the book runs out
+        let white = BookPlayer::wrap(ScriptedPlayer::new(&["b1c3"]), book);
+        let black = ScriptedPlayer::new(&["e7e5", "b8c6"]);
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_max_plies(5);
+        game.set_seed(3);
+        game.start();
+
+        let record = game.record();
+        assert_eq!(record.start.line_san(&record.moves), "e4 e5 Nf3 Nc6 Nc3");
+    }
+
+    #[test]
+    fn test_weights() {
+        let start = Board::new(None).unwrap();
+        let e4 = start.parse_san("e4").unwrap();
+        let d4 = start.parse_san("d4").unwrap();
+        let mut book = OpeningBook::new();
+        book.add(&start, e4, 1).unwrap();
+        book.add(&start, d4, 2).unwrap();
+        book.add(&start, d4, 1).unwrap();
+        assert_eq!(book.moves(&start), [(e4, 1), (d4, 3)]);
+
+        let mut player = BookPlayer::wrap(RandomPlayer::new(), book);
+        let mut d4_count = 0;
+        for seed in 0..100 {
+            let request = MoveRequest::new(&start).with_seed(seed);
+            match player.make_move(&request) {
+                PlayerAction::Move(mv) if mv == d4 => d4_count += 1,
+                action => assert_eq!(action, e4.into()),
+            }
+        }
+        assert!((55..95).contains(&d4_count), "{d4_count}");
+    }
+}
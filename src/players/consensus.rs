@@ -0,0 +1,166 @@
+use crate::{pieces::Move, play::GameResult};
+
+use super::{Evaluation, MoveRequest, Player, PlayerAction};
+
+// ConsensusPlayer asks all of its players for a move and plays the one most of them
+// chose, on a tie the one chosen first. Moves are the same when they have the same
+// squares and special move. When nobody proposes a move, it does what the first
+// player wants.
+pub struct ConsensusPlayer {
+    players: Vec<Box<dyn Player>>,
+    chosen_by: usize, // the first player that proposed the last move played
+}
+
+impl ConsensusPlayer {
+    pub fn new(players: Vec<Box<dyn Player>>) -> Self {
+        assert!(!players.is_empty(), "a consensus needs players");
+        ConsensusPlayer {
+            players,
+            chosen_by: 0,
+        }
+    }
+}
+
+// The moves proposed with their votes, in the order they were first proposed
+fn count_votes(actions: &[PlayerAction]) -> Vec<(Move, usize)> {
+    let mut votes: Vec<(Move, usize)> = Vec::new();
+    for action in actions {
+        let PlayerAction::Move(mv) = action else {
+            continue;
+        };
+        match votes.iter_mut().find(|(proposed, _)| proposed == mv) {
+            Some((_, count)) => *count += 1,
+            None => votes.push((*mv, 1)),
+        }
+    }
+    votes
+}
+
+impl Player for ConsensusPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let actions: Vec<PlayerAction> = self
+            .players
+            .iter_mut()
+            .map(|player| player.make_move(request))
+            .collect();
+        let votes = count_votes(&actions);
+        // max_by_key keeps the last of equal votes, so go through them backwards
+        let Some(&(mv, count)) = votes.iter().rev().max_by_key(|(_, count)| *count) else {
+            self.chosen_by = 0;
+            return actions[0];
+        };
+        self.chosen_by = actions
+            .iter()
+            .position(|action| *action == PlayerAction::Move(mv))
+            .expect("the move was proposed");
+
+        if count < self.players.len() {
+            let tally: Vec<String> = votes
+                .iter()
+                .map(|(mv, count)| format!("{} {count}", mv.san(request.board)))
+                .collect();
+            eprintln!("{}: votes {}", self.name(), tally.join(", "));
+        }
+        mv.into()
+    }
+
+    fn name(&self) -> String {
+        let names: Vec<String> = self.players.iter().map(|player| player.name()).collect();
+        format!("ConsensusPlayer ({})", names.join(", "))
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.players[self.chosen_by].last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.players[self.chosen_by].last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.players.iter().map(|player| player.hints_used()).sum()
+    }
+
+    // Only when most of the players accept
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        let accepting = self
+            .players
+            .iter_mut()
+            .filter_map(|player| player.accepts_draw(request).then_some(()))
+            .count();
+        2 * accepting > self.players.len()
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        for player in self.players.iter_mut() {
+            player.game_over(request, result);
+        }
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.players.iter().find_map(|player| player.start_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, players::ScriptedPlayer};
+
+    fn consensus(moves: &[&str]) -> ConsensusPlayer {
+        let players = moves
+            .iter()
+            .map(|mv| Box::new(ScriptedPlayer::new(&[mv])) as Box<dyn Player>)
+            .collect();
+        ConsensusPlayer::new(players)
+    }
+
+    fn play(player: &mut ConsensusPlayer) -> String {
+        let board = Board::new(None).unwrap();
+        let PlayerAction::Move(mv) = player.make_move(&MoveRequest::new(&board)) else {
+            panic!("the players propose moves");
+        };
+        mv.san(&board)
+    }
+
+    #[test]
+    fn test_unanimous() {
+        let mut player = consensus(&["e2e4", "e2e4", "e2e4"]);
+        assert_eq!(play(&mut player), "e4");
+        assert_eq!(player.chosen_by, 0);
+    }
+
+    #[test]
+    fn test_majority() {
+        let mut player = consensus(&["d2d4", "e2e4", "g1f3", "e2e4"]);
+        assert_eq!(play(&mut player), "e4");
+        assert_eq!(player.chosen_by, 1);
+    }
+
+    #[test]
+    fn test_tie() {
+        // the first choice among the most voted moves wins
+        let mut player = consensus(&["g1f3", "d2d4", "e2e4", "e2e4", "d2d4"]);
+        assert_eq!(play(&mut player), "d4");
+        assert_eq!(player.chosen_by, 1);
+
+        let mut player = consensus(&["c2c4", "b1c3"]);
+        assert_eq!(play(&mut player), "c4");
+    }
+
+    #[test]
+    fn test_count_votes() {
+        let board = Board::new(None).unwrap();
+        // the same move, found in different ways
+        let e4 = board.parse_move("e2e4").unwrap();
+        let san = board.parse_san("e4").unwrap();
+        let actions = [
+            PlayerAction::Resign,
+            e4.into(),
+            PlayerAction::OfferDraw,
+            san.into(),
+        ];
+        assert_eq!(count_votes(&actions), [(e4, 2)]);
+        assert!(count_votes(&[PlayerAction::NoMoves]).is_empty());
+    }
+}
@@ -1,51 +1,337 @@
-use std::{fmt::Display, ops::Neg};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashMap,
+    fmt::Display,
+    ops::Neg,
+    time::{Duration, Instant},
+};
 
-use rand::Rng;
+use anyhow::ensure;
+use clap::ValueEnum;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
 
 use crate::{
-    board::{Board, Position},
+    bitboards::BitboardGame,
+    board::{piece_value, Board, Position, FIFTY_MOVE_PLIES},
     pieces::{Color, Move, Piece, PieceType},
 };
 
-use super::Player;
+use super::{MoveRequest, Player, PlayerAction};
+
+pub struct EnginePlayer {
+    depth: usize,                 // the deepest the search goes, in plies
+    time_limit: Option<Duration>, // per move, on top of what the clock allows
+    params: SearchParams,
+    skill: Option<Skill>, // plays weaker on purpose, if set
+    last_eval: Option<Evaluation>,
+    last_line: Option<Vec<Move>>,
+    table: TranspositionTable, // kept from move to move
+}
+
+// Settings of the search besides its depth
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SearchParams {
+    pub ordering: MoveOrdering, // changes how fast the search is, not the move it finds
+    // in centipawns, what a draw is worth for the side to move at the root, e.g.
+    // -300 when it has to win. None leaves draws even.
+    pub root_draw: Option<i32>,
+    pub backend: Backend,
+}
+
+// The board the search runs on. The bitboards search faster, but evaluate only
+// material and passed pawns.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    #[default]
+    Mailbox,
+    Bitboard,
+}
+
+// The order the moves of a position are searched in. Good moves first lead to
+// more cutoffs.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MoveOrdering {
+    Generated, // as the board generates them
+    MoveOrd,   // by the Ord of Move, special moves first
+    #[default]
+    MvvLva, // captures first, of the most valuable victim by the least valuable attacker
+}
+
+// How a weakened engine picks its moves: it scores every move with a shallow
+// search, then picks among the best at random, now and then giving away some
+// material on purpose
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Skill {
+    pub depth: usize,        // searches no deeper than this
+    pub candidates: usize,   // picks among this many of the best moves
+    pub temperature: f64,    // in centipawns, the higher the more even the choice among them
+    pub blunder_chance: f64, // how often it plays any move that loses at most max_loss
+    pub max_loss: i32,       // in centipawns
+}
+
+pub const MAX_SKILL: u8 = 9;
+
+impl Skill {
+    // From 0, a beginner, up to MAX_SKILL, which still plays noticeably weaker
+    // than the engine at full strength
+    pub fn level(level: u8) -> Self {
+        let level = level.min(MAX_SKILL) as i32;
+        Skill {
+            depth: 1 + level as usize / 4,
+            candidates: 5 - level as usize / 3,
+            temperature: (200 - 20 * level) as f64,
+            blunder_chance: 0.3 - 0.03 * level as f64,
+            max_loss: 300 - 25 * level,
+        }
+    }
+}
 
-pub struct EnginePlayer;
+// Mates count as more than any material
+const MATE_CENTIPAWNS: i32 = 100_000;
+
+fn centipawns(eval: Evaluation) -> i32 {
+    match eval {
+        Evaluation::Win(plies) => MATE_CENTIPAWNS - plies as i32,
+        Evaluation::Eval(eval) => eval,
+        Evaluation::Draw => 0,
+        Evaluation::Loss(plies) => -MATE_CENTIPAWNS + plies as i32,
+    }
+}
+
+const SEARCH_DEPTH: usize = 4;
+// Analysis is limited by time, this only keeps it from running away in simple positions
+const MAX_ANALYSIS_DEPTH: usize = 64;
+
+impl Default for EnginePlayer {
+    fn default() -> Self {
+        EnginePlayer::with_depth(SEARCH_DEPTH)
+    }
+}
 
 impl Player for EnginePlayer {
-    fn make_move(&self, board: &Board) -> Move {
-        let (eval, best_move) = EnginePlayer::evaluate(board);
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        // the search would still find moves that leave the king to be taken
+        if request.board.legal_moves(request.color).is_empty() {
+            return PlayerAction::NoMoves;
+        }
+        let budget = match (request.clock, self.time_limit) {
+            (Some(clock), Some(limit)) => Some(clock.move_budget(request.color).min(limit)),
+            (Some(clock), None) => Some(clock.move_budget(request.color)),
+            (None, limit) => limit,
+        };
+        let deadline = budget.map(|budget| Instant::now() + budget);
+        let previous = request.repetitions.cloned().unwrap_or_default();
+        let mut rng = request.rng();
+        let (eval, line) = match self.skill {
+            Some(skill) => {
+                let depth = self.depth.min(skill.depth);
+                let scored = score_moves(
+                    request.board,
+                    depth,
+                    deadline,
+                    &previous,
+                    &mut self.table,
+                    &mut rng,
+                );
+                let (eval, line) = &scored[skill.choose(&scored, &mut rng)];
+                (*eval, line.clone())
+            }
+            None => {
+                let search = EnginePlayer::search(
+                    request.board,
+                    self.depth,
+                    deadline,
+                    &previous,
+                    self.params,
+                    &mut self.table,
+                    &mut rng,
+                );
+                (search.eval, search.line)
+            }
+        };
 
-        //println!("Eval: {}", eval);
-        best_move
+        self.last_eval = Some(eval);
+        let mv = line[0];
+        self.last_line = Some(line);
+        mv.into()
+    }
+
+    fn name(&self) -> String {
+        match self.skill {
+            Some(_) => format!("EnginePlayer depth {} weakened", self.depth),
+            None => format!("EnginePlayer depth {}", self.depth),
+        }
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.last_eval
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.last_line.clone()
     }
 }
 
 impl EnginePlayer {
-    pub fn evaluate(board: &Board) -> (Evaluation, Move) {
-        let (white, black) = board.count_pieces();
-        let depth = if white + black < 5 {
-            4
-        } else if white + black < 10 {
-            4
-        } else {
-            4
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_depth(depth: usize) -> Self {
+        EnginePlayer {
+            depth,
+            time_limit: None,
+            params: SearchParams::default(),
+            skill: None,
+            last_eval: None,
+            last_line: None,
+            table: TranspositionTable::default(),
+        }
+    }
+
+    // Stops deepening the search once the time is up
+    pub fn with_time_limit(self, time: Duration) -> Self {
+        EnginePlayer {
+            time_limit: Some(time),
+            ..self
+        }
+    }
+
+    // Plays weaker on purpose, see Skill
+    pub fn with_skill(self, skill: Skill) -> Self {
+        EnginePlayer {
+            skill: Some(skill),
+            ..self
+        }
+    }
+
+    pub fn with_params(self, params: SearchParams) -> Self {
+        EnginePlayer { params, ..self }
+    }
+
+    // Avoids draws at the root, counting them as a loss of the given centipawns
+    pub fn with_root_draw(self, centipawns: i32) -> Self {
+        let params = SearchParams {
+            root_draw: Some(-centipawns.abs()),
+            ..self.params
         };
-        let color = board.current_turn();
-        let (eval_board, eval) = negamax_search(board, depth, color);
-
-        (
-            eval,
-            eval_board
-                .last_move
-                .expect("There will always be a last move"),
-        )
+        EnginePlayer { params, ..self }
+    }
+
+    pub fn evaluate(board: &Board) -> anyhow::Result<(Evaluation, Move)> {
+        EnginePlayer::evaluate_with(board, SearchParams::default())
+    }
+
+    pub fn evaluate_with(
+        board: &Board,
+        params: SearchParams,
+    ) -> anyhow::Result<(Evaluation, Move)> {
+        ensure!(
+            board.is_legal_position(),
+            "cannot evaluate an illegal position: {}",
+            board.get_fen()
+        );
+        let search = EnginePlayer::search(
+            board,
+            SEARCH_DEPTH,
+            None,
+            &HashMap::new(),
+            params,
+            &mut TranspositionTable::default(),
+            &mut StdRng::from_entropy(),
+        );
+        Ok((search.eval, search.line[0]))
+    }
+
+    // Searches for up to the given time, returning the evaluation and the line of
+    // best play the engine expects
+    pub fn analyse(board: &Board, time: Duration) -> anyhow::Result<(Evaluation, Vec<Move>)> {
+        ensure!(
+            board.is_legal_position(),
+            "cannot evaluate an illegal position: {}",
+            board.get_fen()
+        );
+        let search = EnginePlayer::search(
+            board,
+            MAX_ANALYSIS_DEPTH,
+            Some(Instant::now() + time),
+            &HashMap::new(),
+            SearchParams::default(),
+            &mut TranspositionTable::default(),
+            &mut StdRng::from_entropy(),
+        );
+        Ok((search.eval, search.line))
+    }
+
+    // With a deadline, searches deeper and deeper until it passes. Positions that
+    // occurred earlier in the game count as draws when reached again. Returns the
+    // principal variation, which always has at least one move, and the nodes
+    // visited at all depths together.
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        board: &Board,
+        depth: usize,
+        deadline: Option<Instant>,
+        previous: &HashMap<u64, u8>,
+        params: SearchParams,
+        table: &mut TranspositionTable,
+        rng: &mut StdRng, // for the evaluation noise
+    ) -> SearchResult<Move> {
+        match params.backend {
+            Backend::Mailbox => deepen(board, depth, deadline, previous, params, table, rng),
+            Backend::Bitboard => {
+                let node = BitboardNode {
+                    game: BitboardGame::from(board),
+                    last_move: None,
+                };
+                deepen(&node, depth, deadline, previous, params, table, rng)
+            }
+        }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Ord)]
+// The iterative deepening of EnginePlayer::search, on either board
+fn deepen<Node: SearchNode>(
+    node: &Node,
+    depth: usize,
+    deadline: Option<Instant>,
+    previous: &HashMap<u64, u8>,
+    params: SearchParams,
+    table: &mut TranspositionTable,
+    rng: &mut StdRng,
+) -> SearchResult<Move> {
+    let color = node.turn();
+
+    // with a deadline, deepen iteratively starting from depth 1. The first
+    // search ignores the deadline so that there is always a move to play.
+    let first_depth = if deadline.is_some() { 1 } else { depth };
+    let search = negamax_search(node, first_depth, color, None, previous, params, table, rng)
+        .expect("Search without deadline always finishes");
+    let (mut line, mut eval, mut nodes) = (search.line, search.eval, search.nodes);
+    for depth in first_depth + 1..=depth {
+        match negamax_search(node, depth, color, deadline, previous, params, table, rng) {
+            Some(search) => {
+                (line, eval) = (search.line, search.eval);
+                nodes += search.nodes;
+            }
+            None => break,
+        }
+    }
+
+    let line: Vec<Move> = line.iter().filter_map(|node| node.last_move()).collect();
+    assert!(!line.is_empty(), "There will always be a last move");
+    SearchResult { line, eval, nodes }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Evaluation {
     Win(usize),
     Eval(i32),
+    Draw, // by repetition or the 50-move rule, compares as an even position
     Loss(usize),
 }
 
@@ -54,9 +340,19 @@ impl Evaluation {
         match self {
             Evaluation::Win(depth) => Evaluation::Win(depth + 1),
             Evaluation::Eval(eval) => Evaluation::Eval(*eval),
+            Evaluation::Draw => Evaluation::Draw,
             Evaluation::Loss(depth) => Evaluation::Loss(depth + 1),
         }
     }
+
+    // The inverse of increment_depth, for passing bounds down to a child
+    fn decrement_depth(&self) -> Evaluation {
+        match self {
+            Evaluation::Win(depth) => Evaluation::Win(depth.saturating_sub(1)),
+            Evaluation::Loss(depth) => Evaluation::Loss(depth.saturating_sub(1)),
+            eval => *eval,
+        }
+    }
 }
 
 impl Display for Evaluation {
@@ -64,27 +360,40 @@ impl Display for Evaluation {
         match self {
             Evaluation::Win(ply) => write!(f, "W{}", ply),
             Evaluation::Eval(eval) => write!(f, "{:.2}", eval),
+            Evaluation::Draw => write!(f, "D"),
             Evaluation::Loss(ply) => write!(f, "L{}", ply),
         }
     }
 }
 
-impl PartialOrd for Evaluation {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+// The order the search uses, best for the side to move last. Note that a draw
+// compares equal to Eval(0) here while == still tells them apart.
+impl Ord for Evaluation {
+    fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
+            // a draw is as good as an even position
+            (Evaluation::Draw, Evaluation::Draw) => Ordering::Equal,
+            (Evaluation::Draw, _) => Evaluation::Eval(0).cmp(other),
+            (_, Evaluation::Draw) => self.cmp(&Evaluation::Eval(0)),
             // wins in fewer moves are better
-            (Evaluation::Win(left), Evaluation::Win(right)) => right.partial_cmp(left),
-            (Evaluation::Win(_), _) => Some(std::cmp::Ordering::Greater),
-            (_, Evaluation::Win(_)) => Some(std::cmp::Ordering::Less),
-            (Evaluation::Eval(left), Evaluation::Eval(right)) => left.partial_cmp(right),
-            (Evaluation::Eval(_), _) => Some(std::cmp::Ordering::Greater),
-            (_, Evaluation::Eval(_)) => Some(std::cmp::Ordering::Less),
+            (Evaluation::Win(left), Evaluation::Win(right)) => right.cmp(left),
+            (Evaluation::Win(_), _) => Ordering::Greater,
+            (_, Evaluation::Win(_)) => Ordering::Less,
+            (Evaluation::Eval(left), Evaluation::Eval(right)) => left.cmp(right),
+            (Evaluation::Eval(_), _) => Ordering::Greater,
+            (_, Evaluation::Eval(_)) => Ordering::Less,
             // losses in more moves are better
-            (Evaluation::Loss(left), Evaluation::Loss(right)) => left.partial_cmp(right),
+            (Evaluation::Loss(left), Evaluation::Loss(right)) => left.cmp(right),
         }
     }
 }
 
+impl PartialOrd for Evaluation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Neg for Evaluation {
     type Output = Evaluation;
 
@@ -92,107 +401,468 @@ impl Neg for Evaluation {
         match self {
             Evaluation::Win(moves) => Evaluation::Loss(moves),
             Evaluation::Eval(eval) => Evaluation::Eval(-eval),
+            Evaluation::Draw => Evaluation::Draw,
             Evaluation::Loss(moves) => Evaluation::Win(moves),
         }
     }
 }
 
 trait SearchNode: Sized + Copy {
-    fn get_next_states(&self) -> Vec<Self>
+    fn get_next_states(&self, ordering: MoveOrdering) -> Vec<Self>
     where
         Self: Sized + Copy;
 
-    fn evaluate(&self) -> Evaluation;
+    fn evaluate(&self, rng: &mut StdRng) -> Evaluation;
+
+    fn position_key(&self) -> u64;
+
+    // Plies since the last pawn move or capture
+    fn halfmoves(&self) -> usize;
+
+    // Whether the side to move could have its king taken right away
+    fn is_in_check(&self) -> bool;
+
+    // Whether the move that led here left the mover's own king to be taken
+    fn exposes_king(&self) -> bool;
+
+    fn turn(&self) -> Color;
+
+    // The move that led here, None at the root
+    fn last_move(&self) -> Option<Move>;
 }
 
 impl SearchNode for Board {
-    fn get_next_states(&self) -> Vec<Board> {
-        let pieces = self.get_pieces(self.current_turn());
+    fn get_next_states(&self, ordering: MoveOrdering) -> Vec<Board> {
         // our king has been taken, game is over
-        if let None = pieces.iter().find(|(_, p)| p.typ == PieceType::King) {
+        if self.king_position(self.current_turn()).is_none() {
             return Vec::new();
         }
+        let pieces = self.get_pieces(self.current_turn());
         let mut moves: Vec<Move> = pieces
             .iter()
             .flat_map(|(pos, _)| self.get_moves(pos))
             .flatten()
             .collect();
 
-        // will search through moves that are likely to be decisive (captures etc) first
-        moves.sort();
+        match ordering {
+            MoveOrdering::Generated => {}
+            MoveOrdering::MoveOrd => moves.sort(),
+            // the sort is stable, so quiet moves stay in the order they were generated
+            MoveOrdering::MvvLva => {
+                let value = |square| self[square].map_or(0, |piece| piece_value(square, &piece));
+                moves.sort_by_cached_key(|mv| Reverse(mvv_lva(mv, value)))
+            }
+        }
 
         moves.iter().map(|mv| self.apply(&mv)).collect()
     }
 
-    fn evaluate(&self) -> Evaluation {
+    // the same key the game counts repetitions with
+    fn position_key(&self) -> u64 {
+        Board::repetition_key(self)
+    }
+
+    fn halfmoves(&self) -> usize {
+        self.ply - self.last_pawn_move
+    }
+
+    fn is_in_check(&self) -> bool {
+        Board::is_in_check(self, self.current_turn())
+    }
+
+    fn exposes_king(&self) -> bool {
+        Board::is_in_check(self, !self.current_turn())
+    }
+
+    fn turn(&self) -> Color {
+        self.current_turn()
+    }
+
+    fn last_move(&self) -> Option<Move> {
+        self.last_move
+    }
+
+    fn evaluate(&self, rng: &mut StdRng) -> Evaluation {
         let color = self.current_turn();
-        let friendly = self.get_pieces(color);
-        if friendly
-            .iter()
-            .find(|(_, p)| p.typ == PieceType::King)
-            .is_none()
-        {
+        if self.king_position(color).is_none() {
             return Evaluation::Loss(0);
         }
-        let enemy = self.get_pieces(!color);
-        if enemy
-            .iter()
-            .find(|(_, p)| p.typ == PieceType::King)
-            .is_none()
+        if self.king_position(!color).is_none() {
+            return Evaluation::Win(0);
+        }
+        // a lone king with nowhere to go and not in check is stalemated, which the
+        // search would only find out a ply later
+        if is_lone_king(self, color)
+            && !Board::is_in_check(self, color)
+            && free_squares(self, !color) == Some(0)
         {
+            return Evaluation::Draw;
+        }
+        let material = self.material_balance(color);
+
+        let endgame =
+            push_to_corner(self, color).unwrap_or(0) - push_to_corner(self, !color).unwrap_or(0);
+        let outposts = outposts(self, color) - outposts(self, !color);
+        let passers = passed_pawns(self, color) - passed_pawns(self, !color);
+
+        let noise: i32 = rng.gen_range(-10..=10);
+        let mut eval = material + endgame + outposts + passers + noise;
+        // a side with only its king left can not win, at best it holds a draw
+        if is_lone_king(self, color) {
+            eval = eval.min(0);
+        }
+        if is_lone_king(self, !color) {
+            eval = eval.max(0);
+        }
+        Evaluation::Eval(eval)
+    }
+}
+
+// The bitboards keep no moves, the search needs the one that led to a position
+#[derive(Copy, Clone)]
+struct BitboardNode {
+    game: BitboardGame,
+    last_move: Option<Move>,
+}
+
+impl SearchNode for BitboardNode {
+    fn get_next_states(&self, ordering: MoveOrdering) -> Vec<BitboardNode> {
+        let game = &self.game;
+        // our king has been taken, game is over
+        if game.pieces((game.turn(), PieceType::King)) == 0 {
+            return Vec::new();
+        }
+        let mut moves = game.pseudo_legal_moves();
+
+        match ordering {
+            MoveOrdering::Generated => {}
+            MoveOrdering::MoveOrd => moves.sort(),
+            MoveOrdering::MvvLva => {
+                let value = |square: Position| {
+                    let index = (square.rank() * 8 + square.file()) as u32;
+                    game.get(index).map_or(0, |(color, typ)| {
+                        let piece = Piece {
+                            color,
+                            typ,
+                            most_recent_move: None,
+                        };
+                        piece_value(square, &piece)
+                    })
+                };
+                moves.sort_by_cached_key(|mv| Reverse(mvv_lva(mv, value)))
+            }
+        }
+
+        moves
+            .iter()
+            .map(|mv| {
+                let mut child = *self;
+                child.game.make_move(mv);
+                child.last_move = Some(*mv);
+                child
+            })
+            .collect()
+    }
+
+    fn position_key(&self) -> u64 {
+        self.game.repetition_key()
+    }
+
+    fn halfmoves(&self) -> usize {
+        self.game.halfmoves() as usize
+    }
+
+    fn is_in_check(&self) -> bool {
+        let color = self.game.turn();
+        let king = self.game.pieces((color, PieceType::King));
+        king != 0 && self.game.is_attacked(king.trailing_zeros(), !color)
+    }
+
+    fn exposes_king(&self) -> bool {
+        let color = !self.game.turn();
+        let king = self.game.pieces((color, PieceType::King));
+        king != 0 && self.game.is_attacked(king.trailing_zeros(), !color)
+    }
+
+    fn turn(&self) -> Color {
+        self.game.turn()
+    }
+
+    fn last_move(&self) -> Option<Move> {
+        self.last_move
+    }
+
+    // Material and passed pawns, as on the mailbox board
+    fn evaluate(&self, rng: &mut StdRng) -> Evaluation {
+        let game = &self.game;
+        let color = game.turn();
+        let has_king = |color| game.pieces((color, PieceType::King)) != 0;
+        if !has_king(color) {
+            return Evaluation::Loss(0);
+        }
+        if !has_king(!color) {
             return Evaluation::Win(0);
         }
-        fn sum_piece_values(pieces: Vec<(Position, Piece)>) -> i32 {
-            pieces
+        let material = game.material_balance(color);
+        let passed = |color| game.passed_pawns(color).count_ones() as i32;
+        let passers = PASSED_PAWN_BONUS * (passed(color) - passed(!color));
+
+        let noise: i32 = rng.gen_range(-10..=10);
+        let mut eval = material + passers + noise;
+        let is_lone_king = |color| game.occupancy(color).count_ones() == 1;
+        if is_lone_king(color) {
+            eval = eval.min(0);
+        }
+        if is_lone_king(!color) {
+            eval = eval.max(0);
+        }
+        Evaluation::Eval(eval)
+    }
+}
+
+const OUTPOST_BONUS: i32 = 25;
+
+// Knights in the opponent's half, backed by a pawn, on a square no enemy pawn can
+// ever attack, are hard to drive away
+fn outposts(board: &Board, color: Color) -> i32 {
+    let count = board
+        .get_pieces(color)
+        .into_iter()
+        .filter(|(pos, piece)| {
+            let enemy_half = match color {
+                Color::White => pos.rank() >= 4,
+                Color::Black => pos.rank() <= 3,
+            };
+            piece.typ == PieceType::Knight
+                && enemy_half
+                && board.is_attacked_by_pawn(pos, color)
+                && !board.can_pawn_attack(pos, !color)
+        })
+        .count();
+    OUTPOST_BONUS * count as i32
+}
+
+const PASSED_PAWN_BONUS: i32 = 20;
+
+// Pawns with no enemy pawn left to stop them, on top of what they are worth for
+// how far they have come
+fn passed_pawns(board: &Board, color: Color) -> i32 {
+    PASSED_PAWN_BONUS * board.passed_pawns(color).len() as i32
+}
+
+fn is_lone_king(board: &Board, color: Color) -> bool {
+    board.get_pieces(color).len() == 1
+}
+
+// How many squares the king of the weaker side can go to without being attacked
+fn free_squares(board: &Board, strong: Color) -> Option<usize> {
+    let king = board.king_position(!strong)?;
+    let free = board
+        .get_moves(&king)
+        .unwrap_or_default()
+        .iter()
+        .filter(|mv| !board.is_square_attacked(&mv.to, strong))
+        .count();
+    Some(free)
+}
+
+// Against a lone king, material alone does not show progress. Rewards the side
+// that can force mate without pawns, as in KQ vs K and KR vs K, for driving the
+// lone king to the edge, taking away its squares and approaching it with the
+// friendly king.
+fn push_to_corner(board: &Board, strong: Color) -> Option<i32> {
+    if !is_lone_king(board, !strong) || board.is_insufficient_material(strong) {
+        return None;
+    }
+    // with pawns, promoting them is the way forward
+    let pawns = board
+        .get_pieces(strong)
+        .iter()
+        .any(|(_, piece)| piece.typ == PieceType::Pawn);
+    if pawns {
+        return None;
+    }
+
+    let strong_king = board.king_position(strong)?;
+    let lone_king = board.king_position(!strong)?;
+    let edge = lone_king.center_distance() as i32; // 0 to 3
+    let closeness = 7 - strong_king.distance(&lone_king) as i32; // 0 to 6
+    let boxed_in = 8 - free_squares(board, strong)? as i32; // 0 to 8
+    Some(50 * edge + 20 * closeness + 20 * boxed_in)
+}
+
+impl Skill {
+    // Picks one of the scored moves, which are sorted best first
+    fn choose(&self, scored: &[(Evaluation, Vec<Move>)], rng: &mut StdRng) -> usize {
+        let best = centipawns(scored[0].0);
+        if rng.gen_bool(self.blunder_chance) {
+            let bad = scored
                 .iter()
-                .map(|(pos, piece)| match piece.typ {
-                    PieceType::King => 10000, // technically infinite, but this will probably suffice
-                    PieceType::Queen => 900,
-                    PieceType::Rook => 500,
-                    PieceType::Bishop => 300,
-                    PieceType::Knight => 275,
-                    PieceType::Pawn => {
-                        // values for white
-                        const PAWN_VALUES: [i32; 8] = [0, 100, 105, 110, 125, 160, 200, 900];
-                        let idx = match piece.color {
-                            Color::White => pos.rank(),
-                            Color::Black => 7 - pos.rank(),
-                        };
-                        PAWN_VALUES[idx]
-                    }
-                })
-                .sum()
+                .take_while(|(eval, _)| centipawns(*eval) >= best - self.max_loss)
+                .count();
+            return rng.gen_range(0..bad);
+        }
+        let weights = scored
+            .iter()
+            .take(self.candidates.max(1))
+            .map(|(eval, _)| ((centipawns(*eval) - best) as f64 / self.temperature).exp());
+        WeightedIndex::new(weights).map_or(0, |weights| weights.sample(rng))
+    }
+}
+
+// Scores every legal move with a search of the given depth, returning the evaluation
+// and expected line of each, best first. Moves not reached by the deadline are left
+// out, but the first is always scored.
+fn score_moves(
+    board: &Board,
+    depth: usize,
+    deadline: Option<Instant>,
+    previous: &HashMap<u64, u8>,
+    table: &mut TranspositionTable,
+    rng: &mut StdRng,
+) -> Vec<(Evaluation, Vec<Move>)> {
+    let color = board.current_turn();
+    let mut scored = Vec::new();
+    for mv in board.legal_moves(color) {
+        let child = board.apply(&mv);
+        let deadline = if scored.is_empty() { None } else { deadline };
+        let params = SearchParams::default();
+        let Some(search) = negamax_search(
+            &child,
+            depth - 1,
+            !color,
+            deadline,
+            previous,
+            params,
+            table,
+            rng,
+        ) else {
+            break;
+        };
+        let mut line = vec![mv];
+        line.extend(search.line.iter().filter_map(|board| board.last_move));
+        scored.push(((-search.eval).increment_depth(), line));
+    }
+    // stable, so equal moves keep the order of the board
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    scored
+}
+
+// Ranks captures by the value of the victim, then by the attacker the cheaper the
+// better. Moves that capture nothing rank last. The value is that of the piece on
+// a square, on either board.
+fn mvv_lva(mv: &Move, value: impl Fn(Position) -> i32) -> (i32, i32) {
+    let Some((_, square)) = mv.captured() else {
+        return (0, 0);
+    };
+    (value(square), -value(mv.from))
+}
+
+// Above this many entries the table starts over, to bound its memory
+const MAX_TABLE_ENTRIES: usize = 1 << 20;
+
+// The best child found for each position searched, by position keys. Searching it
+// first again leads to earlier cutoffs, in deeper iterations and in the searches
+// for later moves of the game.
+#[derive(Default)]
+struct TranspositionTable {
+    best: HashMap<u64, u64>,
+}
+
+impl TranspositionTable {
+    fn best_child(&self, key: u64) -> Option<u64> {
+        self.best.get(&key).copied()
+    }
+
+    fn insert(&mut self, key: u64, child: u64) {
+        if self.best.len() >= MAX_TABLE_ENTRIES {
+            self.best.clear();
         }
-        let enemy_pieces: i32 = sum_piece_values(enemy);
-        let friendly_pieces: i32 = sum_piece_values(friendly);
+        self.best.insert(key, child);
+    }
+}
+
+// The positions that make a repeated position a draw
+struct History<'a> {
+    game: &'a HashMap<u64, u8>, // played before the search started
+    line: Vec<u64>,             // leading up to the current node in the search
+    nodes: usize,               // positions visited so far, to compare move orderings
+    table: &'a mut TranspositionTable,
+}
 
-        let noise: i32 = rand::thread_rng().gen_range(-10..=10);
-        Evaluation::Eval(friendly_pieces - enemy_pieces + noise)
+impl History<'_> {
+    fn contains(&self, key: u64) -> bool {
+        self.game.contains_key(&key) || self.line.contains(&key)
     }
 }
 
+// The outcome of a search that finished
+struct SearchResult<Node> {
+    line: Vec<Node>, // the best line found
+    eval: Evaluation,
+    nodes: usize, // positions visited
+}
+
+// Returns None if the deadline passes before the search is done
+#[allow(clippy::too_many_arguments)]
 fn negamax_search<Node: SearchNode>(
     initial: &Node,
     max_depth: usize,
     color: Color,
-) -> (Node, Evaluation) {
+    deadline: Option<Instant>,
+    previous: &HashMap<u64, u8>,
+    params: SearchParams,
+    table: &mut TranspositionTable,
+    rng: &mut StdRng,
+) -> Option<SearchResult<Node>> {
+    #[allow(clippy::too_many_arguments)]
     fn inner<Node: SearchNode>(
         node: &Node,
         depth: usize,
         mut alpha: Evaluation,
         beta: Evaluation,
         color: Color, // maximizing player
-    ) -> (Node, Evaluation) {
-        let indent = " ".repeat(2 * (4 - depth.max(0)));
-        let child_nodes = node.get_next_states();
+        deadline: Option<Instant>,
+        params: SearchParams,
+        history: &mut History,
+        rng: &mut StdRng,
+    ) -> Option<(Vec<Node>, Evaluation)> {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return None;
+        }
+        history.nodes += 1;
+        let is_root = history.line.is_empty();
+        let key = node.position_key();
+        // the root is always in the game history, but it is not a repetition
+        if !history.line.is_empty()
+            && (history.contains(key) || node.halfmoves() >= FIFTY_MOVE_PLIES)
+        {
+            return Some((Vec::new(), Evaluation::Draw));
+        }
+        let indent = " ".repeat(2 * history.line.len());
+        let mut child_nodes = node.get_next_states(params.ordering);
+        // the move played has to be legal, which a shallow search would not see. When
+        // there is none, the search tells mate from stalemate.
+        if is_root && child_nodes.iter().any(|child| !child.exposes_king()) {
+            child_nodes.retain(|child| !child.exposes_king());
+        }
+        // the best child last time goes first, the rest keep their order
+        if let Some(best) = history.table.best_child(key) {
+            if let Some(index) = child_nodes
+                .iter()
+                .position(|child| child.position_key() == best)
+            {
+                child_nodes[..=index].rotate_right(1);
+            }
+        }
         if depth == 0 || child_nodes.is_empty() {
-            let eval = node.evaluate();
+            let eval = node.evaluate(rng);
 
             eprintln!(
                 "{indent}leaf({:?}): eval = {}, alpha = {}, beta = {}",
                 color, eval, alpha, beta
             );
-            return (*node, eval);
+            return Some((Vec::new(), eval));
         }
 
         eprintln!(
@@ -202,19 +872,42 @@ fn negamax_search<Node: SearchNode>(
             alpha,
             beta,
         );
+        // fail-soft: the best eval is returned even when it falls outside the
+        // window. It is always taken from the first child, so Loss(0) never sticks.
         let mut best_eval = Evaluation::Loss(0);
-        let mut best_child = None;
+        let mut best_line = None; // starting with the best child
+        let mut cut_off = false;
+        history.line.push(key);
         for child in child_nodes {
-            let (_, child_eval) = inner(&child, depth - 1, -beta, -alpha, !color);
-            let child_eval = -child_eval;
+            // the child adds a ply to mate distances on the way back up, so the
+            // window is moved the other way
+            let (child_line, child_eval) = inner(
+                &child,
+                depth - 1,
+                (-beta).decrement_depth(),
+                (-alpha).decrement_depth(),
+                !color,
+                deadline,
+                params,
+                history,
+                rng,
+            )?;
+            let child_eval = match (-child_eval, params.root_draw) {
+                // only the choice of the move to play, draws deeper in the tree stay even
+                (Evaluation::Draw, Some(draw)) if is_root => Evaluation::Eval(draw),
+                (child_eval, _) => child_eval,
+            };
             eprintln!("{indent}  child: eval = {}", child_eval);
-            if child_eval >= best_eval {
-                eprintln!("{indent}  best child: {} >= {}", child_eval, best_eval);
+            // a later child that only ties may have been cut off, so its eval is
+            // just an upper bound. Keep the first child with the best eval.
+            if best_line.is_none() || child_eval > best_eval {
+                eprintln!("{indent}  best child: {} > {}", child_eval, best_eval);
                 best_eval = child_eval;
-                best_child = Some(child);
+                let mut line = vec![child];
+                line.extend(child_line);
+                best_line = Some(line);
             }
 
-            alpha = alpha.max(child_eval);
             if child_eval > alpha {
                 eprintln!(
                     "{indent}  update alpha: {} > {}, beta = {}",
@@ -224,25 +917,57 @@ fn negamax_search<Node: SearchNode>(
             }
             if alpha >= beta {
                 eprintln!("{indent}  cutoff: alpha = {}, beta = {}", alpha, beta);
+                cut_off = true;
                 break;
             }
         }
+        history.line.pop();
+        let best_line = best_line.unwrap();
+        history.table.insert(key, best_line[0].position_key());
+        // every move leaves the king to be taken, which is only a loss when it is
+        // attacked already. Otherwise it is stalemate.
+        if !cut_off && best_eval == Evaluation::Loss(1) && !node.is_in_check() {
+            eprintln!("{indent}  stalemate");
+            return Some((best_line, Evaluation::Draw));
+        }
         let best_eval = best_eval.increment_depth();
-        (best_child.unwrap(), best_eval)
+        Some((best_line, best_eval))
     }
 
-    inner(
+    let mut history = History {
+        game: previous,
+        line: Vec::new(),
+        nodes: 0,
+        table,
+    };
+    let (line, eval) = inner(
         initial,
         max_depth,
         Evaluation::Loss(1),
         Evaluation::Win(1),
         color,
-    )
+        deadline,
+        params,
+        &mut history,
+        rng,
+    )?;
+    Some(SearchResult {
+        line,
+        eval,
+        nodes: history.nodes,
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Evaluation;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{
+        clock::Clock,
+        play::{Game, GameResult, Termination},
+        players::RandomPlayer,
+    };
 
     #[test]
     fn test_eval_cmp() {
@@ -269,6 +994,41 @@ mod tests {
         assert!(w1 >= w1);
     }
 
+    #[test]
+    fn test_eval_ord_agrees() {
+        let evals = [
+            Evaluation::Win(1),
+            Evaluation::Win(4),
+            Evaluation::Eval(53),
+            Evaluation::Eval(0),
+            Evaluation::Eval(-50),
+            Evaluation::Draw,
+            Evaluation::Loss(4),
+            Evaluation::Loss(1),
+        ];
+        for left in evals {
+            for right in evals {
+                assert_eq!(Some(left.cmp(&right)), left.partial_cmp(&right));
+                assert_eq!(left.cmp(&right), right.cmp(&left).reverse());
+            }
+        }
+        // max has to pick by the search order, not by declaration order
+        assert_eq!(
+            Evaluation::Loss(1).max(Evaluation::Loss(4)),
+            Evaluation::Loss(4)
+        );
+        assert_eq!(
+            Evaluation::Win(4).max(Evaluation::Win(1)),
+            Evaluation::Win(1)
+        );
+        assert_eq!(
+            Evaluation::Win(4).max(Evaluation::Eval(90)),
+            Evaluation::Win(4)
+        );
+        assert_eq!(Evaluation::Draw.max(Evaluation::Eval(-1)), Evaluation::Draw);
+        assert_eq!(Evaluation::Draw.cmp(&Evaluation::Eval(0)), Ordering::Equal);
+    }
+
     #[test]
     fn test_eval_invert_cmp() {
         let w1 = Evaluation::Win(1);
@@ -282,4 +1042,559 @@ mod tests {
         assert!(better > -w4);
         assert!(better > -better);
     }
+
+    // A game tree given by hand: the children of each node. Nodes without children
+    // have lost, or won when they are listed in wins.
+    #[derive(Copy, Clone)]
+    struct TreeNode {
+        id: usize,
+        tree: &'static [&'static [usize]],
+        wins: &'static [usize],
+    }
+
+    impl SearchNode for TreeNode {
+        fn get_next_states(&self, _ordering: MoveOrdering) -> Vec<Self> {
+            self.tree[self.id]
+                .iter()
+                .map(|&id| TreeNode { id, ..*self })
+                .collect()
+        }
+
+        fn evaluate(&self, _rng: &mut StdRng) -> Evaluation {
+            assert!(self.tree[self.id].is_empty(), "searched to the leaves");
+            if self.wins.contains(&self.id) {
+                Evaluation::Win(0)
+            } else {
+                Evaluation::Loss(0)
+            }
+        }
+
+        fn position_key(&self) -> u64 {
+            self.id as u64
+        }
+
+        fn halfmoves(&self) -> usize {
+            0
+        }
+
+        // every lost leaf is a mate
+        fn is_in_check(&self) -> bool {
+            true
+        }
+
+        fn exposes_king(&self) -> bool {
+            false
+        }
+
+        fn turn(&self) -> Color {
+            Color::White
+        }
+
+        fn last_move(&self) -> Option<Move> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_picks_longest_loss() {
+        // Every move loses. 1 loses after 4 plies, 5 after 2 through 10. Its other
+        // child 6 looks like the longer loss and cut off the search before 10
+        // was seen, when the window was not moved along with the mate distance.
+        let tree: &[&[usize]] = &[
+            &[1, 5],
+            &[2],
+            &[3],
+            &[4],
+            &[],
+            &[6, 10],
+            &[7],
+            &[8],
+            &[9],
+            &[],
+            &[],
+        ];
+        let root = TreeNode {
+            id: 0,
+            tree,
+            wins: &[9],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let search = negamax_search(
+            &root,
+            8,
+            Color::White,
+            None,
+            &HashMap::new(),
+            SearchParams::default(),
+            &mut TranspositionTable::default(),
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(search.eval, Evaluation::Loss(4));
+        let line: Vec<usize> = search.line.iter().map(|node| node.id).collect();
+        assert_eq!(line, [1, 2, 3, 4]);
+    }
+
+    fn search(fen: &str, depth: usize) -> (Evaluation, Vec<Move>) {
+        let board = Board::new(Some(fen.into())).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let search = EnginePlayer::search(
+            &board,
+            depth,
+            None,
+            &HashMap::new(),
+            SearchParams::default(),
+            &mut TranspositionTable::default(),
+            &mut rng,
+        );
+        (search.eval, search.line)
+    }
+
+    #[test]
+    fn test_move_ordering() {
+        // Qxd8+ wins the rook that covers the back rank, whatever the order
+        let fen = "3r2k1/5ppp/8/8/8/8/3Q1PPP/6K1 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let qxd8 = board.parse_san("Qxd8+").unwrap();
+        let mut nodes = Vec::new();
+        for ordering in [
+            MoveOrdering::Generated,
+            MoveOrdering::MoveOrd,
+            MoveOrdering::MvvLva,
+        ] {
+            let params = SearchParams {
+                ordering,
+                ..Default::default()
+            };
+            let mut rng = StdRng::seed_from_u64(0);
+            let search = negamax_search(
+                &board,
+                3,
+                Color::White,
+                None,
+                &HashMap::new(),
+                params,
+                &mut TranspositionTable::default(),
+                &mut rng,
+            )
+            .unwrap();
+            assert_eq!(search.line[0].last_move, Some(qxd8), "{ordering:?}");
+            nodes.push(search.nodes);
+        }
+        // looking at the capture first cuts off more of the tree
+        assert!(nodes[2] < nodes[0], "{nodes:?}");
+    }
+
+    #[test]
+    fn test_table_kept_between_moves() {
+        let mut board = Board::new(None).unwrap();
+        for san in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5"] {
+            board = board.apply(&board.parse_san(san).unwrap());
+        }
+        let search = |board: &Board, table: &mut TranspositionTable| {
+            // without captures first, the order comes from the table alone
+            EnginePlayer::search(
+                board,
+                3,
+                None,
+                &HashMap::new(),
+                SearchParams {
+                    ordering: MoveOrdering::Generated,
+                    ..Default::default()
+                },
+                table,
+                &mut StdRng::seed_from_u64(0),
+            )
+        };
+
+        // the next move of the game, after the reply the engine expects
+        let mut table = TranspositionTable::default();
+        let first = search(&board, &mut table);
+        let later = board.apply(&first.line[0]).apply(&first.line[1]);
+        let reused = search(&later, &mut table);
+        let fresh = search(&later, &mut TranspositionTable::default());
+        assert!(
+            reused.nodes < fresh.nodes,
+            "{} >= {}",
+            reused.nodes,
+            fresh.nodes
+        );
+
+        let mut player = EnginePlayer::with_depth(2);
+        player.make_move(&MoveRequest::new(&board));
+        assert!(player.table.best_child(board.repetition_key()).is_some());
+    }
+
+    #[test]
+    fn test_mate_distances() {
+        // mate distances count plies up to the capture of the king, so a mate in
+        // one is the mating move, any reply and the capture
+        let mate_in_one = "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1";
+        assert_eq!(search(mate_in_one, 4).0, Evaluation::Win(3));
+        let board = Board::new(Some(mate_in_one.into())).unwrap();
+        let mated = board.apply(&board.parse_san("Qfh2").unwrap());
+        assert_eq!(search(&mated.get_fen(), 4).0, Evaluation::Loss(2));
+
+        let mated_in_one = "7k/8/8/8/8/6q1/5q2/1K6 w - - 0 1";
+        assert_eq!(search(mated_in_one, 4).0, Evaluation::Loss(4));
+
+        // Ra8+ Nf8 Rxf8#
+        let mate_in_two = "7k/R7/4n1K1/8/8/8/8/8 w - - 0 1";
+        assert_eq!(search(mate_in_two, 5).0, Evaluation::Win(5));
+        assert_eq!(search(mate_in_two, 6).0, Evaluation::Win(5));
+    }
+
+    #[test]
+    fn test_root_draw() {
+        // a queen down, white can go back to g1 and repeat the position
+        let board = Board::new(Some("k2q4/8/8/8/8/5N2/8/7K w - - 0 1".into())).unwrap();
+        let back = board.parse_san("Ng1").unwrap();
+        let previous = HashMap::from([(board.apply(&back).repetition_key(), 1)]);
+        let request = MoveRequest::new(&board).with_repetitions(&previous);
+
+        let mut player = EnginePlayer::with_depth(2);
+        assert_eq!(player.make_move(&request), back.into());
+        assert_eq!(player.last_eval(), Some(Evaluation::Draw));
+
+        // when it has to win, it plays on a queen down instead
+        let mut player = EnginePlayer::with_depth(2).with_root_draw(1000);
+        assert_ne!(player.make_move(&request), back.into());
+        assert!(player.last_eval().unwrap() > Evaluation::Eval(-1000));
+    }
+
+    #[test]
+    fn test_backends() {
+        for fen in [
+            "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",   // Rd8#
+            "3r2k1/5ppp/8/8/8/8/3Q1PPP/6K1 w - - 0 1", // Qxd8#
+            "r7/7p/4k3/1N6/8/8/8/4K3 w - - 0 1",       // Nc7+ forks king and rook
+            "4k3/7p/8/3q4/8/8/3R4/3RK3 w - - 0 1",     // Rxd5 wins the queen
+        ] {
+            let board = Board::new(Some(fen.into())).unwrap();
+            let [mailbox, bitboard] = [Backend::Mailbox, Backend::Bitboard].map(|backend| {
+                let params = SearchParams {
+                    backend,
+                    ..SearchParams::default()
+                };
+                EnginePlayer::search(
+                    &board,
+                    4,
+                    None,
+                    &HashMap::new(),
+                    params,
+                    &mut TranspositionTable::default(),
+                    &mut StdRng::seed_from_u64(0),
+                )
+            });
+            assert_eq!(mailbox.line[0], bitboard.line[0], "{fen}");
+            match (mailbox.eval, bitboard.eval) {
+                // the bitboards leave out outposts and the noise differs
+                (Evaluation::Eval(a), Evaluation::Eval(b)) => {
+                    assert!(a.abs_diff(b) <= 30, "{a} and {b} in {fen}")
+                }
+                (a, b) => assert_eq!(a, b, "{fen}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_halfmoves() {
+        // the rook can take the pawn or move quietly
+        let fen = "4k3/8/8/3p4/8/8/8/3R3K w - - 12 40";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let node = BitboardNode {
+            game: BitboardGame::from_fen(fen).unwrap(),
+            last_move: None,
+        };
+        assert_eq!(SearchNode::halfmoves(&board), 12);
+        assert_eq!(node.halfmoves(), 12);
+
+        let mailbox: HashMap<String, usize> = board
+            .get_next_states(MoveOrdering::default())
+            .iter()
+            .map(|child| (child.last_move.unwrap().uci(), child.halfmoves()))
+            .collect();
+        let bitboard: HashMap<String, usize> = node
+            .get_next_states(MoveOrdering::default())
+            .iter()
+            .map(|child| (child.last_move.unwrap().uci(), child.halfmoves()))
+            .collect();
+        for (uci, halfmoves) in [("d1d5", 0), ("d1d2", 13), ("h1h2", 13)] {
+            assert_eq!(mailbox[uci], halfmoves, "{uci}");
+            assert_eq!(bitboard[uci], halfmoves, "{uci}");
+        }
+    }
+
+    #[test]
+    fn test_delays_mate() {
+        // Ra8# follows every move, but a knight on e6 or c6 can still step in
+        let fen = "7k/R7/6K1/8/3n4/8/8/8 b - - 0 1";
+        let (eval, line) = search(fen, 6);
+        assert_eq!(eval, Evaluation::Loss(6));
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(["Ne6", "Nc6"].contains(&line[0].san(&board).as_str()));
+    }
+
+    #[test]
+    fn test_no_moves() {
+        for fen in [
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", // stalemate
+            "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", // checkmate
+        ] {
+            let board = Board::new(Some(fen.into())).unwrap();
+            let request = MoveRequest::new(&board);
+            assert_eq!(
+                EnginePlayer::new().make_move(&request),
+                PlayerAction::NoMoves
+            );
+        }
+    }
+
+    #[test]
+    fn test_stalemate_is_a_draw() {
+        // the black king has nowhere to go, but it is not attacked
+        assert_eq!(
+            search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 4).0,
+            Evaluation::Draw
+        );
+        // so Qf7 does not win, while Qg7 does
+        let (eval, line) = search("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1", 4);
+        assert_eq!(eval, Evaluation::Win(3));
+        let board = Board::new(Some("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1".into())).unwrap();
+        assert_ne!(line[0].san(&board), "Qf7");
+    }
+
+    #[test]
+    fn test_evaluate_illegal_position() {
+        // black is in check with white to move, so the king could simply be taken
+        let fen = "7k/8/8/8/8/8/8/K6Q w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(EnginePlayer::evaluate(&board).is_err());
+
+        let fen = "7k/8/8/8/8/8/8/K5Q1 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert!(EnginePlayer::evaluate(&board).is_ok());
+    }
+
+    #[test]
+    fn test_push_to_corner() {
+        let centered = Board::new(Some("8/8/8/4k3/8/8/8/K2Q4 w - - 0 1".into())).unwrap();
+        let cornered = Board::new(Some("k7/8/2K5/8/8/8/8/3Q4 w - - 0 1".into())).unwrap();
+        assert!(push_to_corner(&cornered, Color::White) > push_to_corner(&centered, Color::White));
+        assert_eq!(push_to_corner(&centered, Color::Black), None);
+
+        // only applies against a lone king
+        let board = Board::new(Some("8/8/8/4k3/4p3/8/8/K2Q4 w - - 0 1".into())).unwrap();
+        assert_eq!(push_to_corner(&board, Color::White), None);
+
+        // two rooks can force mate, a pawn has to promote first
+        let board = Board::new(Some("8/8/8/4k3/8/8/8/K2RR3 w - - 0 1".into())).unwrap();
+        assert!(push_to_corner(&board, Color::White).is_some());
+        let board = Board::new(Some("8/8/8/4k3/8/8/4P3/K2Q4 w - - 0 1".into())).unwrap();
+        assert_eq!(push_to_corner(&board, Color::White), None);
+    }
+
+    #[test]
+    fn test_outposts() {
+        // the knight on d5 is backed by e4, and no black pawn is left on c or e
+        let outpost = Board::new(Some(
+            "4k3/pp3ppp/3p4/3N4/4P3/8/PPP2PPP/4K3 w - - 0 1".into(),
+        ));
+        assert_eq!(outposts(&outpost.unwrap(), Color::White), OUTPOST_BONUS);
+        // c7-c6 would chase it away
+        let chased = Board::new(Some(
+            "4k3/ppp2ppp/3p4/3N4/4P3/8/PP3PPP/4K3 w - - 0 1".into(),
+        ));
+        assert_eq!(outposts(&chased.unwrap(), Color::White), 0);
+        // without the pawn on e4 it stands alone
+        let alone = Board::new(Some("4k3/pp3ppp/3p4/3N4/8/8/PPP2PPP/4K3 w - - 0 1".into()));
+        assert_eq!(outposts(&alone.unwrap(), Color::White), 0);
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        let board = Board::new(Some("4k3/4p1p1/8/1P6/4P2P/8/8/4K3 w - - 0 1".into())).unwrap();
+        assert_eq!(passed_pawns(&board, Color::White), PASSED_PAWN_BONUS);
+        assert_eq!(passed_pawns(&board, Color::Black), 0);
+    }
+
+    #[test]
+    fn test_lone_king() {
+        // the lone king never evaluates as better than a draw, whatever the noise
+        let board = Board::new(Some("8/8/8/4k3/8/8/8/K2Q4 b - - 0 1".into())).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            assert!(board.evaluate(&mut rng) <= Evaluation::Eval(0));
+        }
+        // and a stalemated one is a draw, not a queen down
+        let stalemate = Board::new(Some("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".into())).unwrap();
+        assert_eq!(stalemate.evaluate(&mut rng), Evaluation::Draw);
+
+        // the queen has to box the king in without taking its last square
+        for (fen, seed) in [
+            ("k7/8/8/8/8/8/8/4K1Q1 w - - 0 1", 0),
+            ("8/8/3k4/8/8/8/1Q6/6K1 w - - 0 1", 1),
+            ("8/8/8/8/8/2k5/8/K6Q w - - 0 1", 2),
+        ] {
+            let mut game = Game::new(
+                Some(fen.into()),
+                EnginePlayer::with_depth(3),
+                RandomPlayer::with_seed(seed),
+            )
+            .unwrap();
+            game.set_max_plies(60);
+            let result = game.start();
+            assert_eq!(
+                result,
+                GameResult::Win(Color::White, Termination::Checkmate),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_mates_kq_vs_k() {
+        let fen = "8/8/8/4k3/8/8/8/K2Q4 w - - 0 1";
+        let mut game =
+            Game::new(Some(fen.into()), EnginePlayer::new(), EnginePlayer::new()).unwrap();
+        game.set_max_plies(60);
+
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Checkmate)
+        );
+    }
+
+    #[test]
+    fn test_analyse() {
+        // mate in two: the queen takes the rook, then the king
+        let fen = "r6k/6pp/8/8/8/8/8/K6Q w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let (eval, line) = EnginePlayer::analyse(&board, Duration::from_millis(200)).unwrap();
+        assert!(matches!(eval, Evaluation::Win(_)));
+        assert_eq!(line[0], board.parse_move("h1a8").unwrap());
+        // the line is made of moves that follow each other, past the mate they leave
+        // the king to be taken
+        assert!(board.apply_checked(&line[0]).is_ok());
+        line.iter().fold(board, |board, mv| {
+            assert!(board.get_moves(&mv.from).unwrap_or_default().contains(mv));
+            board.apply(mv)
+        });
+    }
+
+    #[test]
+    fn test_avoids_repetition_when_ahead() {
+        // every rook move but one leads back to a position from the game
+        let board = Board::new(Some("k7/8/8/8/8/8/8/K6R w - - 0 1".into())).unwrap();
+        let keep = board.parse_move("h1h7").unwrap();
+        let previous: HashMap<u64, u8> = board
+            .legal_moves(Color::White)
+            .iter()
+            .filter(|mv| **mv != keep)
+            .map(|mv| (board.apply(mv).repetition_key(), 1))
+            .collect();
+
+        let SearchResult { eval, line, .. } = EnginePlayer::search(
+            &board,
+            SEARCH_DEPTH,
+            None,
+            &previous,
+            SearchParams::default(),
+            &mut TranspositionTable::default(),
+            &mut StdRng::from_entropy(),
+        );
+        assert_eq!(line[0], keep);
+        assert!(eval > Evaluation::Draw);
+    }
+
+    #[test]
+    fn test_seeks_repetition_when_behind() {
+        let board = Board::new(Some("k7/8/8/8/8/8/8/K6R b - - 0 1".into())).unwrap();
+        let repeat = board.parse_move("a8b8").unwrap();
+        let previous = HashMap::from([(board.apply(&repeat).repetition_key(), 2)]);
+
+        let SearchResult { eval, line, .. } = EnginePlayer::search(
+            &board,
+            SEARCH_DEPTH,
+            None,
+            &previous,
+            SearchParams::default(),
+            &mut TranspositionTable::default(),
+            &mut StdRng::from_entropy(),
+        );
+        assert_eq!(line[0], repeat);
+        assert_eq!(eval, Evaluation::Draw);
+    }
+
+    #[test]
+    fn test_skill() {
+        let mut full = EnginePlayer::with_depth(2);
+        let mut weak = EnginePlayer::with_depth(2).with_skill(Skill::level(0));
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut full_best, mut weak_best) = (0, 0);
+        for seed in 0..100 {
+            // a position some random moves into a game
+            let mut board = Board::new(None).unwrap();
+            for _ in 0..rng.gen_range(0..20) {
+                let moves = board.legal_moves(board.current_turn());
+                if moves.is_empty() {
+                    break;
+                }
+                board = board.apply(&moves[rng.gen_range(0..moves.len())]);
+            }
+            let color = board.current_turn();
+            if board.legal_moves(color).is_empty() {
+                continue;
+            }
+
+            let request = MoveRequest::new(&board).with_color(color).with_seed(seed);
+            let PlayerAction::Move(best) = full.make_move(&request) else {
+                panic!("there are moves");
+            };
+            let other_seed = MoveRequest::new(&board)
+                .with_color(color)
+                .with_seed(seed + 1000);
+            if full.make_move(&other_seed) == best.into() {
+                full_best += 1;
+            }
+            let PlayerAction::Move(mv) = weak.make_move(&request) else {
+                panic!("there are moves");
+            };
+            assert!(
+                board.legal_moves(color).contains(&mv),
+                "{}",
+                board.get_fen()
+            );
+            assert_eq!(weak.last_line().unwrap()[0], mv);
+            if mv == best {
+                weak_best += 1;
+            }
+        }
+        assert!(weak_best < full_best, "{weak_best} >= {full_best}");
+    }
+
+    #[test]
+    fn test_skill_levels() {
+        for level in 0..MAX_SKILL {
+            let (weaker, stronger) = (Skill::level(level), Skill::level(level + 1));
+            assert!(weaker.depth <= stronger.depth);
+            assert!(weaker.candidates >= stronger.candidates);
+            assert!(weaker.blunder_chance > stronger.blunder_chance);
+            assert!(weaker.max_loss > stronger.max_loss);
+        }
+        assert!(Skill::level(MAX_SKILL).temperature > 0.0);
+        assert!(Skill::level(MAX_SKILL).blunder_chance > 0.0);
+        assert_eq!(Skill::level(MAX_SKILL + 1), Skill::level(MAX_SKILL));
+    }
+
+    #[test]
+    fn test_respects_clock() {
+        let board = Board::new(None).unwrap();
+        let clock = Clock::new(Duration::from_secs(1), Duration::ZERO);
+        let request = MoveRequest::new(&board).with_clock(&clock);
+
+        let start = Instant::now();
+        EnginePlayer::new().make_move(&request);
+        assert!(start.elapsed() < clock.remaining(Color::White));
+    }
 }
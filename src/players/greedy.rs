@@ -0,0 +1,98 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    board::Board,
+    pieces::{Move, SpecialMove},
+};
+
+use super::{MoveRequest, Player, PlayerAction};
+
+// GreedyPlayer takes the most material it can with the next move, without looking
+// any further ahead. Between equal moves, quiet ones included, it picks at random.
+#[derive(Default)]
+pub struct GreedyPlayer {
+    rng: Option<StdRng>, // its own seeded source, otherwise the one of the request
+}
+
+impl GreedyPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Makes the same choices every time, also in games that are not seeded
+    pub fn with_seed(seed: u64) -> Self {
+        GreedyPlayer {
+            rng: Some(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+// The material the move wins: what it captures, plus what a pawn becomes on promotion
+fn material_gain(board: &Board, mv: &Move) -> i32 {
+    let color = board.current_turn();
+    let after = board.apply(mv);
+    let captured = board.material(!color) - after.material(!color);
+    let promoted = match mv.special {
+        Some(SpecialMove::Promotion(_, _)) => after.material(color) - board.material(color),
+        _ => 0,
+    };
+    captured + promoted
+}
+
+impl Player for GreedyPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let board = request.board;
+        let moves: Vec<(Move, i32)> = board
+            .legal_moves(request.color)
+            .into_iter()
+            .map(|mv| (mv, material_gain(board, &mv)))
+            .collect();
+        let Some(best) = moves.iter().map(|(_, gain)| *gain).max() else {
+            return PlayerAction::NoMoves;
+        };
+        let candidates: Vec<Move> = moves
+            .into_iter()
+            .filter(|(_, gain)| *gain == best)
+            .map(|(mv, _)| mv)
+            .collect();
+
+        let choice = match &mut self.rng {
+            Some(rng) => candidates.choose(rng),
+            None => candidates.choose(&mut request.rng()),
+        };
+        (*choice.expect("there is at least one move")).into()
+    }
+
+    fn name(&self) -> String {
+        "GreedyPlayer".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_takes_hanging_queen() {
+        // the queen on d5 can be taken by the pawn or the knight, the pawn on a7 by
+        // the rook
+        let fen = "4k3/p7/8/3q4/4P3/2N5/8/R3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        for seed in 0..20 {
+            let request = MoveRequest::new(&board).with_seed(seed);
+            let PlayerAction::Move(mv) = GreedyPlayer::new().make_move(&request) else {
+                panic!("there are moves");
+            };
+            assert_eq!(mv.to, b"d5".into());
+        }
+    }
+
+    #[test]
+    fn test_prefers_promotion() {
+        let fen = "4k3/1P6/8/8/8/8/p7/4K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let request = MoveRequest::new(&board);
+        let mv = board.parse_san("b8=Q").unwrap();
+        assert_eq!(GreedyPlayer::with_seed(1).make_move(&request), mv.into());
+    }
+}
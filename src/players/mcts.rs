@@ -0,0 +1,267 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    board::{Board, FIFTY_MOVE_PLIES},
+    pieces::{Color, Move},
+    play::{no_moves_result, GameResult, Termination},
+};
+
+use super::{MoveRequest, Player, PlayerAction};
+
+const DEFAULT_ITERATIONS: usize = 10000;
+// Rollouts that get this long are called a draw
+const ROLLOUT_PLIES: usize = 40;
+// How much UCB1 favors trying less visited moves
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+// How long to search for each move
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MctsBudget {
+    Iterations(usize),
+    Time(Duration),
+}
+
+// MctsPlayer runs Monte Carlo tree search: it grows a tree of moves, picking which
+// to look at by UCB1, and scores new positions by playing random moves to the end.
+// It plays the move it looked at most.
+pub struct MctsPlayer {
+    budget: MctsBudget,
+    rng: Option<StdRng>, // its own seeded source, otherwise the one of the request
+}
+
+impl Default for MctsPlayer {
+    fn default() -> Self {
+        MctsPlayer::with_budget(MctsBudget::Iterations(DEFAULT_ITERATIONS))
+    }
+}
+
+impl MctsPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_budget(budget: MctsBudget) -> Self {
+        MctsPlayer { budget, rng: None }
+    }
+
+    // Makes the same choices every time, also in games that are not seeded
+    pub fn with_seed(self, seed: u64) -> Self {
+        MctsPlayer {
+            rng: Some(StdRng::seed_from_u64(seed)),
+            ..self
+        }
+    }
+}
+
+struct Node {
+    board: Board,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>, // moves without a child yet
+    result: Option<GameResult>,
+    visits: u32,
+    score: f64, // for the side that moved into this node, a win counting 1 and a draw 0.5
+}
+
+impl Node {
+    fn new(board: Board, parent: Option<usize>) -> Self {
+        let untried = board.legal_moves(board.current_turn());
+        let result = terminal_result(&board, untried.is_empty());
+        Node {
+            board,
+            parent,
+            children: Vec::new(),
+            untried,
+            result,
+            visits: 0,
+            score: 0.0,
+        }
+    }
+
+    fn ucb1(&self, parent_visits: u32) -> f64 {
+        let visits = self.visits as f64;
+        self.score / visits + EXPLORATION * ((parent_visits as f64).ln() / visits).sqrt()
+    }
+}
+
+// The result if the game is over in this position
+fn terminal_result(board: &Board, no_moves: bool) -> Option<GameResult> {
+    if no_moves {
+        Some(no_moves_result(board))
+    } else if board.ply - board.last_pawn_move >= FIFTY_MOVE_PLIES {
+        Some(GameResult::Draw(Termination::FiftyMove))
+    } else if board.is_dead_position() {
+        Some(GameResult::Draw(Termination::InsufficientMaterial))
+    } else {
+        None
+    }
+}
+
+// A random legal move, or None when there is none
+fn random_move(board: &Board, rng: &mut StdRng) -> Option<Move> {
+    let color = board.current_turn();
+    let mut moves: Vec<Move> = board
+        .get_pieces(color)
+        .iter()
+        .flat_map(|(pos, _)| board.get_moves(pos))
+        .flatten()
+        .collect();
+    // trying them in random order finds a legal one without checking them all
+    moves.shuffle(rng);
+    moves
+        .into_iter()
+        .find(|mv| !board.apply(mv).is_in_check(color))
+}
+
+// Plays random moves until the game ends or runs too long, returning the score for white
+fn rollout(board: &Board, rng: &mut StdRng) -> f64 {
+    let mut board = *board;
+    for _ in 0..ROLLOUT_PLIES {
+        let Some(mv) = random_move(&board, rng) else {
+            return white_score(no_moves_result(&board));
+        };
+        board = board.apply(&mv);
+        if let Some(result) = terminal_result(&board, false) {
+            return white_score(result);
+        }
+    }
+    0.5
+}
+
+fn white_score(result: GameResult) -> f64 {
+    match result {
+        GameResult::Win(Color::White, _) => 1.0,
+        GameResult::Win(Color::Black, _) => 0.0,
+        GameResult::Draw(_) => 0.5,
+    }
+}
+
+// Searches the position and returns the most visited move
+fn search(board: &Board, budget: MctsBudget, deadline: Option<Instant>, rng: &mut StdRng) -> Move {
+    let mut tree = vec![Node::new(*board, None)];
+    let deadline = match budget {
+        MctsBudget::Time(time) => Some(deadline.map_or(Instant::now() + time, |deadline| {
+            deadline.min(Instant::now() + time)
+        })),
+        MctsBudget::Iterations(_) => deadline,
+    };
+
+    let mut iterations = 0;
+    loop {
+        let done = match budget {
+            MctsBudget::Iterations(max) => iterations >= max,
+            MctsBudget::Time(_) => false,
+        };
+        // always search once, so that there is a move to play
+        if iterations > 0 && (done || deadline.is_some_and(|deadline| Instant::now() >= deadline)) {
+            break;
+        }
+        iterations += 1;
+
+        // selection: follow the best children by UCB1 down to a node that can grow
+        let mut current = 0;
+        while tree[current].untried.is_empty() && !tree[current].children.is_empty() {
+            let visits = tree[current].visits;
+            current = *tree[current]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| tree[a].ucb1(visits).total_cmp(&tree[b].ucb1(visits)))
+                .expect("there are children");
+        }
+
+        // expansion: add one of the moves not tried yet
+        if !tree[current].untried.is_empty() {
+            let index = rng.gen_range(0..tree[current].untried.len());
+            let mv = tree[current].untried.swap_remove(index);
+            let child = Node::new(tree[current].board.apply(&mv), Some(current));
+            tree.push(child);
+            let child = tree.len() - 1;
+            tree[current].children.push(child);
+            current = child;
+        }
+
+        // simulation
+        let score = match tree[current].result {
+            Some(result) => white_score(result),
+            None => rollout(&tree[current].board, rng),
+        };
+
+        // backpropagation
+        let mut node = Some(current);
+        while let Some(index) = node {
+            let mover = !tree[index].board.current_turn();
+            tree[index].visits += 1;
+            tree[index].score += match mover {
+                Color::White => score,
+                Color::Black => 1.0 - score,
+            };
+            node = tree[index].parent;
+        }
+    }
+
+    let best = tree[0]
+        .children
+        .iter()
+        .max_by_key(|&&child| tree[child].visits)
+        .expect("the root has legal moves");
+    tree[*best]
+        .board
+        .last_move
+        .expect("a child is reached by a move")
+}
+
+impl Player for MctsPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        if request.board.legal_moves(request.color).is_empty() {
+            return PlayerAction::NoMoves;
+        }
+        let deadline = request
+            .clock
+            .map(|clock| Instant::now() + clock.move_budget(request.color));
+        let mv = match &mut self.rng {
+            Some(rng) => search(request.board, self.budget, deadline, rng),
+            None => search(request.board, self.budget, deadline, &mut request.rng()),
+        };
+        mv.into()
+    }
+
+    fn name(&self) -> String {
+        match self.budget {
+            MctsBudget::Iterations(iterations) => format!("MctsPlayer {iterations} iterations"),
+            MctsBudget::Time(time) => format!("MctsPlayer {} ms", time.as_millis()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        let fen = "7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let mut player = MctsPlayer::with_budget(MctsBudget::Iterations(1000)).with_seed(1);
+        let PlayerAction::Move(mv) = player.make_move(&MoveRequest::new(&board)) else {
+            panic!("there are moves");
+        };
+        let after = board.apply(&mv);
+        assert!(after.legal_moves(Color::Black).is_empty());
+        assert_eq!(
+            no_moves_result(&after),
+            GameResult::Win(Color::White, Termination::Checkmate)
+        );
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let board = Board::new(None).unwrap();
+        let mut player = MctsPlayer::with_budget(MctsBudget::Time(Duration::from_millis(50)));
+        let started = Instant::now();
+        let action = player.make_move(&MoveRequest::new(&board));
+        assert!(matches!(action, PlayerAction::Move(_)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+}
@@ -7,11 +7,512 @@ pub use terminal::*;
 mod random;
 pub use random::*;
 
+mod greedy;
+pub use greedy::*;
+
 mod engine;
 pub use engine::*;
 
-use crate::{board::Board, pieces::Move};
+mod mcts;
+pub use mcts::*;
+
+mod uci;
+pub use uci::*;
+
+mod net;
+pub use net::*;
+
+mod book;
+pub use book::*;
+
+mod phased;
+pub use phased::*;
+
+mod consensus;
+pub use consensus::*;
+
+#[cfg(test)]
+mod scripted;
+#[cfg(test)]
+pub use scripted::*;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    board::Board,
+    clock::Clock,
+    pieces::{Color, Move},
+    play::GameResult,
+};
+
+// Everything a player gets to see when asked for a move
+pub struct MoveRequest<'a> {
+    pub board: &'a Board,
+    pub color: Color, // the side the player is moving for
+    pub clock: Option<&'a Clock>,
+    pub repetitions: Option<&'a HashMap<u64, u8>>, // how often each earlier position occurred
+    pub seed: Option<u64>, // for players making random choices, so that games can be replayed
+    pub history: Option<(&'a Board, &'a [Move])>, // the start position and the moves since
+}
+
+impl<'a> MoveRequest<'a> {
+    pub fn new(board: &'a Board) -> Self {
+        MoveRequest {
+            board,
+            color: board.current_turn(),
+            clock: None,
+            repetitions: None,
+            seed: None,
+            history: None,
+        }
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        MoveRequest { color, ..self }
+    }
+
+    pub fn with_repetitions(self, repetitions: &'a HashMap<u64, u8>) -> Self {
+        MoveRequest {
+            repetitions: Some(repetitions),
+            ..self
+        }
+    }
+
+    pub fn with_clock(self, clock: &'a Clock) -> Self {
+        MoveRequest {
+            clock: Some(clock),
+            ..self
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        MoveRequest {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    pub fn with_history(self, start: &'a Board, moves: &'a [Move]) -> Self {
+        MoveRequest {
+            history: Some((start, moves)),
+            ..self
+        }
+    }
+
+    // The source of randomness for this move, seeded if the game is
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+}
+
+// What a player decides to do on their turn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerAction {
+    Move(Move),
+    Undo(usize), // take back this many plies
+    Resign,
+    OfferDraw, // the game is drawn if the opponent accepts, otherwise the player is asked again
+    NoMoves,   // there is no legal move, the position is mate or stalemate
+}
+
+impl From<Move> for PlayerAction {
+    fn from(mv: Move) -> Self {
+        PlayerAction::Move(mv)
+    }
+}
+
+// Players are Send so that the game can let them think on a separate thread
+pub trait Player: Send {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction;
+
+    // Used for the PGN headers
+    fn name(&self) -> String {
+        "Unknown".into()
+    }
+
+    // The evaluation behind the most recent move, from the player's perspective
+    fn last_eval(&self) -> Option<Evaluation> {
+        None
+    }
+
+    // The line of play the player expected with its most recent move, starting
+    // with that move
+    fn last_line(&self) -> Option<Vec<Move>> {
+        None
+    }
+
+    // How often the player asked an engine for help during the game
+    fn hints_used(&self) -> usize {
+        0
+    }
+
+    // Whether the player agrees to the draw the opponent offers, in the position of
+    // the request
+    fn accepts_draw(&mut self, _request: &MoveRequest) -> bool {
+        false
+    }
+
+    // Called when the game has ended, with the final position
+    fn game_over(&mut self, _request: &MoveRequest, _result: GameResult) {}
+
+    // The position the player insists on starting from, such as one chosen by a
+    // remote opponent
+    fn start_fen(&self) -> Option<String> {
+        None
+    }
+
+    // How long the player took for its moves, if it keeps track
+    fn move_times(&self) -> Option<MoveTimes> {
+        None
+    }
+}
+
+// Lets a single player object sit on both sides of the board, taking turns with the lock
+impl<P: Player + Send + ?Sized> Player for Arc<Mutex<P>> {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        self.lock().unwrap().make_move(request)
+    }
+
+    fn name(&self) -> String {
+        self.lock().unwrap().name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.lock().unwrap().last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.lock().unwrap().last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.lock().unwrap().hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.lock().unwrap().accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.lock().unwrap().game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.lock().unwrap().start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.lock().unwrap().move_times()
+    }
+}
+
+// Lets players chosen at runtime be wrapped
+impl<P: Player + ?Sized> Player for Box<P> {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        (**self).make_move(request)
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        (**self).last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        (**self).last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        (**self).hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        (**self).accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        (**self).game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        (**self).start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        (**self).move_times()
+    }
+}
+
+// How long an external engine thinks per move unless told otherwise
+const UCI_MOVETIME: Duration = Duration::from_millis(100);
+
+// Creates a player from a spec such as "random", "random:seed=7", "greedy", "terminal",
+// "engine", "engine:depth=3", "engine:skill=0", "engine:ordering=generated",
+// "engine:backend=bitboard", "engine:root_draw=50", "mcts:iters=10000", "uci:path=/usr/bin/stockfish,ms=100",
+// "net:listen=0.0.0.0:9999", "phased:open=engine:depth=2,end=engine:depth=6" or
+// "consensus:engine:depth=2+mcts+greedy"
+pub fn make_player(spec: &str) -> anyhow::Result<Box<dyn Player>> {
+    let (kind, options) = spec.split_once(':').unwrap_or((spec, ""));
+    // the options hold the specs of other players
+    if kind == "phased" {
+        return Ok(Box::new(PhasedPlayer::from_spec(options)?));
+    }
+    if kind == "consensus" {
+        let players = options
+            .split('+')
+            .map(make_player)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if players.len() < 2 {
+            bail!("consensus needs players to vote, e.g. consensus:engine+mcts+greedy");
+        }
+        return Ok(Box::new(ConsensusPlayer::new(players)));
+    }
+    let options: Vec<(&str, &str)> = options
+        .split(',')
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            option
+                .split_once('=')
+                .with_context(|| format!("option {option} should look like name=value"))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    match (kind, options.as_slice()) {
+        ("random", []) => Ok(Box::new(RandomPlayer::new())),
+        ("random", [("seed", seed)]) => {
+            let seed = seed.parse().context("could not parse seed")?;
+            Ok(Box::new(RandomPlayer::with_seed(seed)))
+        }
+        ("terminal", options) => {
+            let mut hints = None;
+            for (name, value) in options {
+                let options = hints.get_or_insert_with(HintOptions::default);
+                match *name {
+                    "hint_ms" => {
+                        let ms = value.parse().context("could not parse hint_ms")?;
+                        options.time = Duration::from_millis(ms);
+                    }
+                    "hint_depth" => {
+                        options.depth = value.parse().context("could not parse hint_depth")?;
+                        if options.depth == 0 {
+                            bail!("hint_depth must be at least 1");
+                        }
+                    }
+                    _ => bail!("unknown terminal option {name}"),
+                }
+            }
+            let player = TerminalPlayer::new();
+            match hints {
+                Some(options) => Ok(Box::new(player.with_hints(options))),
+                None => Ok(Box::new(player)),
+            }
+        }
+        ("engine", options) => {
+            let mut engine = EnginePlayer::new();
+            let mut skill = None;
+            let mut root_draw = None;
+            let mut params = SearchParams::default();
+            for (name, value) in options {
+                match *name {
+                    "depth" => {
+                        let depth = value.parse().context("could not parse depth")?;
+                        if depth == 0 {
+                            bail!("depth must be at least 1");
+                        }
+                        engine = EnginePlayer::with_depth(depth);
+                    }
+                    "skill" => {
+                        let level = value.parse().context("could not parse skill")?;
+                        if level > MAX_SKILL {
+                            bail!("skill must be at most {MAX_SKILL}");
+                        }
+                        skill = Some(Skill::level(level));
+                    }
+                    "ordering" => {
+                        params.ordering = match *value {
+                            "generated" => MoveOrdering::Generated,
+                            "moveord" => MoveOrdering::MoveOrd,
+                            "mvvlva" => MoveOrdering::MvvLva,
+                            _ => bail!("unknown move ordering {value}"),
+                        };
+                    }
+                    "backend" => {
+                        params.backend = match *value {
+                            "mailbox" => Backend::Mailbox,
+                            "bitboard" => Backend::Bitboard,
+                            _ => bail!("unknown backend {value}"),
+                        };
+                    }
+                    "root_draw" => {
+                        root_draw = Some(value.parse().context("could not parse root_draw")?);
+                    }
+                    _ => bail!("unknown engine option {name}"),
+                }
+            }
+            let mut engine = engine.with_params(params);
+            if let Some(centipawns) = root_draw {
+                engine = engine.with_root_draw(centipawns);
+            }
+            match skill {
+                Some(skill) => Ok(Box::new(engine.with_skill(skill))),
+                None => Ok(Box::new(engine)),
+            }
+        }
+        ("greedy", []) => Ok(Box::new(GreedyPlayer::new())),
+        ("greedy", [("seed", seed)]) => {
+            let seed = seed.parse().context("could not parse seed")?;
+            Ok(Box::new(GreedyPlayer::with_seed(seed)))
+        }
+        ("mcts", options) => {
+            let mut mcts = MctsPlayer::new();
+            let mut seed = None;
+            for (name, value) in options {
+                match *name {
+                    "iters" => {
+                        let iterations = value.parse().context("could not parse iters")?;
+                        if iterations == 0 {
+                            bail!("iters must be at least 1");
+                        }
+                        mcts = MctsPlayer::with_budget(MctsBudget::Iterations(iterations));
+                    }
+                    "ms" => {
+                        let ms = value.parse().context("could not parse ms")?;
+                        mcts = MctsPlayer::with_budget(MctsBudget::Time(Duration::from_millis(ms)));
+                    }
+                    "seed" => seed = Some(value.parse().context("could not parse seed")?),
+                    _ => bail!("unknown mcts option {name}"),
+                }
+            }
+            if let Some(seed) = seed {
+                mcts = mcts.with_seed(seed);
+            }
+            Ok(Box::new(mcts))
+        }
+        ("uci", options) => {
+            let mut path = None;
+            let mut movetime = UCI_MOVETIME;
+            for (name, value) in options {
+                match *name {
+                    "path" => path = Some(*value),
+                    "ms" => {
+                        movetime =
+                            Duration::from_millis(value.parse().context("could not parse ms")?)
+                    }
+                    _ => bail!("unknown uci option {name}"),
+                }
+            }
+            let path = path.context("uci needs the path to the engine, e.g. uci:path=stockfish")?;
+            Ok(Box::new(UciEnginePlayer::new(path, movetime)?))
+        }
+        ("net", [("listen", address)]) => Ok(Box::new(NetPlayer::listen(address)?)),
+        ("net", [("connect", address)]) => Ok(Box::new(NetPlayer::connect(address)?)),
+        ("net", _) => bail!("net takes either listen=ADDRESS or connect=ADDRESS"),
+        ("random" | "greedy", _) => bail!("{kind} only takes a seed"),
+        _ => bail!("unknown player {kind}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_player() {
+        assert_eq!(make_player("random").unwrap().name(), "RandomPlayer");
+        assert_eq!(make_player("terminal").unwrap().name(), "TerminalPlayer");
+        assert!(make_player("terminal:hint_ms=500,hint_depth=3").is_ok());
+        assert!(make_player("terminal:hint_depth=0").is_err());
+        assert!(make_player("terminal:hints=1").is_err());
+        assert_eq!(
+            make_player("engine").unwrap().name(),
+            "EnginePlayer depth 4"
+        );
+        assert_eq!(
+            make_player("engine:depth=2").unwrap().name(),
+            "EnginePlayer depth 2"
+        );
+
+        assert_eq!(
+            make_player("engine:skill=0,depth=3").unwrap().name(),
+            "EnginePlayer depth 3 weakened"
+        );
+        assert!(make_player("engine:skill=10").is_err());
+        assert!(make_player("engine:depth=0").is_err());
+        assert!(make_player("engine:depth=deep").is_err());
+        assert!(make_player("engine:speed=3").is_err());
+        assert!(make_player("engine:depth=2,ordering=moveord").is_ok());
+        assert!(make_player("engine:ordering=best").is_err());
+        assert!(make_player("engine:backend=bitboard").is_ok());
+        assert!(make_player("engine:backend=magic").is_err());
+        assert!(make_player("engine:root_draw=50").is_ok());
+        assert!(make_player("engine:root_draw=never").is_err());
+        assert!(make_player("engine:depth").is_err());
+        assert_eq!(make_player("random:seed=7").unwrap().name(), "RandomPlayer");
+        assert!(make_player("random:seed=-1").is_err());
+        assert!(make_player("random:depth=3").is_err());
+        assert_eq!(make_player("greedy").unwrap().name(), "GreedyPlayer");
+        assert_eq!(make_player("greedy:seed=7").unwrap().name(), "GreedyPlayer");
+        assert!(make_player("greedy:depth=1").is_err());
+        assert_eq!(
+            make_player("mcts").unwrap().name(),
+            "MctsPlayer 10000 iterations"
+        );
+        assert_eq!(
+            make_player("mcts:iters=500,seed=3").unwrap().name(),
+            "MctsPlayer 500 iterations"
+        );
+        assert_eq!(
+            make_player("mcts:ms=200").unwrap().name(),
+            "MctsPlayer 200 ms"
+        );
+        assert!(make_player("mcts:iters=0").is_err());
+        assert!(make_player("mcts:depth=3").is_err());
+        let fake_engine = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake_uci.sh");
+        assert_eq!(
+            make_player(&format!("uci:path={fake_engine},ms=10"))
+                .unwrap()
+                .name(),
+            "FakeEngine"
+        );
+        assert!(make_player("uci").is_err());
+        assert!(make_player("net").is_err());
+        assert!(make_player("net:listen=localhost:1,connect=localhost:1").is_err());
+        assert!(make_player("uci:path=/nonexistent/engine").is_err());
+        assert!(make_player("stockfish").is_err());
+        assert!(make_player("phased:open=engine:depth=2,end=engine:depth=6").is_ok());
+        assert!(make_player("phased:open=stockfish").is_err());
+        assert_eq!(
+            make_player("consensus:engine:depth=2,skill=1+greedy")
+                .unwrap()
+                .name(),
+            "ConsensusPlayer (EnginePlayer depth 2 weakened, GreedyPlayer)"
+        );
+        assert!(make_player("consensus:engine").is_err());
+        assert!(make_player("consensus:engine+stockfish").is_err());
+    }
+    #[test]
+    fn test_engine_root_draw() {
+        // a queen down, repeating the position with Ng1 draws
+        let board = Board::new(Some("k2q4/8/8/8/8/5N2/8/7K w - - 0 1".into())).unwrap();
+        let back = board.parse_san("Ng1").unwrap();
+        let previous = HashMap::from([(board.apply(&back).repetition_key(), 1)]);
+        let request = MoveRequest::new(&board).with_repetitions(&previous);
 
-pub trait Player {
-    fn make_move(&self, board: &Board) -> Move;
+        let mut player = make_player("engine:depth=2").unwrap();
+        assert_eq!(player.make_move(&request), back.into());
+        let mut player = make_player("engine:root_draw=1000,depth=2").unwrap();
+        assert_ne!(player.make_move(&request), back.into());
+    }
 }
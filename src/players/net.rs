@@ -0,0 +1,392 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    board::Board,
+    play::{GameResult, Termination},
+};
+
+use super::{MoveRequest, Player, PlayerAction};
+
+// NetPlayer is an opponent on the other end of a TCP connection, who plays the same
+// game on their own board. The host sends the start position, after which both sides
+// send one line per message: their moves such as "move e2e4", "resign", "draw" to
+// offer a draw and "accept" or "decline" to answer one. Moves that are illegal on our
+// board, or anything else unexpected, forfeit the game. Take-backs are not passed on.
+pub struct NetPlayer {
+    connection: Connection,
+    peer: String,
+    start_fen: Option<String>, // the position the host sent
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    fen_sent: bool, // only the host sends the position, the client starts out with it sent
+    synced: usize,  // the moves of the game the other side knows about
+    offered: bool,  // the other side offered a draw that has not been answered
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Message {
+    Fen(String),
+    Move(String),
+    Resign,
+    Draw,
+    Accept,
+    Decline,
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Fen(fen) => write!(f, "fen {fen}"),
+            Message::Move(mv) => write!(f, "move {mv}"),
+            Message::Resign => write!(f, "resign"),
+            Message::Draw => write!(f, "draw"),
+            Message::Accept => write!(f, "accept"),
+            Message::Decline => write!(f, "decline"),
+        }
+    }
+}
+
+fn parse_message(line: &str) -> Option<Message> {
+    match line.trim().split_once(' ') {
+        Some(("fen", fen)) => Some(Message::Fen(fen.into())),
+        Some(("move", mv)) => Some(Message::Move(mv.into())),
+        Some(_) => None,
+        None => match line.trim() {
+            "resign" => Some(Message::Resign),
+            "draw" => Some(Message::Draw),
+            "accept" => Some(Message::Accept),
+            "decline" => Some(Message::Decline),
+            _ => None,
+        },
+    }
+}
+
+impl Connection {
+    fn new(stream: TcpStream, host: bool) -> anyhow::Result<Self> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            fen_sent: !host,
+            synced: 0,
+            offered: false,
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> anyhow::Result<()> {
+        writeln!(self.writer, "{message}").context("could not send to the other side")
+    }
+
+    fn receive(&mut self) -> anyhow::Result<Message> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("the other side left");
+        }
+        parse_message(&line).with_context(|| format!("unexpected message {:?}", line.trim()))
+    }
+
+    // Sends the start position, if not done yet, and the moves the other side has not seen
+    fn sync(&mut self, request: &MoveRequest) -> anyhow::Result<()> {
+        let Some((start, moves)) = request.history else {
+            bail!("the game history is needed to play over the network");
+        };
+        if !self.fen_sent {
+            self.send(&Message::Fen(start.get_fen()))?;
+            self.fen_sent = true;
+        }
+        for mv in moves.iter().skip(self.synced) {
+            self.send(&Message::Move(mv.uci()))?;
+        }
+        self.synced = moves.len();
+        Ok(())
+    }
+
+    fn next_action(&mut self, request: &MoveRequest) -> anyhow::Result<PlayerAction> {
+        self.sync(request)?;
+        // asked again, so the offer was turned down
+        if std::mem::take(&mut self.offered) {
+            self.send(&Message::Decline)?;
+        }
+        match self.receive()? {
+            Message::Move(uci) => {
+                let board = request.board;
+                let mv = board.parse_move(&uci)?;
+                if !board.legal_moves(request.color).contains(&mv) {
+                    bail!("illegal move {uci}");
+                }
+                self.synced += 1;
+                Ok(mv.into())
+            }
+            Message::Resign => Ok(PlayerAction::Resign),
+            Message::Draw => {
+                self.offered = true;
+                Ok(PlayerAction::OfferDraw)
+            }
+            message => bail!("unexpected message {message}"),
+        }
+    }
+
+    fn offer_draw(&mut self, request: &MoveRequest) -> anyhow::Result<bool> {
+        self.sync(request)?;
+        self.send(&Message::Draw)?;
+        match self.receive()? {
+            Message::Accept => Ok(true),
+            Message::Decline => Ok(false),
+            message => bail!("expected an answer to the draw offer, got {message}"),
+        }
+    }
+
+    // Tells the other side about endings it can not see on its own board
+    fn finish(&mut self, request: &MoveRequest, result: GameResult) -> anyhow::Result<()> {
+        self.sync(request)?;
+        match result {
+            GameResult::Draw(Termination::Agreement) if self.offered => self.send(&Message::Accept),
+            // we lost, but not on the board
+            GameResult::Win(winner, reason)
+                if winner == request.color && reason != Termination::Checkmate =>
+            {
+                self.send(&Message::Resign)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl NetPlayer {
+    // Waits for the other side to connect to the given address, e.g. "0.0.0.0:9999"
+    pub fn listen(address: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address).context("could not listen")?;
+        println!("Waiting for the other player on {}", listener.local_addr()?);
+        Self::accept(&listener)
+    }
+
+    pub fn accept(listener: &TcpListener) -> anyhow::Result<Self> {
+        let (stream, peer) = listener.accept()?;
+        Ok(NetPlayer {
+            connection: Connection::new(stream, true)?,
+            peer: peer.to_string(),
+            start_fen: None,
+        })
+    }
+
+    // Connects to a host and waits for the start position, which comes with the
+    // first move of the game
+    pub fn connect(address: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(address).context("could not connect")?;
+        let peer = stream.peer_addr()?.to_string();
+        let mut connection = Connection::new(stream, false)?;
+        println!("Connected to {peer}, waiting for the game to start");
+        let fen = match connection.receive()? {
+            Message::Fen(fen) => fen,
+            message => bail!("expected the start position, got {message}"),
+        };
+        Board::new(Some(fen.clone())).context("the host sent an invalid position")?;
+
+        Ok(NetPlayer {
+            connection,
+            peer,
+            start_fen: Some(fen),
+        })
+    }
+}
+
+impl Player for NetPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        match self.connection.next_action(request) {
+            Ok(action) => action,
+            Err(err) => {
+                // a forfeit, unless there is no legal move anyway
+                println!("{}: {err:#}", self.name());
+                PlayerAction::NoMoves
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("NetPlayer {}", self.peer)
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.connection.offer_draw(request).unwrap_or_else(|err| {
+            println!("{}: {err:#}", self.name());
+            false
+        })
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        if let Err(err) = self.connection.finish(request, result) {
+            println!("{}: {err:#}", self.name());
+        }
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.start_fen.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, thread};
+
+    use super::*;
+    use crate::{
+        pieces::Color,
+        play::Game,
+        players::{Player, ScriptedPlayer, SharedOutput},
+    };
+
+    // A Ruy Lopez, with castling on both sides
+    const WHITE: [&str; 10] = [
+        "e2e4", "g1f3", "f1b5", "b5a4", "e1g1", "f1e1", "a4b3", "c2c3", "h2h3", "d2d4",
+    ];
+    const BLACK: [&str; 10] = [
+        "e7e5", "b8c6", "a7a6", "g8f6", "f8e7", "b7b5", "d7d6", "e8g8", "c6b8", "b8d7",
+    ];
+
+    // Agrees to every draw
+    struct Agreeable;
+
+    impl Player for Agreeable {
+        fn make_move(&mut self, _request: &MoveRequest) -> PlayerAction {
+            PlayerAction::Resign
+        }
+
+        fn accepts_draw(&mut self, _request: &MoveRequest) -> bool {
+            true
+        }
+    }
+
+    fn bind() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        (listener, address)
+    }
+
+    // Plays the other side by hand: sends the given lines, then returns all it received
+    fn raw_client(address: SocketAddr, lines: &[&str]) -> thread::JoinHandle<Vec<String>> {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            for line in lines {
+                writeln!(stream, "{line}").unwrap();
+            }
+            BufReader::new(stream).lines().map(Result::unwrap).collect()
+        })
+    }
+
+    #[test]
+    fn test_scripted_game() {
+        let (listener, address) = bind();
+        let client = thread::spawn(move || {
+            let host = NetPlayer::connect(address).unwrap();
+            let fen = host.start_fen();
+            let mut game = Game::new(fen, host, ScriptedPlayer::new(&BLACK)).unwrap();
+            game.set_max_plies(20);
+            (game.start(), game.record().moves)
+        });
+
+        let client_player = NetPlayer::accept(&listener).unwrap();
+        let mut game = Game::new(None, ScriptedPlayer::new(&WHITE), client_player).unwrap();
+        game.set_max_plies(20);
+        let result = game.start();
+        let moves = game.record().moves;
+
+        let (client_result, client_moves) = client.join().unwrap();
+        assert_eq!(result, GameResult::Draw(Termination::MaxMoves));
+        assert_eq!(client_result, result);
+        assert_eq!(moves.len(), 20);
+        assert_eq!(client_moves, moves);
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let (listener, address) = bind();
+        let client = raw_client(address, &["move e7e4"]);
+        let host = NetPlayer::accept(&listener).unwrap();
+        let mut game = Game::new(None, ScriptedPlayer::new(&WHITE), host).unwrap();
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::White, Termination::Forfeit)
+        );
+        drop(game);
+        let received = client.join().unwrap();
+        assert!(received[0].starts_with("fen "));
+        assert_eq!(received[1], "move e2e4");
+    }
+
+    #[test]
+    fn test_draw_offers() {
+        // declined by the scripted player, then the other side resigns
+        let (listener, address) = bind();
+        let client = raw_client(address, &["draw", "resign"]);
+        let host = NetPlayer::accept(&listener).unwrap();
+        let mut game = Game::new(None, host, ScriptedPlayer::new(&BLACK)).unwrap();
+        let out = SharedOutput::default();
+        game.set_output(out.clone());
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Resignation)
+        );
+        drop(game);
+        assert_eq!(client.join().unwrap()[1..], ["decline"]);
+        assert_eq!(out.contents(), "Black declined the draw offer\n");
+
+        let (listener, address) = bind();
+        let client = raw_client(address, &["draw"]);
+        let host = NetPlayer::accept(&listener).unwrap();
+        let mut game = Game::new(None, host, Agreeable).unwrap();
+        assert_eq!(game.start(), GameResult::Draw(Termination::Agreement));
+        drop(game);
+        assert_eq!(client.join().unwrap()[1..], ["accept"]);
+
+        // our offer, turned down before the other side resigns
+        let (listener, address) = bind();
+        let client = raw_client(address, &["decline", "resign"]);
+        let mut host = NetPlayer::accept(&listener).unwrap();
+        let request_board = Board::new(None).unwrap();
+        let request = MoveRequest::new(&request_board).with_history(&request_board, &[]);
+        assert!(!host.accepts_draw(&request));
+        assert_eq!(host.make_move(&request), PlayerAction::Resign);
+        drop(host);
+        assert_eq!(client.join().unwrap()[1..], ["draw"]);
+    }
+
+    #[test]
+    fn test_resigning_is_passed_on() {
+        let (listener, address) = bind();
+        let client = raw_client(address, &[]);
+        let host = NetPlayer::accept(&listener).unwrap();
+        let mut game = Game::new(None, Agreeable, host).unwrap();
+        assert_eq!(
+            game.start(),
+            GameResult::Win(Color::Black, Termination::Resignation)
+        );
+        drop(game);
+        assert_eq!(client.join().unwrap()[1..], ["resign"]);
+    }
+
+    #[test]
+    fn test_parse_message() {
+        for message in [
+            Message::Fen("8/8/8/8/8/8/8/k3K3 w - - 0 1".into()),
+            Message::Move("e7e8q".into()),
+            Message::Resign,
+            Message::Draw,
+            Message::Accept,
+            Message::Decline,
+        ] {
+            assert_eq!(parse_message(&message.to_string()), Some(message));
+        }
+        assert_eq!(parse_message("draw\r\n"), Some(Message::Draw));
+        assert_eq!(parse_message("hello"), None);
+        assert_eq!(parse_message("resign now"), None);
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{bail, Context};
+
+use crate::{
+    board::Board,
+    pieces::{Color, Move},
+    play::GameResult,
+};
+
+use super::{make_player, EnginePlayer, Evaluation, MoveRequest, Player, PlayerAction};
+
+// Up to and including this move the game counts as in the opening
+const OPENING_MOVES: usize = 10;
+// With this many pieces left, kings included, the game counts as in the endgame
+const ENDGAME_PIECES: usize = 10;
+
+// When a player of PhasedPlayer gets to move
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PhaseRule {
+    Always,
+    UpToMove(usize),  // the move number, counting from 1 like in PGN
+    MaxPieces(usize), // of both sides together, kings included
+}
+
+impl PhaseRule {
+    pub fn applies(&self, board: &Board) -> bool {
+        match *self {
+            PhaseRule::Always => true,
+            PhaseRule::UpToMove(number) => board.ply / 2 < number,
+            PhaseRule::MaxPieces(pieces) => {
+                let count =
+                    board.get_pieces(Color::White).len() + board.get_pieces(Color::Black).len();
+                count <= pieces
+            }
+        }
+    }
+}
+
+// PhasedPlayer hands every move to the first of its players whose rule applies to
+// the position, for playing the phases of the game with different strategies. The
+// last player moves when no rule applies.
+pub struct PhasedPlayer {
+    players: Vec<(PhaseRule, Box<dyn Player>)>,
+    current: usize, // the player that moved last
+}
+
+impl PhasedPlayer {
+    pub fn new(players: Vec<(PhaseRule, Box<dyn Player>)>) -> Self {
+        assert!(!players.is_empty(), "a phased player needs players");
+        PhasedPlayer {
+            players,
+            current: 0,
+        }
+    }
+
+    // Reads options such as "open=engine:depth=2,end=engine:depth=6". The phases are
+    // open, middle and end, each followed by the spec of its player, and the
+    // thresholds open_moves and end_pieces. A phase that is not given is played by
+    // the default engine.
+    pub fn from_spec(options: &str) -> anyhow::Result<Self> {
+        let mut specs: [Option<String>; 3] = [None, None, None]; // open, middle, end
+        let mut last = None;
+        let mut open_moves = OPENING_MOVES;
+        let mut end_pieces = ENDGAME_PIECES;
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            let phase = match name {
+                "open" => 0,
+                "middle" => 1,
+                "end" => 2,
+                "open_moves" => {
+                    open_moves = value.parse().context("could not parse open_moves")?;
+                    continue;
+                }
+                "end_pieces" => {
+                    end_pieces = value.parse().context("could not parse end_pieces")?;
+                    continue;
+                }
+                // an option of the player of the phase before
+                _ => {
+                    let phase: usize = last.with_context(|| format!("unknown phase {name}"))?;
+                    let spec = specs[phase].as_mut().expect("the phase has a spec");
+                    spec.push(',');
+                    spec.push_str(option);
+                    continue;
+                }
+            };
+            if specs[phase].is_some() {
+                bail!("phase {name} is given twice");
+            }
+            specs[phase] = Some(value.to_string());
+            last = Some(phase);
+        }
+        if last.is_none() {
+            bail!("phased needs at least one phase, e.g. phased:open=random,end=engine");
+        }
+
+        let [open, middle, end] = specs;
+        let player = |spec: Option<String>, phase: &str| -> anyhow::Result<Box<dyn Player>> {
+            match spec {
+                Some(spec) => make_player(&spec).with_context(|| format!("invalid {phase} player")),
+                None => Ok(Box::new(EnginePlayer::new())),
+            }
+        };
+        Ok(PhasedPlayer::new(vec![
+            (PhaseRule::MaxPieces(end_pieces), player(end, "end")?),
+            (PhaseRule::UpToMove(open_moves), player(open, "open")?),
+            (PhaseRule::Always, player(middle, "middle")?),
+        ]))
+    }
+
+    fn player(&self) -> &dyn Player {
+        self.players[self.current].1.as_ref()
+    }
+}
+
+impl Player for PhasedPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        self.current = self
+            .players
+            .iter()
+            .position(|(rule, _)| rule.applies(request.board))
+            .unwrap_or(self.players.len() - 1);
+        self.players[self.current].1.make_move(request)
+    }
+
+    fn name(&self) -> String {
+        let names: Vec<String> = self
+            .players
+            .iter()
+            .map(|(_, player)| player.name())
+            .collect();
+        format!("PhasedPlayer ({})", names.join(", "))
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player().last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player().last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.players
+            .iter()
+            .map(|(_, player)| player.hints_used())
+            .sum()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.players[self.current].1.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        for (_, player) in self.players.iter_mut() {
+            player.game_over(request, result);
+        }
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.players
+            .iter()
+            .find_map(|(_, player)| player.start_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::players::RandomPlayer;
+
+    // Plays like RandomPlayer, counting its moves
+    struct Counted {
+        moves: Arc<AtomicUsize>,
+    }
+
+    impl Player for Counted {
+        fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+            self.moves.fetch_add(1, Ordering::SeqCst);
+            RandomPlayer::new().make_move(request)
+        }
+
+        fn name(&self) -> String {
+            "Counted".into()
+        }
+    }
+
+    #[test]
+    fn test_phases() {
+        let counters: Vec<Arc<AtomicUsize>> = (0..3).map(|_| Arc::default()).collect();
+        let counted = |i: usize| -> Box<dyn Player> {
+            Box::new(Counted {
+                moves: counters[i].clone(),
+            })
+        };
+        let mut player = PhasedPlayer::new(vec![
+            (PhaseRule::MaxPieces(6), counted(0)),
+            (PhaseRule::UpToMove(10), counted(1)),
+            (PhaseRule::Always, counted(2)),
+        ]);
+        let moves = || -> Vec<usize> {
+            counters
+                .iter()
+                .map(|counter| counter.load(Ordering::SeqCst))
+                .collect()
+        };
+
+        // the opening, even with pieces gone already
+        let board = Board::new(Some("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 5".into())).unwrap();
+        player.make_move(&MoveRequest::new(&board));
+        assert_eq!(moves(), [0, 1, 0]);
+        // later on with the same pieces
+        let board = Board::new(Some("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 30".into())).unwrap();
+        player.make_move(&MoveRequest::new(&board));
+        assert_eq!(moves(), [0, 1, 1]);
+        // down to six pieces, which beats the move number
+        let board = Board::new(Some("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 5".into())).unwrap();
+        player.make_move(&MoveRequest::new(&board));
+        assert_eq!(moves(), [1, 1, 1]);
+    }
+
+    #[test]
+    fn test_from_spec() {
+        let player = PhasedPlayer::from_spec("open=random,end=engine:depth=6").unwrap();
+        assert_eq!(
+            player.name(),
+            "PhasedPlayer (EnginePlayer depth 6, RandomPlayer, EnginePlayer depth 4)"
+        );
+        // the options after a phase belong to its player
+        let player = PhasedPlayer::from_spec("end=engine:depth=2,skill=3,end_pieces=8").unwrap();
+        assert_eq!(player.players[0].0, PhaseRule::MaxPieces(8));
+        assert_eq!(player.players[0].1.name(), "EnginePlayer depth 2 weakened");
+
+        assert!(PhasedPlayer::from_spec("").is_err());
+        assert!(PhasedPlayer::from_spec("depth=2,open=random").is_err());
+        assert!(PhasedPlayer::from_spec("open=random,open=greedy").is_err());
+        assert!(PhasedPlayer::from_spec("open=chess").is_err());
+        assert!(PhasedPlayer::from_spec("open=random,open_moves=many").is_err());
+    }
+}
@@ -1,23 +1,73 @@
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{board::Board, pieces::Move};
-
-use super::Player;
+use super::{MoveRequest, Player, PlayerAction};
 
 // RandomPlayer makes a random legal move
-pub struct RandomPlayer;
+#[derive(Default)]
+pub struct RandomPlayer {
+    rng: Option<StdRng>, // its own seeded source, otherwise the one of the request
+}
+
+impl RandomPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Makes the same choices every time, also in games that are not seeded
+    pub fn with_seed(seed: u64) -> Self {
+        RandomPlayer {
+            rng: Some(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
 
 impl Player for RandomPlayer {
-    fn make_move(&self, board: &Board) -> Move {
-        let color = board.current_turn();
-        let pieces = board.get_pieces(color);
-        let moves: Vec<Move> = pieces
-            .iter()
-            .flat_map(|(pos, _)| board.get_moves(pos))
-            .flatten()
-            .collect();
-
-        let random_index = rand::thread_rng().gen_range(0..moves.len());
-        return moves[random_index];
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let moves = request.board.legal_moves(request.color);
+        if moves.is_empty() {
+            return PlayerAction::NoMoves;
+        }
+
+        let random_index = match &mut self.rng {
+            Some(rng) => rng.gen_range(0..moves.len()),
+            None => request.rng().gen_range(0..moves.len()),
+        };
+        moves[random_index].into()
+    }
+
+    fn name(&self) -> String {
+        "RandomPlayer".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, play::Game};
+
+    #[test]
+    fn test_with_seed() {
+        let play = |seed| {
+            let white = RandomPlayer::with_seed(seed);
+            let black = RandomPlayer::with_seed(seed + 1);
+            let mut game = Game::new(None, white, black).unwrap();
+            game.set_max_plies(40);
+            game.start();
+            game.record()
+        };
+
+        assert_eq!(play(3).moves, play(3).moves);
+        assert_ne!(play(3).moves, play(5).moves);
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        let board = Board::new(Some(stalemate.into())).unwrap();
+        let request = MoveRequest::new(&board);
+        assert_eq!(
+            RandomPlayer::new().make_move(&request),
+            PlayerAction::NoMoves
+        );
     }
 }
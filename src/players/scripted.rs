@@ -0,0 +1,62 @@
+use crate::board::Position;
+
+use super::{Evaluation, MoveRequest, Player, PlayerAction};
+
+// ScriptedPlayer plays a fixed list of moves given as "e2e4", in order, and has no
+// moves after the last one.
+pub struct ScriptedPlayer {
+    moves: Vec<(Position, Position)>,
+    evals: Vec<Evaluation>, // reported for the moves of the same index
+    next: usize,
+}
+
+impl ScriptedPlayer {
+    pub fn new(moves: &[&str]) -> Self {
+        let moves = moves
+            .iter()
+            .map(|mv| {
+                let mv = mv.as_bytes();
+                let from: Position = (&[mv[0], mv[1]]).into();
+                let to: Position = (&[mv[2], mv[3]]).into();
+                (from, to)
+            })
+            .collect();
+        Self {
+            moves,
+            evals: Vec::new(),
+            next: 0,
+        }
+    }
+
+    // Reports these evaluations for its moves, to script adjudication
+    pub fn with_evals(self, evals: &[Evaluation]) -> Self {
+        Self {
+            evals: evals.to_vec(),
+            ..self
+        }
+    }
+}
+
+impl Player for ScriptedPlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        // at the end of the script, as when mated
+        let Some(&(from, to)) = self.moves.get(self.next) else {
+            return PlayerAction::NoMoves;
+        };
+        self.next += 1;
+
+        request
+            .board
+            .get_moves(&from)
+            .and_then(|moves| moves.into_iter().find(|mv| mv.to == to))
+            .expect("scripted move should be possible")
+            .into()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.next
+            .checked_sub(1)
+            .and_then(|last| self.evals.get(last))
+            .copied()
+    }
+}
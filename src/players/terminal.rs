@@ -1,84 +1,571 @@
-use std::io;
+use std::{io, time::Duration};
+
+use anyhow::bail;
 
 use crate::{
     board::{Board, Position},
-    pieces::Move,
+    pieces::{Color, Move, PieceType, SpecialMove},
 };
 
-use super::Player;
+use super::{EnginePlayer, MoveRequest, Player, PlayerAction};
 
 // TerminalPlayer asks stdin for which moves to make.
-pub struct TerminalPlayer;
+#[derive(Default)]
+pub struct TerminalPlayer {
+    flipped: bool,               // show the board from black's side
+    hints: Option<EnginePlayer>, // suggests moves when asked, if hints are on
+    hints_used: usize,
+}
+
+// How hard the engine thinks about a hint
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HintOptions {
+    pub depth: usize,
+    pub time: Duration,
+}
+
+impl Default for HintOptions {
+    fn default() -> Self {
+        HintOptions {
+            depth: MAX_HINT_DEPTH,
+            time: ANALYSIS_TIME,
+        }
+    }
+}
+
+// What the user typed at a prompt
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Move(Move),
+    Square(Position), // only the piece to move, the destination is asked for next
+    Promotion(Vec<Move>), // a pawn move to the last rank, without saying what to promote to
+    Command(Command),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Command {
+    Moves,
+    Fen,
+    Eval,
+    Board,
+    Flip,
+    Undo,
+    Resign,
+    Draw,
+    Hint,
+    Help,
+}
+
+const HELP: &str = "Enter a move as e2e4, e2 e4, e7e8q or in SAN such as Nf3, or just a square
+such as e2 to see where that piece can go. Other commands:
+  moves   list all legal moves
+  fen     print the position as FEN
+  eval    let the engine analyse the position for a second
+  board   print the board
+  flip    turn the board around
+  undo    take back your last move
+  resign  give up the game
+  draw    offer your opponent a draw
+  hint    let the engine suggest a move, if hints are on
+  help    print this text";
+
+const ANALYSIS_TIME: Duration = Duration::from_secs(1);
+// Hints are limited by time, this only keeps them from running away in simple positions
+const MAX_HINT_DEPTH: usize = 64;
+
+// Invalid lines in a row before the help is pointed out, and before giving up
+// on the game, e.g. when junk is piped into stdin
+const HINT_AFTER_INVALID: usize = 3;
+const MAX_INVALID: usize = 20;
+
+fn parse_command(text: &str) -> Option<Command> {
+    match text {
+        "moves" => Some(Command::Moves),
+        "fen" => Some(Command::Fen),
+        "eval" => Some(Command::Eval),
+        "board" => Some(Command::Board),
+        "flip" => Some(Command::Flip),
+        "undo" => Some(Command::Undo),
+        "resign" => Some(Command::Resign),
+        "draw" => Some(Command::Draw),
+        "hint" => Some(Command::Hint),
+        "help" | "?" => Some(Command::Help),
+        _ => None,
+    }
+}
+
+// Parses a square such as "e2"
+pub(super) fn parse_square(text: &str) -> Option<Position> {
+    match text.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some((&[*file, *rank]).into()),
+        _ => None,
+    }
+}
+
+// The piece for a promotion suffix such as "q"
+fn promotion_piece(text: &str) -> Option<PieceType> {
+    match text {
+        "q" => Some(PieceType::Queen),
+        "r" => Some(PieceType::Rook),
+        "b" => Some(PieceType::Bishop),
+        "n" => Some(PieceType::Knight),
+        _ => None,
+    }
+}
+
+// Picks the promotion the user answered with, a queen if the answer is empty
+fn choose_promotion(moves: &[Move], answer: &str) -> Option<Move> {
+    let typ = match answer.trim().to_ascii_lowercase().as_str() {
+        "" => PieceType::Queen,
+        answer => promotion_piece(answer)?,
+    };
+    moves
+        .iter()
+        .find(
+            |mv| matches!(mv.special, Some(SpecialMove::Promotion(promoted, _)) if promoted == typ),
+        )
+        .copied()
+}
+
+// Parses a command, or a move such as "e2e4", "e2 e4", "e7e8q", "Nf3" or just "e2",
+// checking it against the legal moves of the given color
+fn parse_input(line: &str, board: &Board, color: Color) -> anyhow::Result<Input> {
+    if let Some(command) = parse_command(&line.trim().to_ascii_lowercase()) {
+        return Ok(Input::Command(command));
+    }
+    // "e4" is both a square and a pawn move, so SAN is only tried when the line
+    // can not be read as coordinates
+    parse_coordinates(line, board, color)
+        .or_else(|err| board.parse_san(line).map(Input::Move).map_err(|_| err))
+}
+
+fn parse_coordinates(line: &str, board: &Board, color: Color) -> anyhow::Result<Input> {
+    let line: String = line
+        .split_whitespace()
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    if !line.is_ascii() {
+        bail!("could not read a move from \"{line}\", try e.g. e2e4");
+    }
+
+    let (from, rest) = line.split_at(line.len().min(2));
+    let Some(from) = parse_square(from) else {
+        bail!("could not read a move from \"{line}\", try e.g. e2e4");
+    };
+    if board[from].is_none() {
+        bail!("no piece on {from}");
+    }
+    if !board.is_occupied_by(from, Some(color), None) {
+        bail!("the piece on {from} is not yours");
+    }
+    if rest.is_empty() {
+        if board.legal_moves(color).iter().any(|mv| mv.from == from) {
+            return Ok(Input::Square(from));
+        }
+        bail!("the piece on {from} has no legal moves");
+    }
+
+    let (to, promotion) = rest.split_at(rest.len().min(2));
+    let Some(to) = parse_square(to) else {
+        bail!("could not read a move from \"{line}\", try e.g. e2e4");
+    };
+    let promotion = match promotion {
+        "" => None,
+        text => match promotion_piece(text) {
+            Some(typ) => Some(typ),
+            None => bail!("unknown promotion piece \"{text}\", use q, r, b or n"),
+        },
+    };
+
+    let moves: Vec<Move> = board
+        .get_moves(&from)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|mv| mv.to == to)
+        .filter(|mv| match (promotion, mv.special) {
+            (None, _) => true,
+            (Some(typ), Some(SpecialMove::Promotion(promoted, _))) => typ == promoted,
+            (Some(_), _) => false,
+        })
+        .collect();
+    let Some(mv) = moves.first().copied() else {
+        bail!("the piece on {from} can not move to {to}");
+    };
+    if !board.legal_moves(color).contains(&mv) {
+        bail!("that move is illegal: your king would be in check");
+    }
+    if moves.len() > 1 {
+        return Ok(Input::Promotion(moves));
+    }
+    Ok(Input::Move(mv))
+}
+
+// A couple of the legal moves, to show what a move looks like
+fn example_moves(board: &Board, color: Color) -> String {
+    let san: Vec<String> = board
+        .legal_moves(color)
+        .iter()
+        .take(2)
+        .map(|mv| mv.san(board))
+        .collect();
+    let example = match san.as_slice() {
+        [] => return "There are no legal moves".into(),
+        [first] => first.clone(),
+        [first, second, ..] => format!("{first} or {second}"),
+    };
+    format!("You could play e.g. {example}")
+}
+
+// The legal moves in SAN, one line per piece
+fn list_moves(board: &Board, color: Color) -> String {
+    let moves = board.legal_moves(color);
+    board
+        .get_pieces(color)
+        .iter()
+        .filter_map(|(from, piece)| {
+            let san: Vec<String> = moves
+                .iter()
+                .filter(|mv| mv.from == *from)
+                .map(|mv| mv.san(board))
+                .collect();
+            (!san.is_empty()).then(|| format!("{piece} {from}: {}", san.join(", ")))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
 impl TerminalPlayer {
-    fn read_position(&self, prompt: &str) -> Position {
-        loop {
-            println!("{prompt}");
-            let mut input = String::new();
-            let _ = io::stdin().read_line(&mut input);
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-            if input.len() < 3 {
-                continue;
-            }
+    // Lets the player ask the engine for a move with the hint command
+    pub fn with_hints(self, options: HintOptions) -> Self {
+        let engine = EnginePlayer::with_depth(options.depth).with_time_limit(options.time);
+        TerminalPlayer {
+            hints: Some(engine),
+            ..self
+        }
+    }
 
-            let rank = input.remove(1) as u8;
-            let file = input.remove(0) as u8;
-
-            let rank = match rank {
-                b'1' => 0,
-                b'2' => 8,
-                b'3' => 16,
-                b'4' => 24,
-                b'5' => 32,
-                b'6' => 40,
-                b'7' => 48,
-                b'8' => 56,
-                _ => continue,
-            };
+    // The move the engine suggests, in SAN with its evaluation
+    fn hint(&mut self, board: &Board, color: Color) -> String {
+        let Some(engine) = &mut self.hints else {
+            return "Hints are off, turn them on with e.g. terminal:hint_ms=1000".into();
+        };
+        let PlayerAction::Move(mv) = engine.make_move(&MoveRequest::new(board).with_color(color))
+        else {
+            return "There is no move to suggest.".into();
+        };
+        self.hints_used += 1;
+        match engine.last_eval() {
+            Some(eval) => format!("Hint: {} (eval {eval})", mv.san(board)),
+            None => format!("Hint: {}", mv.san(board)),
+        }
+    }
 
-            let file = match file {
-                b'a' => 0,
-                b'b' => 1,
-                b'c' => 2,
-                b'd' => 3,
-                b'e' => 4,
-                b'f' => 5,
-                b'g' => 6,
-                b'h' => 7,
-                _ => continue,
-            };
+    // Carries out a command that does not end the turn
+    fn run_command(&mut self, command: Command, board: &Board, color: Color) {
+        match command {
+            Command::Moves => println!("{}", list_moves(board, color)),
+            Command::Fen => println!("{}", board.get_fen()),
+            Command::Eval => match EnginePlayer::analyse(board, ANALYSIS_TIME) {
+                Ok((eval, line)) => println!("Eval: {eval} ({})", board.line_san(&line)),
+                Err(err) => println!("{err}"),
+            },
+            Command::Board => println!("{}", board.render(&[], self.flipped)),
+            Command::Flip => {
+                self.flipped = !self.flipped;
+                println!("{}", board.render(&[], self.flipped));
+            }
+            Command::Hint => println!("{}", self.hint(board, color)),
+            Command::Help => println!("{HELP}"),
+            Command::Undo | Command::Resign | Command::Draw => {}
+        }
+    }
 
-            return ((rank + file) as usize).into();
+    // None once stdin is closed or can not be read
+    fn read_line(&self, prompt: &str) -> Option<String> {
+        println!("{prompt}");
+        let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(input),
         }
     }
 }
 
 impl Player for TerminalPlayer {
-    fn make_move(&self, board: &Board) -> Move {
-        let color = board.current_turn();
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let board = request.board;
+        let color = request.color;
+        if board.legal_moves(color).is_empty() {
+            println!("You have no legal moves.");
+            return PlayerAction::NoMoves;
+        }
+        let mut invalid = 0;
+        // with nobody left to answer, the only way out is to give up
+        let resign = |reason: &str| {
+            println!("{reason}, resigning.");
+            PlayerAction::Resign
+        };
         loop {
-            let from = self.read_position("What piece to move?");
-
-            if !board.is_occupied_by(from, Some(color), None) {
-                println!("That is not one of your pieces.");
-                continue;
-            }
+            let Some(line) = self.read_line("Your move? (type help for commands)") else {
+                return resign("No more input");
+            };
+            let input = match parse_input(&line, board, color) {
+                Ok(Input::Square(from)) => {
+                    let moves: Vec<Move> = board
+                        .legal_moves(color)
+                        .into_iter()
+                        .filter(|mv| mv.from == from)
+                        .collect();
+                    let destinations: Vec<Position> = moves.iter().map(|mv| mv.to).collect();
+                    println!("{}", board.render(&destinations, self.flipped));
+                    let san: Vec<String> = moves.iter().map(|mv| mv.san(board)).collect();
+                    println!("{}", san.join(", "));
 
-            if let Some(moves) = board.get_moves(&from) {
-                if moves.is_empty() {
-                    println!("That piece has no moves.");
-                    continue;
-                }
-                for mv in moves.iter() {
-                    println!("{}", mv);
+                    let Some(line) = self.read_line("Where to move the piece?") else {
+                        return resign("No more input");
+                    };
+                    // a destination on its own belongs to the piece picked above
+                    let line = match line.trim().len() {
+                        2 | 3 => format!("{from}{}", line.trim()),
+                        _ => line,
+                    };
+                    parse_input(&line, board, color)
                 }
-                let to = self.read_position("Where to move the piece?");
+                input => input,
+            };
 
-                match moves.iter().find(|mv| mv.to == to) {
-                    Some(mv) => return *mv,
-                    None => continue,
+            match input {
+                Ok(Input::Move(mv)) => return mv.into(),
+                Ok(Input::Promotion(moves)) => {
+                    for _ in 0..MAX_INVALID {
+                        let Some(answer) =
+                            self.read_line("Promote to (q/r/b/n)? Queen if left empty")
+                        else {
+                            return resign("No more input");
+                        };
+                        match choose_promotion(&moves, &answer) {
+                            Some(mv) => return mv.into(),
+                            None => println!("Please answer q, r, b or n."),
+                        }
+                    }
+                    return resign("Too many invalid answers");
+                }
+                // take back our last move along with the reply to it
+                Ok(Input::Command(Command::Undo)) => return PlayerAction::Undo(2),
+                Ok(Input::Command(Command::Resign)) => return PlayerAction::Resign,
+                Ok(Input::Command(Command::Draw)) => return PlayerAction::OfferDraw,
+                Ok(Input::Command(command)) => self.run_command(command, board, color),
+                Ok(Input::Square(_)) => continue,
+                Err(err) => {
+                    println!("{err}. {}", example_moves(board, color));
+                    invalid += 1;
+                    if invalid == MAX_INVALID {
+                        return resign("Too many invalid moves");
+                    }
+                    if invalid % HINT_AFTER_INVALID == 0 {
+                        println!("Type help to see what you can enter.");
+                    }
+                    continue;
                 }
             }
+            invalid = 0;
         }
     }
+
+    fn name(&self) -> String {
+        "TerminalPlayer".into()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.hints_used
+    }
+
+    fn accepts_draw(&mut self, _request: &MoveRequest) -> bool {
+        // no answer counts as a no
+        self.read_line("Your opponent offers a draw. Accept? (y/n)")
+            .is_some_and(|answer| {
+                matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str, fen: Option<&str>) -> anyhow::Result<Input> {
+        let board = Board::new(fen.map(Into::into)).unwrap();
+        parse_input(line, &board, board.current_turn())
+    }
+
+    fn error(line: &str, fen: Option<&str>) -> String {
+        parse(line, fen).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let board = Board::new(None).unwrap();
+        let e4 = Input::Move(board.parse_move("e2e4").unwrap());
+        assert_eq!(parse("e2e4", None).unwrap(), e4);
+        assert_eq!(parse(" e2 e4\n", None).unwrap(), e4);
+        assert_eq!(parse("E2E4", None).unwrap(), e4);
+        assert_eq!(parse("e2", None).unwrap(), Input::Square(b"e2".into()));
+        assert_eq!(parse("e4", None).unwrap(), e4);
+        let knight = board.parse_move("g1f3").unwrap();
+        assert_eq!(parse("Nf3", None).unwrap(), Input::Move(knight));
+
+        let fen = "8/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let promotion = Input::Move(board.parse_move("e7e8").unwrap());
+        assert_eq!(parse("e7e8q", Some(fen)).unwrap(), promotion);
+        assert_eq!(parse("e8=Q", Some(fen)).unwrap(), promotion);
+        let knight = Input::Move(board.parse_move("e7e8n").unwrap());
+        assert_eq!(parse("e7e8n", Some(fen)).unwrap(), knight);
+        // without a piece the player gets to choose
+        let Input::Promotion(choices) = parse("e7e8", Some(fen)).unwrap() else {
+            panic!("expected a choice of promotions");
+        };
+        assert_eq!(choices.len(), 4);
+
+        // castling and captures in either notation
+        let fen = "r3k2r/8/8/3p4/4P3/8/8/R3K2R w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let castle = Input::Move(board.parse_move("e1g1").unwrap());
+        assert_eq!(parse("O-O", Some(fen)).unwrap(), castle);
+        assert_eq!(parse("e1g1", Some(fen)).unwrap(), castle);
+        let long_castle = Input::Move(board.parse_move("e1c1").unwrap());
+        assert_eq!(parse("O-O-O", Some(fen)).unwrap(), long_castle);
+        let capture = Input::Move(board.parse_move("e4d5").unwrap());
+        assert_eq!(parse("exd5", Some(fen)).unwrap(), capture);
+        assert_eq!(parse("e4d5", Some(fen)).unwrap(), capture);
+    }
+
+    #[test]
+    fn test_example_moves() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(
+            example_moves(&board, Color::White),
+            "You could play e.g. Nc3 or Na3"
+        );
+        let fen = "k7/8/1Q6/8/8/8/8/K7 b - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(
+            example_moves(&board, Color::Black),
+            "There are no legal moves"
+        );
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let command = |line| match parse(line, None).unwrap() {
+            Input::Command(command) => command,
+            input => panic!("expected a command, got {input:?}"),
+        };
+        assert_eq!(command("moves"), Command::Moves);
+        assert_eq!(command("fen\n"), Command::Fen);
+        assert_eq!(command(" Eval "), Command::Eval);
+        assert_eq!(command("board"), Command::Board);
+        assert_eq!(command("flip"), Command::Flip);
+        assert_eq!(command("undo"), Command::Undo);
+        assert_eq!(command("resign"), Command::Resign);
+        assert_eq!(command("draw"), Command::Draw);
+        assert_eq!(command("hint"), Command::Hint);
+        assert_eq!(command("help"), Command::Help);
+        assert_eq!(command("?"), Command::Help);
+        assert!(parse("resign now", None).is_err());
+    }
+
+    #[test]
+    fn test_list_moves() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K2N w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        assert_eq!(
+            list_moves(&board, Color::White),
+            "♔ e1: Kf1, Kd1, Kf2, Kd2\n♘ h1: Nf2, Ng3\n♙ e2: e3, e4"
+        );
+    }
+
+    #[test]
+    fn test_hint() {
+        // only the rook mates on the back rank
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1";
+        let board = Board::new(Some(fen.into())).unwrap();
+        let options = HintOptions {
+            depth: 3,
+            ..HintOptions::default()
+        };
+        let mut player = TerminalPlayer::new().with_hints(options);
+        assert_eq!(player.hint(&board, Color::White), "Hint: Ra8+ (eval W3)");
+        assert_eq!(player.hints_used(), 1);
+
+        let mut player = TerminalPlayer::new();
+        assert!(player
+            .hint(&board, Color::White)
+            .starts_with("Hints are off"));
+        assert_eq!(player.hints_used(), 0);
+    }
+
+    #[test]
+    fn test_choose_promotion() {
+        let promotion = |typ| Move {
+            from: b"e7".into(),
+            to: b"e8".into(),
+            special: Some(SpecialMove::Promotion(typ, None)),
+        };
+        let moves = [
+            promotion(PieceType::Knight),
+            promotion(PieceType::Bishop),
+            promotion(PieceType::Rook),
+            promotion(PieceType::Queen),
+        ];
+
+        let queen = Some(promotion(PieceType::Queen));
+        assert_eq!(choose_promotion(&moves, "\n"), queen);
+        assert_eq!(choose_promotion(&moves, "q\n"), queen);
+        assert_eq!(choose_promotion(&moves, " Q "), queen);
+        assert_eq!(
+            choose_promotion(&moves, "n"),
+            Some(promotion(PieceType::Knight))
+        );
+        assert_eq!(
+            choose_promotion(&moves, "r"),
+            Some(promotion(PieceType::Rook))
+        );
+        assert_eq!(
+            choose_promotion(&moves, "b"),
+            Some(promotion(PieceType::Bishop))
+        );
+        assert_eq!(choose_promotion(&moves, "k"), None);
+        assert_eq!(choose_promotion(&moves, "queen"), None);
+        assert_eq!(choose_promotion(&moves[..3], ""), None);
+    }
+
+    #[test]
+    fn test_parse_input_errors() {
+        assert_eq!(error("e3e4", None), "no piece on e3");
+        assert_eq!(error("e7e5", None), "the piece on e7 is not yours");
+        assert_eq!(error("e2e5", None), "the piece on e2 can not move to e5");
+        assert_eq!(error("d1", None), "the piece on d1 has no legal moves");
+        assert!(error("hello", None).starts_with("could not read a move"));
+        assert!(error("e2e9", None).starts_with("could not read a move"));
+        assert!(error("é2e4", None).starts_with("could not read a move"));
+        assert!(error("é", None).starts_with("could not read a move"));
+        assert!(error("e2é4", None).starts_with("could not read a move"));
+        assert!(error("♘f3", None).starts_with("could not read a move"));
+        assert!(error("\u{0}\u{7f}", None).starts_with("could not read a move"));
+        assert!(error("", None).starts_with("could not read a move"));
+        assert!(error("e2e4x", None).starts_with("unknown promotion piece"));
+
+        // the bishop is pinned against the king
+        let fen = "4r2k/8/8/8/8/8/4B3/4K3 w - - 0 1";
+        assert_eq!(
+            error("e2d3", Some(fen)),
+            "that move is illegal: your king would be in check"
+        );
+    }
 }
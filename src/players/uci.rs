@@ -0,0 +1,266 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+use crate::board::Board;
+
+use super::{MoveRequest, Player, PlayerAction};
+
+// How long the engine may take to answer anything but a search
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+// How much longer than the move time the engine may take before it counts as hung
+const MOVE_GRACE: Duration = Duration::from_secs(2);
+
+// UciEnginePlayer lets an external engine such as Stockfish play, talking UCI to it
+// over its standard input and output
+pub struct UciEnginePlayer {
+    engine: UciEngine,
+    name: String,
+    movetime: Duration,
+}
+
+// The running engine process, which is told to quit, and killed if need be, on drop
+struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>, // read from its output on a separate thread
+    timeout: Duration,
+}
+
+impl UciEngine {
+    fn start(path: &str, timeout: Duration) -> anyhow::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("could not start engine {path}"))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(UciEngine {
+            child,
+            stdin,
+            lines,
+            timeout,
+        })
+    }
+
+    fn send(&mut self, command: &str) -> anyhow::Result<()> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .context("could not write to the engine")
+    }
+
+    // The lines the engine writes up to and including one starting with the given
+    // word, which has to come within the timeout
+    fn read_until(&self, word: &str, timeout: Duration) -> anyhow::Result<Vec<String>> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) => {
+                    let done = line.split_whitespace().next() == Some(word);
+                    lines.push(line);
+                    if done {
+                        return Ok(lines);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    bail!("the engine did not send {word} within {timeout:?}")
+                }
+                Err(RecvTimeoutError::Disconnected) => bail!("the engine exited"),
+            }
+        }
+    }
+
+    // Waits until the engine is done with everything sent so far
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.send("isready")?;
+        self.read_until("readyok", self.timeout)?;
+        Ok(())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // give it a moment to quit by itself
+        let deadline = Instant::now() + Duration::from_millis(100);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl UciEnginePlayer {
+    // Starts the engine at the given path, which thinks for the given time per move
+    pub fn new(path: &str, movetime: Duration) -> anyhow::Result<Self> {
+        Self::with_timeout(path, movetime, RESPONSE_TIMEOUT)
+    }
+
+    fn with_timeout(path: &str, movetime: Duration, timeout: Duration) -> anyhow::Result<Self> {
+        let mut engine = UciEngine::start(path, timeout)?;
+        engine.send("uci")?;
+        let lines = engine.read_until("uciok", timeout)?;
+        let name = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("id name "))
+            .unwrap_or(path)
+            .to_string();
+        engine.send("ucinewgame")?;
+        engine.sync()?;
+
+        Ok(UciEnginePlayer {
+            engine,
+            name,
+            movetime,
+        })
+    }
+
+    fn best_move(&mut self, request: &MoveRequest) -> anyhow::Result<PlayerAction> {
+        let movetime = match request.clock {
+            Some(clock) => self.movetime.min(clock.move_budget(request.color)),
+            None => self.movetime,
+        };
+        let engine = &mut self.engine;
+        engine.send(&position_command(request))?;
+        engine.send(&format!("go movetime {}", movetime.as_millis()))?;
+        let lines = engine.read_until("bestmove", movetime + MOVE_GRACE)?;
+
+        let bestmove = lines
+            .last()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .context("bestmove without a move")?;
+        if bestmove == "(none)" {
+            return Ok(PlayerAction::NoMoves);
+        }
+        Ok(request.board.parse_move(bestmove)?.into())
+    }
+}
+
+// The UCI command that sets up the position, from the start of the game if known
+fn position_command(request: &MoveRequest) -> String {
+    let Some((start, moves)) = request.history else {
+        return format!("position fen {}", request.board.get_fen());
+    };
+    let start_fen = start.get_fen();
+    let mut command = if start_fen == Board::new(None).unwrap().get_fen() {
+        "position startpos".to_string()
+    } else {
+        format!("position fen {start_fen}")
+    };
+    if !moves.is_empty() {
+        command.push_str(" moves");
+        for mv in moves {
+            command.push(' ');
+            command.push_str(&mv.uci());
+        }
+    }
+    command
+}
+
+impl Player for UciEnginePlayer {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        match self.best_move(request) {
+            Ok(action) => action,
+            Err(err) => {
+                println!("{}: {err:#}, resigning", self.name);
+                PlayerAction::Resign
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Move;
+
+    const FAKE_ENGINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake_uci.sh");
+
+    #[test]
+    fn test_fake_engine() {
+        let mut player = UciEnginePlayer::new(FAKE_ENGINE, Duration::from_millis(10)).unwrap();
+        assert_eq!(player.name(), "FakeEngine");
+
+        let board = Board::new(None).unwrap();
+        let e4 = board.parse_move("e2e4").unwrap();
+        assert_eq!(player.make_move(&MoveRequest::new(&board)), e4.into());
+    }
+
+    #[test]
+    fn test_unresponsive_engine() {
+        let timeout = Duration::from_millis(100);
+        // cat only repeats what it is told
+        let err = UciEnginePlayer::with_timeout("cat", Duration::ZERO, timeout).err();
+        assert!(err.unwrap().to_string().contains("did not send uciok"));
+
+        // true exits at once, before or after it is written to
+        assert!(UciEnginePlayer::with_timeout("true", Duration::ZERO, timeout).is_err());
+
+        assert!(UciEnginePlayer::new("/nonexistent/engine", Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_position_command() {
+        let start = Board::new(None).unwrap();
+        assert_eq!(
+            position_command(&MoveRequest::new(&start).with_history(&start, &[])),
+            "position startpos"
+        );
+
+        let moves: Vec<Move> = ["e2e4", "e7e5"]
+            .iter()
+            .scan(start, |board, mv| {
+                let mv = board.parse_move(mv).unwrap();
+                *board = board.apply(&mv);
+                Some(mv)
+            })
+            .collect();
+        let board = start.apply(&moves[0]).apply(&moves[1]);
+        assert_eq!(
+            position_command(&MoveRequest::new(&board).with_history(&start, &moves)),
+            "position startpos moves e2e4 e7e5"
+        );
+        assert_eq!(
+            position_command(&MoveRequest::new(&board)),
+            format!("position fen {}", board.get_fen())
+        );
+
+        let fen = "8/4P3/8/8/8/8/8/k3K3 w - - 0 1";
+        let start = Board::new(Some(fen.into())).unwrap();
+        let promotion = start.parse_move("e7e8").unwrap();
+        let board = start.apply(&promotion);
+        assert_eq!(
+            position_command(&MoveRequest::new(&board).with_history(&start, &[promotion])),
+            format!("position fen {fen} moves e7e8q")
+        );
+    }
+}
@@ -1,64 +1,859 @@
-use std::io;
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use crate::{board::Board, pieces::Move};
+use crate::{
+    board::{Board, Position},
+    pieces::{Color, Move},
+    play::GameResult,
+};
 
-use super::Player;
+use super::{terminal::parse_square, EnginePlayer, Evaluation, MoveRequest, Player, PlayerAction};
 
+// PrintBoard prints the board before every move of the wrapped player
 pub struct PrintBoard<P: Player> {
     player: P,
+    options: BoardOptions,
+    out: Box<dyn Write + Send>, // stdout unless set
 }
 
+// What PrintBoard shows besides the board and its FEN
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BoardOptions {
+    pub flip: bool,         // show the board from the side of the wrapped player
+    pub clear_screen: bool, // so that the board stays in place
+    pub material: bool,     // what each side has taken, beneath the board
+    pub status: bool,       // the last move and check, above the board
+}
+
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
+
 impl<P: Player> Player for PrintBoard<P> {
-    fn make_move(&self, board: &Board) -> Move {
-        println!("{}", board);
-        println!("{}", board.get_fen());
-        println!();
-        self.player.make_move(board)
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let board = self.render(request);
+        if let Err(err) = write!(self.out, "{board}").and_then(|_| self.out.flush()) {
+            eprintln!("Could not print the board: {err}");
+        }
+        self.player.make_move(request)
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player.last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.move_times()
     }
 }
 
 impl<P: Player> PrintBoard<P> {
+    #[cfg(test)]
     pub fn wrap(player: P) -> Self {
-        Self { player }
+        Self::with_options(player, BoardOptions::default())
+    }
+
+    // Clears the screen before every board when asked to, so that it stays in place
+    pub fn wrap_with(player: P, clear: bool) -> Self {
+        let options = BoardOptions {
+            clear_screen: clear,
+            ..BoardOptions::default()
+        };
+        Self::with_options(player, options)
+    }
+
+    pub fn with_options(player: P, options: BoardOptions) -> Self {
+        Self {
+            player,
+            options,
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_writer(mut self, out: impl Write + Send + 'static) -> Self {
+        self.out = Box::new(out);
+        self
+    }
+
+    fn render(&self, request: &MoveRequest) -> String {
+        let board = request.board;
+        let mut out = String::new();
+        if self.options.clear_screen {
+            out.push_str(CLEAR_SCREEN);
+        }
+        if self.options.status {
+            // the board before the last move, to write that move in SAN
+            let previous = request.history.and_then(|(start, moves)| {
+                let (_, earlier) = moves.split_last()?;
+                Some(start.apply_moves(earlier))
+            });
+            out.push_str(&board.status_line(previous.as_ref()));
+            out.push('\n');
+        }
+        let flipped = self.options.flip && request.color == Color::Black;
+        out.push_str(&board.render(&[], flipped));
+        if self.options.material {
+            out.push_str(&board.material_line());
+            out.push('\n');
+        }
+        out.push_str(&format!("\n{}\n\n", board.get_fen()));
+        out
     }
 }
 
 // ---
 
+// PrintMoves writes out every move of the game as numbered SAN, the opponent's as
+// soon as the wrapped player gets to see them, and the result at the end
 pub struct PrintMoves<P: Player> {
     player: P,
+    out: Box<dyn Write + Send>, // stdout unless set
+    written: usize,             // how many moves of the game have been written
 }
 
 impl<P: Player> Player for PrintMoves<P> {
-    fn make_move(&self, board: &Board) -> Move {
-        let mv = self.player.make_move(board);
-        println!("{}", mv);
-        mv
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        self.catch_up(request);
+        let action = self.player.make_move(request);
+        if let PlayerAction::Move(mv) = action {
+            // an illegal move will be asked for again, and written then
+            if request.board.legal_moves(request.color).contains(&mv) {
+                self.write(&mv.numbered_san(request.board));
+                self.written += 1;
+            }
+        }
+        action
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player.last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.catch_up(request);
+        self.write(&result.to_string());
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.move_times()
     }
 }
 
 impl<P: Player> PrintMoves<P> {
     pub fn wrap(player: P) -> Self {
-        Self { player }
+        Self {
+            player,
+            out: Box::new(io::stdout()),
+            written: 0,
+        }
+    }
+
+    pub fn with_writer(mut self, out: impl Write + Send + 'static) -> Self {
+        self.out = Box::new(out);
+        self
+    }
+
+    // Appends to the file at the given path instead
+    pub fn to_file(player: P, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::wrap(player).with_writer(file))
+    }
+
+    // Flushes every line, so that the game is not lost if the program crashes
+    fn write(&mut self, line: &str) {
+        if let Err(err) = writeln!(self.out, "{line}").and_then(|_| self.out.flush()) {
+            eprintln!("Could not write the moves: {err}");
+        }
+    }
+
+    // Writes the moves of the game that have not been written yet
+    fn catch_up(&mut self, request: &MoveRequest) {
+        let Some((start, moves)) = request.history else {
+            return;
+        };
+        if moves.len() < self.written {
+            self.write(&format!("(took back {} plies)", self.written - moves.len()));
+            self.written = moves.len();
+        }
+        let mut board = *start;
+        for (i, mv) in moves.iter().enumerate() {
+            if i >= self.written {
+                self.write(&mv.numbered_san(&board));
+            }
+            board = board.apply(mv);
+        }
+        self.written = moves.len();
     }
 }
 
 // ---
 
+// ManualStep waits for enter before every move of the wrapped player, and takes
+// commands to look at the position in the meantime
 pub struct ManualStep<P: Player> {
     player: P,
+    skip: usize, // moves still to make without waiting
+}
+
+// What was typed while ManualStep waits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum StepCommand {
+    Step,
+    Fen,
+    Eval,
+    Moves(Position),
+    Skip(usize),
+}
+
+const STEP_HELP: &str = "Press enter to make the move, or: fen, eval, moves <square>, skip <moves>";
+const STEP_EVAL_TIME: Duration = Duration::from_millis(500);
+
+fn parse_step_command(line: &str) -> Option<StepCommand> {
+    let line = line.trim().to_ascii_lowercase();
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [] => Some(StepCommand::Step),
+        ["fen"] => Some(StepCommand::Fen),
+        ["eval"] => Some(StepCommand::Eval),
+        ["moves", square] => parse_square(square).map(StepCommand::Moves),
+        ["skip", moves] => moves.parse().ok().map(StepCommand::Skip),
+        _ => None,
+    }
+}
+
+// The legal moves of the piece on the square, in SAN
+fn square_moves(board: &Board, square: Position) -> String {
+    let Some(piece) = board[square] else {
+        return format!("No piece on {square}");
+    };
+    let san: Vec<String> = board
+        .legal_moves(piece.color)
+        .iter()
+        .filter(|mv| mv.from == square)
+        .map(|mv| mv.san(board))
+        .collect();
+    if san.is_empty() {
+        return format!("{piece} {square} has no legal moves");
+    }
+    format!("{piece} {square}: {}", san.join(", "))
 }
 
 impl<P: Player> Player for ManualStep<P> {
-    fn make_move(&self, board: &Board) -> Move {
-        let mut input = String::new();
-        let _ = io::stdin().read_line(&mut input);
-        self.player.make_move(board)
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        if self.pause() {
+            println!("{STEP_HELP}");
+            loop {
+                let mut input = String::new();
+                // go on by itself once stdin is closed
+                if matches!(io::stdin().read_line(&mut input), Ok(0) | Err(_)) {
+                    break;
+                }
+                match parse_step_command(&input) {
+                    Some(command) => {
+                        if self.run_command(command, request.board) {
+                            break;
+                        }
+                    }
+                    None => println!("{STEP_HELP}"),
+                }
+            }
+        }
+        self.player.make_move(request)
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player.last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.move_times()
     }
 }
 
 impl<P: Player> ManualStep<P> {
+    pub fn wrap(player: P) -> Self {
+        Self { player, skip: 0 }
+    }
+
+    // Whether to wait before this move, counting down the moves to skip
+    fn pause(&mut self) -> bool {
+        let skip = self.skip;
+        self.skip = skip.saturating_sub(1);
+        skip == 0
+    }
+
+    // Carries out a command, returning whether to go on with the move
+    fn run_command(&mut self, command: StepCommand, board: &Board) -> bool {
+        match command {
+            StepCommand::Step => return true,
+            StepCommand::Skip(moves) => {
+                // this move counts as the first one skipped
+                self.skip = moves.saturating_sub(1);
+                return true;
+            }
+            StepCommand::Fen => println!("{}", board.get_fen()),
+            StepCommand::Eval => match EnginePlayer::analyse(board, STEP_EVAL_TIME) {
+                Ok((eval, line)) => println!("Eval: {eval} ({})", board.line_san(&line)),
+                Err(err) => println!("{err}"),
+            },
+            StepCommand::Moves(square) => println!("{}", square_moves(board, square)),
+        }
+        false
+    }
+}
+
+// ---
+
+// Prints every move of the wrapped player in SAN and, for players that share it,
+// the evaluation along with the line they expect
+pub struct AnalysisWrapper<P: Player> {
+    player: P,
+}
+
+impl<P: Player> Player for AnalysisWrapper<P> {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let action = self.player.make_move(request);
+        if let PlayerAction::Move(mv) = action {
+            let analysis = describe_move(
+                request.board,
+                &mv,
+                self.player.last_eval(),
+                self.player.last_line(),
+            );
+            println!("{analysis}");
+        }
+        action
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player.last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        self.player.move_times()
+    }
+}
+
+impl<P: Player> AnalysisWrapper<P> {
     pub fn wrap(player: P) -> Self {
         Self { player }
     }
 }
+
+// E.g. "Qh2, eval W3 (Qh2 Kg8 Qxg8)"
+fn describe_move(
+    board: &Board,
+    mv: &Move,
+    eval: Option<Evaluation>,
+    line: Option<Vec<Move>>,
+) -> String {
+    let san = mv.san(board);
+    match (eval, line) {
+        (Some(eval), Some(line)) => format!("{san}, eval {eval} ({})", board.line_san(&line)),
+        (Some(eval), None) => format!("{san}, eval {eval}"),
+        _ => san,
+    }
+}
+
+// ---
+
+// TimedPlayer measures how long the wrapped player takes for each move, and says
+// so after every move
+pub struct TimedPlayer<P: Player> {
+    player: P,
+    times: MoveTimes,
+}
+
+// How long a player thought about its moves
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MoveTimes {
+    pub moves: usize,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl MoveTimes {
+    fn add(&mut self, time: Duration) {
+        self.min = if self.moves == 0 {
+            time
+        } else {
+            self.min.min(time)
+        };
+        self.max = self.max.max(time);
+        self.total += time;
+        self.moves += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.moves {
+            0 => Duration::ZERO,
+            moves => self.total / moves as u32,
+        }
+    }
+}
+
+impl Display for MoveTimes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1}s for {} moves, {}ms on average, {}ms to {}ms",
+            self.total.as_secs_f64(),
+            self.moves,
+            self.mean().as_millis(),
+            self.min.as_millis(),
+            self.max.as_millis()
+        )
+    }
+}
+
+impl<P: Player> Player for TimedPlayer<P> {
+    fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+        let start = Instant::now();
+        let action = self.player.make_move(request);
+        let time = start.elapsed();
+
+        self.times.add(time);
+        if let PlayerAction::Move(mv) = action {
+            println!(
+                "{} moved {} in {}ms",
+                self.player.name(),
+                mv.san(request.board),
+                time.as_millis()
+            );
+        }
+        action
+    }
+
+    fn name(&self) -> String {
+        self.player.name()
+    }
+
+    fn last_eval(&self) -> Option<Evaluation> {
+        self.player.last_eval()
+    }
+
+    fn last_line(&self) -> Option<Vec<Move>> {
+        self.player.last_line()
+    }
+
+    fn hints_used(&self) -> usize {
+        self.player.hints_used()
+    }
+
+    fn accepts_draw(&mut self, request: &MoveRequest) -> bool {
+        self.player.accepts_draw(request)
+    }
+
+    fn game_over(&mut self, request: &MoveRequest, result: GameResult) {
+        self.player.game_over(request, result)
+    }
+
+    fn start_fen(&self) -> Option<String> {
+        self.player.start_fen()
+    }
+
+    fn move_times(&self) -> Option<MoveTimes> {
+        Some(self.times)
+    }
+}
+
+impl<P: Player> TimedPlayer<P> {
+    pub fn wrap(player: P) -> Self {
+        Self {
+            player,
+            times: MoveTimes::default(),
+        }
+    }
+}
+
+// Output that can still be read after it was handed to a wrapper or game
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        play::Game,
+        players::{EnginePlayer, RandomPlayer, ScriptedPlayer},
+    };
+
+    #[test]
+    fn test_analysis_wrapper_keeps_moves() {
+        let white = AnalysisWrapper::wrap(ScriptedPlayer::new(&["e2e4", "g1f3"]));
+        let black = AnalysisWrapper::wrap(ScriptedPlayer::new(&["e7e5", "b8c6"]));
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_max_plies(4);
+        game.start();
+
+        let moves: Vec<String> = game.record().moves.iter().map(Move::to_string).collect();
+        let expected: Vec<String> = ["e2e4", "e7e5", "g1f3", "b8c6"]
+            .iter()
+            .map(|mv| {
+                Board::new(None)
+                    .unwrap()
+                    .parse_move(mv)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(moves, expected);
+    }
+
+    // Takes a known time for every move
+    struct SlowPlayer(Duration);
+
+    impl Player for SlowPlayer {
+        fn make_move(&mut self, request: &MoveRequest) -> PlayerAction {
+            std::thread::sleep(self.0);
+            request.board.legal_moves(request.color)[0].into()
+        }
+    }
+
+    #[test]
+    fn test_timed_player() {
+        let time = Duration::from_millis(20);
+        let white = TimedPlayer::wrap(SlowPlayer(time));
+        let black = TimedPlayer::wrap(SlowPlayer(2 * time));
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_max_plies(6);
+        game.start();
+
+        let [white, black] = game.record().times;
+        let (white, black) = (white.unwrap(), black.unwrap());
+        assert_eq!(white.moves, 3);
+        assert_eq!(black.moves, 3);
+        let slack = Duration::from_millis(200);
+        assert!(white.min >= time && white.max < time + slack, "{white:?}");
+        assert!(
+            black.min >= 2 * time && black.max < 2 * time + slack,
+            "{black:?}"
+        );
+        assert!(white.min <= white.mean() && white.mean() <= white.max);
+    }
+
+    #[test]
+    fn test_move_times() {
+        let mut times = MoveTimes::default();
+        assert_eq!(times.mean(), Duration::ZERO);
+        for ms in [300, 100, 200] {
+            times.add(Duration::from_millis(ms));
+        }
+        assert_eq!(times.min, Duration::from_millis(100));
+        assert_eq!(times.max, Duration::from_millis(300));
+        assert_eq!(times.mean(), Duration::from_millis(200));
+        assert_eq!(
+            times.to_string(),
+            "0.6s for 3 moves, 200ms on average, 100ms to 300ms"
+        );
+    }
+
+    #[test]
+    fn test_parse_step_command() {
+        assert_eq!(parse_step_command("\n"), Some(StepCommand::Step));
+        assert_eq!(parse_step_command("fen\n"), Some(StepCommand::Fen));
+        assert_eq!(parse_step_command(" Eval "), Some(StepCommand::Eval));
+        assert_eq!(
+            parse_step_command("moves e2"),
+            Some(StepCommand::Moves(b"e2".into()))
+        );
+        assert_eq!(parse_step_command("skip 10"), Some(StepCommand::Skip(10)));
+        assert_eq!(parse_step_command("moves"), None);
+        assert_eq!(parse_step_command("moves e9"), None);
+        assert_eq!(parse_step_command("skip -1"), None);
+        assert_eq!(parse_step_command("e2e4"), None);
+    }
+
+    #[test]
+    fn test_manual_step_skip() {
+        let board = Board::new(None).unwrap();
+        let mut step = ManualStep::wrap(RandomPlayer::new());
+        assert!(step.pause());
+        // skipping makes this move and the next two without waiting
+        assert!(step.run_command(StepCommand::Skip(3), &board));
+        assert!(!step.pause());
+        assert!(!step.pause());
+        assert!(step.pause());
+        assert!(step.pause());
+
+        assert!(!step.run_command(StepCommand::Fen, &board));
+        assert!(step.run_command(StepCommand::Step, &board));
+        assert!(step.pause());
+    }
+
+    #[test]
+    fn test_square_moves() {
+        let board = Board::new(None).unwrap();
+        assert_eq!(square_moves(&board, b"g1".into()), "♘ g1: Nh3, Nf3");
+        assert_eq!(square_moves(&board, b"g8".into()), "♞ g8: Nh6, Nf6");
+        assert_eq!(
+            square_moves(&board, b"d1".into()),
+            "♕ d1 has no legal moves"
+        );
+        assert_eq!(square_moves(&board, b"e4".into()), "No piece on e4");
+    }
+
+    #[test]
+    fn test_print_board() {
+        let start = Board::new(None).unwrap();
+        let moves: Vec<Move> = ["e2e4", "d7d5", "e4d5"]
+            .iter()
+            .scan(start, |board, mv| {
+                let mv = board.parse_move(mv).unwrap();
+                *board = board.apply(&mv);
+                Some(mv)
+            })
+            .collect();
+        let board = start.apply_moves(&moves);
+        let request = MoveRequest::new(&board)
+            .with_color(Color::Black)
+            .with_history(&start, &moves);
+
+        // the plain board is unchanged
+        let plain = PrintBoard::wrap(RandomPlayer::new()).render(&request);
+        assert_eq!(plain, format!("{board}\n{}\n\n", board.get_fen()));
+
+        let options = BoardOptions {
+            flip: true,
+            material: true,
+            status: true,
+            ..BoardOptions::default()
+        };
+        let expected = [
+            "Black to move after 2. exd5",
+            "  h g f e d c b a",
+            "1 ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖",
+            "2 ♙ ♙ ♙   ♙ ♙ ♙ ♙",
+            "3                ",
+            "4      >         ",
+            "5        >♙      ",
+            "6                ",
+            "7 ♟ ♟ ♟ ♟   ♟ ♟ ♟",
+            "8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜",
+            "White took ♟, black took nothing, white is up 1",
+            "",
+            &board.get_fen(),
+            "",
+            "",
+        ];
+        let flipped = PrintBoard::with_options(RandomPlayer::new(), options).render(&request);
+        assert_eq!(flipped, expected.join("\n"));
+
+        let options = BoardOptions {
+            clear_screen: true,
+            ..BoardOptions::default()
+        };
+        let cleared = PrintBoard::with_options(RandomPlayer::new(), options).render(&request);
+        assert_eq!(cleared, format!("{CLEAR_SCREEN}{plain}"));
+    }
+
+    #[test]
+    fn test_print_board_output() {
+        let board = Board::new(Some("4k3/8/8/8/8/8/8/R3K3 w - - 0 1".into())).unwrap();
+        let out = SharedOutput::default();
+        let mut player = PrintBoard::wrap(ScriptedPlayer::new(&["a1a8"])).with_writer(out.clone());
+        player.make_move(&MoveRequest::new(&board));
+
+        let expected = [
+            "  a b c d e f g h",
+            "8         ♚      ",
+            "7                ",
+            "6                ",
+            "5                ",
+            "4                ",
+            "3                ",
+            "2                ",
+            "1 ♖       ♔      ",
+            "",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "",
+            "",
+        ];
+        assert_eq!(out.contents(), expected.join("\n"));
+    }
+
+    #[test]
+    fn test_print_board_clear() {
+        let board = Board::new(None).unwrap();
+        let printed = |clear: bool| {
+            let out = SharedOutput::default();
+            let mut player = PrintBoard::wrap_with(ScriptedPlayer::new(&["e2e4"]), clear)
+                .with_writer(out.clone());
+            player.make_move(&MoveRequest::new(&board));
+            out.contents()
+        };
+
+        let plain = printed(false);
+        assert!(!plain.contains(CLEAR_SCREEN));
+        assert_eq!(printed(true), format!("{CLEAR_SCREEN}{plain}"));
+    }
+
+    #[test]
+    fn test_print_moves_output() {
+        let out = SharedOutput::default();
+        let white = PrintMoves::wrap(ScriptedPlayer::new(&["e2e4"])).with_writer(out.clone());
+        let black = ScriptedPlayer::new(&["c7c5"]);
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_max_plies(2);
+        game.start();
+        assert_eq!(out.contents(), "1. e4\n1... c5\nDraw by move limit\n");
+    }
+
+    #[test]
+    fn test_print_moves_to_file() {
+        let path = std::env::temp_dir().join(format!("chess-moves-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // only white is wrapped, black's moves are still logged
+        let white = ScriptedPlayer::new(&["e2e4", "g1f3"]);
+        let white = PrintMoves::to_file(white, &path).unwrap();
+        let black = ScriptedPlayer::new(&["e7e5", "b8c6"]);
+        let mut game = Game::new(None, white, black).unwrap();
+        game.set_max_plies(4);
+        game.start();
+        drop(game);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            log,
+            "1. e4\n1... e5\n2. Nf3\n2... Nc6\nDraw by move limit\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_move() {
+        let board = Board::new(Some("7k/8/8/8/8/2K3Q1/5Q2/8 w - - 0 1".into())).unwrap();
+        let mv = board.parse_san("Qfh2").unwrap();
+        assert_eq!(describe_move(&board, &mv, None, None), "Qfh2+");
+        assert_eq!(
+            describe_move(&board, &mv, Some(Evaluation::Win(3)), None),
+            "Qfh2+, eval W3"
+        );
+
+        let mut engine = EnginePlayer::new();
+        let PlayerAction::Move(mv) = engine.make_move(&MoveRequest::new(&board)) else {
+            panic!("the engine should move");
+        };
+        let san = mv.san(&board);
+        let analysis = describe_move(&board, &mv, engine.last_eval(), engine.last_line());
+        assert!(analysis.starts_with(&format!("{san}, eval W3 ({san} ")));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{fmt::Display, path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    observer::JsonlObserver,
+    pgn::{append_pgn, GameInfo},
+    pieces::Color,
+    play::{play_pair, Game, TimeoutPolicy},
+    players::make_player,
+};
+
+// The outcome of a round-robin, scores[i][j] being the points player i scored against player j
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    pub scores: Vec<Vec<f32>>,
+    pub outcomes: Vec<[usize; 3]>, // wins, draws and losses per player
+}
+
+impl Crosstable {
+    pub fn total(&self, player: usize) -> f32 {
+        self.scores[player].iter().sum()
+    }
+
+    // Player indices, best first
+    pub fn standings(&self) -> Vec<usize> {
+        let mut players: Vec<usize> = (0..self.names.len()).collect();
+        players.sort_by(|a, b| self.total(*b).total_cmp(&self.total(*a)));
+        players
+    }
+}
+
+impl Display for Crosstable {
+    // Rows and columns both in order of the standings
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.names.iter().map(|name| name.len()).max().unwrap_or(0);
+        let standings = self.standings();
+
+        write!(f, "    {:<width$}", "")?;
+        for rank in 1..=standings.len() {
+            write!(f, " {:>5}", rank)?;
+        }
+        writeln!(f, " | {:>5} {:>3} {:>3} {:>3}", "total", "+", "=", "-")?;
+
+        for (rank, player) in standings.iter().enumerate() {
+            write!(f, "{:>2}. {:<width$}", rank + 1, self.names[*player])?;
+            for opponent in standings.iter() {
+                if opponent == player {
+                    write!(f, " {:>5}", "-")?;
+                } else {
+                    write!(f, " {:>5.1}", self.scores[*player][*opponent])?;
+                }
+            }
+            let [wins, draws, losses] = self.outcomes[*player];
+            writeln!(
+                f,
+                " | {:>5.1} {wins:>3} {draws:>3} {losses:>3}",
+                self.total(*player)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Every player meets every other player the same number of times, alternating colors
+pub struct Tournament {
+    specs: Vec<String>,
+    games_per_pair: usize,
+    max_plies: Option<usize>,
+    adjudication: Option<(i32, usize)>, // see Game::set_adjudication
+    draw_adjudication: Option<(i32, usize)>, // see Game::set_draw_adjudication
+    move_timeout: Option<(Duration, TimeoutPolicy)>,
+    seed: Option<u64>,
+    log: Option<PathBuf>, // JSON lines for every move of every game
+    pgn: Option<PathBuf>,
+}
+
+impl Tournament {
+    // Takes player specs as understood by make_player
+    pub fn new(specs: Vec<String>) -> anyhow::Result<Self> {
+        if specs.len() < 2 {
+            bail!("a tournament needs at least two players");
+        }
+        for spec in specs.iter() {
+            make_player(spec)?;
+        }
+        Ok(Tournament {
+            specs,
+            games_per_pair: 2,
+            max_plies: None,
+            adjudication: None,
+            draw_adjudication: None,
+            move_timeout: None,
+            seed: None,
+            log: None,
+            pgn: None,
+        })
+    }
+
+    pub fn with_games_per_pair(self, games_per_pair: usize) -> Self {
+        Tournament {
+            games_per_pair,
+            ..self
+        }
+    }
+
+    // Each game is declared a draw after this many plies
+    pub fn with_max_plies(self, max_plies: usize) -> Self {
+        Tournament {
+            max_plies: Some(max_plies),
+            ..self
+        }
+    }
+
+    // Ends games as a win once the evaluations agree one side is ahead, see
+    // Game::set_adjudication
+    pub fn with_adjudication(self, threshold: i32, plies: usize) -> Self {
+        Tournament {
+            adjudication: Some((threshold, plies)),
+            ..self
+        }
+    }
+
+    // Ends games as a draw once the evaluations stay even late in the game, see
+    // Game::set_draw_adjudication
+    pub fn with_draw_adjudication(self, margin: i32, plies: usize) -> Self {
+        Tournament {
+            draw_adjudication: Some((margin, plies)),
+            ..self
+        }
+    }
+
+    // Limits how long the players may think about a move, see Game::set_move_timeout
+    pub fn with_move_timeout(self, timeout: Duration, policy: TimeoutPolicy) -> Self {
+        Tournament {
+            move_timeout: Some((timeout, policy)),
+            ..self
+        }
+    }
+
+    // Each game gets its own seed derived from this one
+    pub fn with_seed(self, seed: u64) -> Self {
+        Tournament {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    // Appends the games to a JSON lines file as they are played
+    pub fn with_log(self, path: impl Into<PathBuf>) -> Self {
+        Tournament {
+            log: Some(path.into()),
+            ..self
+        }
+    }
+
+    // Appends every finished game to a PGN file
+    pub fn with_pgn(self, path: impl Into<PathBuf>) -> Self {
+        Tournament {
+            pgn: Some(path.into()),
+            ..self
+        }
+    }
+
+    pub fn run(&self) -> anyhow::Result<Crosstable> {
+        let players = self.specs.len();
+        let mut scores = vec![vec![0.0; players]; players];
+        let mut outcomes = vec![[0; 3]; players];
+
+        let mut round = 0;
+        let mut pair = 0;
+        for a in 0..players {
+            for b in a + 1..players {
+                // two games at a time, the second with colors swapped
+                for i in (0..self.games_per_pair).step_by(2) {
+                    pair += 1;
+                    let mut game = self.new_game(a, b, pair)?;
+                    let records = if i + 1 < self.games_per_pair {
+                        let (first, second) = play_pair(game);
+                        vec![first, second]
+                    } else {
+                        game.start();
+                        vec![game.record()]
+                    };
+
+                    for (mut record, (white, black)) in records.into_iter().zip([(a, b), (b, a)]) {
+                        round += 1;
+                        record.info = record.info.with_round(round.to_string());
+                        let result = record.result.context("the game did not finish")?;
+                        let score = result.score(Color::White);
+                        scores[white][black] += score;
+                        scores[black][white] += 1.0 - score;
+                        if result.is_draw() {
+                            outcomes[white][1] += 1;
+                            outcomes[black][1] += 1;
+                        } else {
+                            let (winner, loser) = match result.winner() {
+                                Some(Color::White) => (white, black),
+                                _ => (black, white),
+                            };
+                            outcomes[winner][0] += 1;
+                            outcomes[loser][2] += 1;
+                        }
+
+                        if let Some(path) = &self.pgn {
+                            append_pgn(path, &record.to_pgn())
+                                .with_context(|| format!("could not write {}", path.display()))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Crosstable {
+            names: self.specs.clone(),
+            scores,
+            outcomes,
+        })
+    }
+
+    // A game of the given pair with player a as white, set up as configured
+    fn new_game(&self, a: usize, b: usize, pair: usize) -> anyhow::Result<Game> {
+        let mut game = Game::new_boxed(
+            None,
+            make_player(&self.specs[a])?,
+            make_player(&self.specs[b])?,
+        )?;
+        if let Some(max_plies) = self.max_plies {
+            game.set_max_plies(max_plies);
+        }
+        if let Some((threshold, plies)) = self.adjudication {
+            game.set_adjudication(threshold, plies);
+        }
+        if let Some((margin, plies)) = self.draw_adjudication {
+            game.set_draw_adjudication(margin, plies);
+        }
+        if let Some((timeout, policy)) = self.move_timeout {
+            game.set_move_timeout(timeout, policy);
+        }
+        if let Some(seed) = self.seed {
+            game.set_seed(seed.wrapping_add(pair as u64));
+        }
+        if let Some(path) = &self.log {
+            let log = JsonlObserver::create(path)
+                .with_context(|| format!("could not open {}", path.display()))?;
+            game.add_observer(Box::new(log));
+        }
+        game.set_info(
+            GameInfo::default()
+                .with_event("Round robin")
+                .with_white(&self.specs[a])
+                .with_black(&self.specs[b]),
+        );
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin() {
+        let specs = vec!["random".into(), "random".into(), "engine:depth=1".into()];
+        let tournament = Tournament::new(specs)
+            .unwrap()
+            .with_games_per_pair(1)
+            .with_max_plies(10);
+        let table = tournament.run().unwrap();
+
+        assert_eq!(table.names.len(), 3);
+        assert_eq!(table.scores.len(), 3);
+        assert!(table.scores.iter().all(|row| row.len() == 3));
+        for player in 0..3 {
+            assert_eq!(table.scores[player][player], 0.0);
+        }
+        // one point per game, three pairings
+        let total: f32 = (0..3).map(|player| table.total(player)).sum();
+        assert_eq!(total, 3.0);
+
+        let standings = table.standings();
+        assert!(table.total(standings[0]) >= table.total(standings[2]));
+        // every player played two games
+        for [wins, draws, losses] in table.outcomes.iter() {
+            assert_eq!(wins + draws + losses, 2);
+        }
+    }
+
+    #[test]
+    fn test_two_players() {
+        let specs = vec![
+            "engine:depth=1".into(),
+            "engine:depth=1,ordering=generated".into(),
+        ];
+        let tournament = Tournament::new(specs)
+            .unwrap()
+            .with_games_per_pair(2)
+            .with_max_plies(12);
+        let table = tournament.run().unwrap();
+
+        // what one gets, the other does not
+        assert_eq!(table.scores[0][1] + table.scores[1][0], 2.0);
+        assert_eq!(table.total(0) + table.total(1), 2.0);
+        assert_eq!(table.standings().len(), 2);
+    }
+
+    #[test]
+    fn test_log() {
+        let path =
+            std::env::temp_dir().join(format!("chess-round-robin-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let specs = vec!["random".into(), "random".into()];
+        let tournament = Tournament::new(specs)
+            .unwrap()
+            .with_max_plies(4)
+            .with_log(&path);
+        tournament.run().unwrap();
+
+        // two games of four moves, each followed by the result
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(log.lines().count(), 2 * (4 + 1));
+        assert_eq!(
+            log.lines()
+                .filter(|line| line.starts_with("{\"result\""))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_pgn() {
+        let path =
+            std::env::temp_dir().join(format!("chess-round-robin-{}.pgn", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let specs = vec!["random".into(), "random".into()];
+        let tournament = Tournament::new(specs)
+            .unwrap()
+            .with_max_plies(4)
+            .with_pgn(&path);
+        tournament.run().unwrap();
+
+        let pgn = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let rounds: Vec<&str> = pgn
+            .lines()
+            .filter(|line| line.starts_with("[Round "))
+            .collect();
+        assert_eq!(rounds, ["[Round \"1\"]", "[Round \"2\"]"]);
+        // a blank line after each game
+        assert_eq!(pgn.matches("\n\n[Event ").count(), 1);
+        assert!(pgn.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        assert!(Tournament::new(vec!["random".into()]).is_err());
+        assert!(Tournament::new(vec!["random".into(), "nobody".into()]).is_err());
+    }
+}
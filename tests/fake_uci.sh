@@ -0,0 +1,13 @@
+#!/bin/sh
+# A stand-in for a UCI engine in the tests, which always plays e2e4
+while read -r line; do
+    case "$line" in
+        uci)
+            echo "id name FakeEngine"
+            echo "uciok"
+            ;;
+        isready) echo "readyok" ;;
+        go*) echo "bestmove e2e4" ;;
+        quit) exit 0 ;;
+    esac
+done
@@ -0,0 +1,61 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// Pipes the given input into a game of the terminal player against a random one
+fn play_terminal(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chess"))
+        .args([
+            "play", "--white", "terminal", "--black", "random", "--seed", "1",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // stdin is closed when dropped, so the input ends here
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_terminal_player_resigns_at_end_of_input() {
+    let output = play_terminal("e2e4\n");
+    assert!(output.contains("No more input, resigning."));
+    assert!(output.contains("Game over: Black won by resignation after 2 moves"));
+}
+
+#[test]
+fn test_terminal_player_gives_up_on_junk() {
+    let output = play_terminal(&"é♞\n".repeat(100));
+    assert!(output.contains("Type help to see what you can enter."));
+    assert!(output.contains("Too many invalid moves, resigning."));
+    assert!(output.contains("Game over: Black won by resignation after 1 moves"));
+}
+
+#[test]
+fn test_uci_engine() {
+    let engine = format!("uci:path={}/tests/fake_uci.sh", env!("CARGO_MANIFEST_DIR"));
+    let output = Command::new(env!("CARGO_BIN_EXE_chess"))
+        .args([
+            "play",
+            "--white",
+            &engine,
+            "--black",
+            "random",
+            "--max-plies",
+            "1",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains("Game over: Draw by move limit"));
+}